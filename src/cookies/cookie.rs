@@ -0,0 +1,409 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use http;
+use http::header::{self, HeaderValue};
+use httpdate;
+
+/// The value of a cookie's `SameSite` attribute.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SameSite {
+    /// The cookie is only sent with same-site requests.
+    Strict,
+    /// The cookie is sent with same-site requests, and with top-level navigations from other sites.
+    Lax,
+    /// The cookie is sent with all requests. Browsers require such cookies to also be `Secure`.
+    None,
+}
+
+impl SameSite {
+    /// Returns the value of the attribute, as it appears in a `Set-Cookie` header.
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        }
+    }
+}
+
+/// A HTTP cookie.
+///
+/// A `Cookie` is created with a name and value, and its attributes are set using the builder
+/// methods. Its `Display` implementation formats it as the value of a `Set-Cookie` header.
+///
+/// Names and values are not encoded, and must only contain the characters allowed by [RFC 6265]:
+/// a value cannot contain whitespace, control characters, `"`, `,`, `;` or `\`, and the `Path` and
+/// `Domain` attributes cannot contain control characters or `;`. The `Display` implementation does
+/// not check this, so that cookies can always be printed (e.g. in logs). Cookies are checked by
+/// [`to_header_value`], which is used whenever a cookie is sent, so that a value taken from user
+/// input can't add attributes to the `Set-Cookie` header. Use [`validate`] to check a cookie up
+/// front.
+///
+/// [RFC 6265]: https://tools.ietf.org/html/rfc6265#section-4.1.1
+/// [`validate`]: #method.validate
+/// [`to_header_value`]: #method.to_header_value
+///
+/// # Example
+///
+/// ```
+/// # extern crate aitch;
+/// #
+/// use std::time::Duration;
+///
+/// use aitch::cookies::{Cookie, SameSite};
+///
+/// # fn main() {
+/// let cookie = Cookie::new("theme", "dark")
+///     .path("/")
+///     .max_age(Duration::from_secs(3600))
+///     .secure(true)
+///     .http_only(true)
+///     .same_site(SameSite::Strict);
+///
+/// assert_eq!(
+///     cookie.to_string(),
+///     "theme=dark; Path=/; Max-Age=3600; Secure; HttpOnly; SameSite=Strict"
+/// );
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    expires: Option<SystemTime>,
+    max_age: Option<Duration>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+}
+
+impl Cookie {
+    /// Creates a cookie with the given name and value, and no attributes.
+    pub fn new<N, V>(name: N, value: V) -> Cookie
+    where
+        N: Into<String>,
+        V: Into<String>,
+    {
+        Cookie {
+            name: name.into(),
+            value: value.into(),
+            path: None,
+            domain: None,
+            expires: None,
+            max_age: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+        }
+    }
+
+    /// Creates a cookie with the given name and an empty value.
+    ///
+    /// This is useful for identifying a cookie to remove from a [`CookieJar`].
+    ///
+    /// [`CookieJar`]: struct.CookieJar.html
+    pub fn named<N: Into<String>>(name: N) -> Cookie {
+        Cookie::new(name, "")
+    }
+
+    /// Returns the name of the cookie.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the value of the cookie.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Sets the `Path` attribute.
+    pub fn path<S: Into<String>>(mut self, path: S) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Sets the `Domain` attribute.
+    pub fn domain<S: Into<String>>(mut self, domain: S) -> Self {
+        self.domain = Some(domain.into());
+        self
+    }
+
+    /// Sets the `Expires` attribute.
+    pub fn expires(mut self, expires: SystemTime) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    /// Sets the `Max-Age` attribute.
+    ///
+    /// Browsers prefer `Max-Age` to `Expires` when both are set.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Sets whether the `Secure` attribute is included.
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    /// Sets whether the `HttpOnly` attribute is included.
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    /// Sets the `SameSite` attribute.
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    /// Sets the value of the cookie, keeping its attributes.
+    pub fn set_value<V: Into<String>>(&mut self, value: V) {
+        self.value = value.into();
+    }
+
+    /// Checks that the cookie's name, value and attributes only contain the characters allowed by
+    /// [RFC 6265].
+    ///
+    /// [RFC 6265]: https://tools.ietf.org/html/rfc6265#section-4.1.1
+    pub fn validate(&self) -> Result<(), InvalidCookie> {
+        let invalid = |part| {
+            Err(InvalidCookie {
+                name: self.name.clone(),
+                part,
+            })
+        };
+        if self.name.is_empty() || !self.name.bytes().all(is_token) {
+            return invalid("name");
+        }
+        let value = &self.value;
+        let quoted = value.len() >= 2 && value.starts_with('"') && value.ends_with('"');
+        let value = if quoted {
+            &value[1..value.len() - 1]
+        } else {
+            value
+        };
+        if !value.bytes().all(is_cookie_octet) {
+            return invalid("value");
+        }
+        if let Some(ref path) = self.path {
+            if !is_attribute_value(path) {
+                return invalid("path");
+            }
+        }
+        if let Some(ref domain) = self.domain {
+            if !is_attribute_value(domain) {
+                return invalid("domain");
+            }
+        }
+        Ok(())
+    }
+
+    /// Formats the cookie as the value of a `Set-Cookie` header, after checking that it is valid.
+    pub fn to_header_value(&self) -> Result<HeaderValue, InvalidCookie> {
+        self.validate()?;
+        HeaderValue::from_str(&self.to_string()).map_err(|_| InvalidCookie {
+            name: self.name.clone(),
+            part: "attribute",
+        })
+    }
+
+    /// Returns a cookie which, when sent in a `Set-Cookie` header, removes this cookie from the
+    /// browser.
+    ///
+    /// The returned cookie has an empty value, and has already expired. Its `Path` and `Domain`
+    /// match this cookie's, as browsers only remove cookies when these match.
+    pub fn into_removal(self) -> Cookie {
+        Cookie {
+            value: String::new(),
+            expires: Some(UNIX_EPOCH),
+            max_age: Some(Duration::from_secs(0)),
+            ..self
+        }
+    }
+}
+
+// A `token` character (RFC 7230), as used in cookie names.
+fn is_token(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+// A `cookie-octet` (RFC 6265): visible ASCII, excluding `"`, `,`, `;` and `\`.
+fn is_cookie_octet(b: u8) -> bool {
+    b > 0x20 && b < 0x7f && !b"\",;\\".contains(&b)
+}
+
+// An attribute value (RFC 6265): any character except control characters and `;`.
+fn is_attribute_value(value: &str) -> bool {
+    value.bytes().all(|b| b >= 0x20 && b != 0x7f && b != b';')
+}
+
+impl fmt::Display for Cookie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if let Some(ref path) = self.path {
+            write!(f, "; Path={}", path)?;
+        }
+        if let Some(ref domain) = self.domain {
+            write!(f, "; Domain={}", domain)?;
+        }
+        if let Some(expires) = self.expires {
+            write!(f, "; Expires={}", httpdate::fmt_http_date(expires))?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        if self.secure {
+            f.write_str("; Secure")?;
+        }
+        if self.http_only {
+            f.write_str("; HttpOnly")?;
+        }
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={}", same_site.as_str())?;
+        }
+        Ok(())
+    }
+}
+
+/// An error returned when a cookie cannot be sent in a `Set-Cookie` header, because its name, value
+/// or one of its attributes contains characters which are not allowed.
+///
+/// See [`Cookie::validate`].
+///
+/// [`Cookie::validate`]: struct.Cookie.html#method.validate
+#[derive(Debug)]
+pub struct InvalidCookie {
+    name: String,
+    part: &'static str,
+}
+
+impl InvalidCookie {
+    /// Returns the name of the invalid cookie.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl fmt::Display for InvalidCookie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "cookie {:?} has an invalid {}", self.name, self.part)
+    }
+}
+
+impl StdError for InvalidCookie {
+    fn description(&self) -> &str {
+        "invalid cookie"
+    }
+}
+
+/// An extension trait, which allows cookies to be set using a [`ResponseBuilder`].
+///
+/// [`ResponseBuilder`]: ../type.ResponseBuilder.html
+///
+/// # Example
+///
+/// ```
+/// # extern crate aitch;
+/// # extern crate http;
+/// #
+/// use aitch::cookies::{Cookie, ResponseBuilderExt};
+/// use aitch::{Responder, ResponseBuilder};
+/// use http::Request;
+///
+/// fn handler(_req: Request<()>, mut resp: ResponseBuilder) -> impl Responder {
+///     resp.cookie(&Cookie::new("theme", "dark").path("/"))
+///         .body("Hello, world!".to_owned())
+/// }
+/// # fn main() {}
+/// ```
+pub trait ResponseBuilderExt {
+    /// Appends a `Set-Cookie` header for the cookie.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cookie is invalid. Cookies whose value is taken from user input should be
+    /// checked using [`Cookie::validate`] first.
+    ///
+    /// [`Cookie::validate`]: struct.Cookie.html#method.validate
+    fn cookie(&mut self, cookie: &Cookie) -> &mut Self;
+}
+
+impl ResponseBuilderExt for http::response::Builder {
+    fn cookie(&mut self, cookie: &Cookie) -> &mut Self {
+        match cookie.to_header_value() {
+            Ok(value) => self.header(header::SET_COOKIE, value),
+            Err(err) => panic!("{}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{Cookie, SameSite};
+
+    #[test]
+    fn display() {
+        assert_eq!(Cookie::new("a", "b").to_string(), "a=b");
+
+        let cookie = Cookie::new("id", "123")
+            .path("/app")
+            .domain("example.com")
+            .expires(UNIX_EPOCH + Duration::from_secs(1_000_000_000))
+            .max_age(Duration::from_secs(60))
+            .secure(true)
+            .http_only(true)
+            .same_site(SameSite::None);
+        assert_eq!(
+            cookie.to_string(),
+            "id=123; Path=/app; Domain=example.com; Expires=Sun, 09 Sep 2001 01:46:40 GMT; \
+             Max-Age=60; Secure; HttpOnly; SameSite=None"
+        );
+    }
+
+    #[test]
+    fn validate() {
+        assert!(Cookie::new("a", "b").validate().is_ok());
+        assert!(Cookie::new("a", "\"quoted\"").validate().is_ok());
+        assert!(Cookie::new("token", "YWJj+/=").validate().is_ok());
+        assert!(Cookie::new("a", "").validate().is_ok());
+
+        let cookie = Cookie::new("a", "x; Domain=evil.com; Max-Age=99999");
+        let err = cookie.validate().unwrap_err();
+        assert_eq!(err.to_string(), "cookie \"a\" has an invalid value");
+        assert!(cookie.to_header_value().is_err());
+        // Invalid cookies can still be printed.
+        assert_eq!(cookie.to_string(), "a=x; Domain=evil.com; Max-Age=99999");
+
+        for value in &["a b", "a,b", "a\"b", "a\\b", "a\tb", "a\u{7f}b", "é"] {
+            assert!(Cookie::new("a", *value).validate().is_err(), "{:?}", value);
+        }
+        for name in &["", "a=b", "a b", "a;b", "(a)"] {
+            assert!(Cookie::new(*name, "b").validate().is_err(), "{:?}", name);
+        }
+        assert!(Cookie::new("a", "b").path("/; Secure").validate().is_err());
+        assert!(Cookie::new("a", "b").domain("x\r\ny").validate().is_err());
+        assert!(Cookie::new("a", "b").path("/a b").validate().is_ok());
+    }
+
+    #[test]
+    fn removal() {
+        let cookie = Cookie::new("id", "123")
+            .path("/app")
+            .http_only(true)
+            .into_removal();
+        assert_eq!(
+            cookie.to_string(),
+            "id=; Path=/app; Expires=Thu, 01 Jan 1970 00:00:00 GMT; Max-Age=0; HttpOnly"
+        );
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use http::header::{self, HeaderMap};
+
+use super::Cookie;
+
+#[derive(Debug, Default)]
+struct State {
+    // The cookies sent by the client.
+    original: HashMap<String, Cookie>,
+    // Cookies which have been added or removed since the jar was created, which must be sent to
+    // the client. Removed cookies are stored as removal cookies (see `Cookie::into_removal`).
+    delta: HashMap<String, Cookie>,
+    // The names of cookies which have been removed.
+    removed: Vec<String>,
+}
+
+/// A collection of cookies, which tracks the changes made to it.
+///
+/// A `CookieJar` is created from the cookies sent by the client in a request's `Cookie` header.
+/// Cookies can then be added to or removed from the jar, and the jar keeps track of which cookies
+/// have changed, so that only these need to be sent in `Set-Cookie` headers.
+///
+/// The [`with_cookies`] middleware stores a `CookieJar` in the extensions of each request, and
+/// sends the changes once the handler has responded. A `CookieJar` can be cloned, and all clones
+/// share the same cookies, which allows the jar to be modified after the request has been consumed
+/// (e.g. by an asynchronous handler).
+///
+/// [`with_cookies`]: ../middlewares/fn.with_cookies.html
+#[derive(Clone, Debug, Default)]
+pub struct CookieJar {
+    state: Arc<Mutex<State>>,
+}
+
+impl CookieJar {
+    /// Creates an empty `CookieJar`.
+    pub fn new() -> CookieJar {
+        CookieJar::default()
+    }
+
+    /// Creates a `CookieJar` containing the cookies in the `Cookie` headers of a request.
+    ///
+    /// Malformed cookies are ignored. If the client sent more than one cookie with the same name,
+    /// the first is used.
+    pub fn from_headers(headers: &HeaderMap) -> CookieJar {
+        let mut original = HashMap::new();
+        let values = headers
+            .get_all(header::COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok());
+        for value in values {
+            for pair in value.split(';') {
+                let mut parts = pair.splitn(2, '=');
+                let name = parts.next().unwrap_or("").trim();
+                let value = match parts.next() {
+                    Some(value) => value.trim(),
+                    None => continue,
+                };
+                if name.is_empty() || original.contains_key(name) {
+                    continue;
+                }
+                let value = value.trim_matches('"');
+                original.insert(name.to_owned(), Cookie::new(name, value));
+            }
+        }
+
+        CookieJar {
+            state: Arc::new(Mutex::new(State {
+                original,
+                ..State::default()
+            })),
+        }
+    }
+
+    /// Returns the cookie with the given name, taking into account any changes made to the jar.
+    pub fn get(&self, name: &str) -> Option<Cookie> {
+        let state = self.state.lock().unwrap();
+        if state.removed.iter().any(|removed| removed == name) {
+            return None;
+        }
+        state
+            .delta
+            .get(name)
+            .or_else(|| state.original.get(name))
+            .cloned()
+    }
+
+    /// Returns all of the cookies in the jar, taking into account any changes made to the jar.
+    pub fn cookies(&self) -> Vec<Cookie> {
+        let state = self.state.lock().unwrap();
+        let mut cookies: Vec<Cookie> = state
+            .original
+            .values()
+            .filter(|cookie| !state.delta.contains_key(cookie.name()))
+            .chain(state.delta.values())
+            .filter(|cookie| !state.removed.iter().any(|removed| removed == cookie.name()))
+            .cloned()
+            .collect();
+        cookies.sort_by(|a, b| a.name().cmp(b.name()));
+        cookies
+    }
+
+    /// Adds a cookie to the jar, replacing any existing cookie with the same name.
+    ///
+    /// The cookie will be sent to the client, unless it is identical to the cookie sent by the
+    /// client (in which case there is no change to send).
+    pub fn add(&self, cookie: Cookie) {
+        let mut state = self.state.lock().unwrap();
+        state.removed.retain(|removed| removed != cookie.name());
+        if state.original.get(cookie.name()) == Some(&cookie) {
+            state.delta.remove(cookie.name());
+        } else {
+            state.delta.insert(cookie.name().to_owned(), cookie);
+        }
+    }
+
+    /// Removes a cookie from the jar.
+    ///
+    /// If the client sent the cookie, a removal cookie (see [`Cookie::into_removal`]) is sent to
+    /// the client. The `Path` and `Domain` of the provided cookie should match those that were used
+    /// when the cookie was set.
+    ///
+    /// [`Cookie::into_removal`]: struct.Cookie.html#method.into_removal
+    pub fn remove(&self, cookie: Cookie) {
+        let mut state = self.state.lock().unwrap();
+        let name = cookie.name().to_owned();
+        state.delta.remove(&name);
+        state.removed.retain(|removed| *removed != name);
+        if state.original.contains_key(&name) {
+            state.delta.insert(name.clone(), cookie.into_removal());
+            state.removed.push(name);
+        }
+    }
+
+    /// Returns the cookies which have changed since the jar was created, and which should be sent
+    /// to the client in `Set-Cookie` headers.
+    pub fn delta(&self) -> Vec<Cookie> {
+        let state = self.state.lock().unwrap();
+        let mut delta: Vec<Cookie> = state.delta.values().cloned().collect();
+        delta.sort_by(|a, b| a.name().cmp(b.name()));
+        delta
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use http::header::{self, HeaderMap, HeaderValue};
+
+    use super::CookieJar;
+    use cookies::Cookie;
+
+    fn jar(cookies: &'static str) -> CookieJar {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, HeaderValue::from_static(cookies));
+        CookieJar::from_headers(&headers)
+    }
+
+    #[test]
+    fn parsing() {
+        let jar = jar("a=1; b=\"two\";c=; =bad; bad; a=ignored; d=x=y");
+        let cookies: Vec<_> = jar
+            .cookies()
+            .into_iter()
+            .map(|cookie| (cookie.name().to_owned(), cookie.value().to_owned()))
+            .collect();
+        assert_eq!(
+            cookies,
+            vec![
+                ("a".to_owned(), "1".to_owned()),
+                ("b".to_owned(), "two".to_owned()),
+                ("c".to_owned(), "".to_owned()),
+                ("d".to_owned(), "x=y".to_owned()),
+            ]
+        );
+        assert!(jar.delta().is_empty());
+    }
+
+    #[test]
+    fn delta() {
+        let jar = jar("a=1; b=2");
+
+        // Re-adding an unchanged cookie is not a change.
+        jar.add(Cookie::new("a", "1"));
+        assert!(jar.delta().is_empty());
+
+        jar.add(Cookie::new("b", "3"));
+        jar.add(Cookie::new("c", "4"));
+        assert_eq!(jar.get("b").unwrap().value(), "3");
+        assert_eq!(
+            jar.delta(),
+            vec![Cookie::new("b", "3"), Cookie::new("c", "4")]
+        );
+
+        // Removing a cookie which the client doesn't have only discards the pending change.
+        jar.remove(Cookie::named("c"));
+        assert!(jar.get("c").is_none());
+        assert_eq!(jar.delta(), vec![Cookie::new("b", "3")]);
+
+        jar.remove(Cookie::named("a").path("/"));
+        assert!(jar.get("a").is_none());
+        assert_eq!(jar.cookies(), vec![Cookie::new("b", "3")]);
+        assert_eq!(
+            jar.delta(),
+            vec![
+                Cookie::named("a").path("/").into_removal(),
+                Cookie::new("b", "3"),
+            ]
+        );
+
+        // Adding a removed cookie makes it available again.
+        jar.add(Cookie::new("a", "5"));
+        assert_eq!(jar.get("a").unwrap().value(), "5");
+    }
+}
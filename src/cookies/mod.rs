@@ -0,0 +1,62 @@
+//! Types for reading cookies from requests, and setting cookies on responses.
+//!
+//! The [`with_cookies`] middleware parses the `Cookie` header of each request into a
+//! [`CookieJar`], which is stored in the request's extensions. Handlers can read cookies from the
+//! jar, and add or remove cookies. Once the handler has responded, the middleware adds a
+//! `Set-Cookie` header to the response for each cookie which was changed.
+//!
+//! Handlers which don't use the middleware can parse cookies using [`CookieJar::from_headers`], and
+//! can set cookies directly on a [`ResponseBuilder`] using [`ResponseBuilderExt::cookie`].
+//!
+//! Cookies which must not be tampered with can be signed, using [`SignedCookies`], or encrypted,
+//! using [`PrivateCookies`]. These require the `secure-cookies` feature, which is enabled by
+//! default. The [`with_cookie_keys`] middleware makes both available to handlers.
+//!
+//! [`SignedCookies`]: struct.SignedCookies.html
+//! [`PrivateCookies`]: struct.PrivateCookies.html
+//! [`with_cookie_keys`]: ../middlewares/fn.with_cookie_keys.html
+//! [`with_cookies`]: ../middlewares/fn.with_cookies.html
+//! [`CookieJar`]: struct.CookieJar.html
+//! [`CookieJar::from_headers`]: struct.CookieJar.html#method.from_headers
+//! [`ResponseBuilder`]: ../type.ResponseBuilder.html
+//! [`ResponseBuilderExt::cookie`]: trait.ResponseBuilderExt.html#tymethod.cookie
+//!
+//! # Example
+//!
+//! ```
+//! extern crate aitch;
+//! extern crate http;
+//!
+//! use aitch::cookies::{Cookie, CookieJar, SameSite};
+//! use aitch::{middlewares, Responder, ResponseBuilder};
+//! use http::Request;
+//!
+//! fn handler(req: Request<()>, mut resp: ResponseBuilder) -> impl Responder {
+//!     let jar = req.extensions().get::<CookieJar>().unwrap();
+//!     let visits = match jar.get("visits") {
+//!         Some(cookie) => cookie.value().parse().unwrap_or(0) + 1,
+//!         None => 1,
+//!     };
+//!     jar.add(
+//!         Cookie::new("visits", visits.to_string())
+//!             .path("/")
+//!             .http_only(true)
+//!             .same_site(SameSite::Lax),
+//!     );
+//!     resp.body(format!("You have visited {} times", visits))
+//! }
+//!
+//! # fn main() {
+//! let wrapped = middlewares::with_cookies(handler);
+//! # }
+//! ```
+
+mod cookie;
+mod jar;
+#[cfg(feature = "secure-cookies")]
+mod secure;
+
+pub use self::cookie::{Cookie, InvalidCookie, ResponseBuilderExt, SameSite};
+pub use self::jar::CookieJar;
+#[cfg(feature = "secure-cookies")]
+pub use self::secure::{CookieKeys, Key, PrivateCookies, SignedCookies};
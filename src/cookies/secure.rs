@@ -0,0 +1,407 @@
+use std::fmt;
+use std::sync::Arc;
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use super::{Cookie, CookieJar};
+use random;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+
+fn hmac(key: &[u8], data: &[u8]) -> HmacSha256 {
+    let mut mac =
+        <HmacSha256 as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac
+}
+
+fn encode(data: &[u8]) -> String {
+    base64::encode_config(data, base64::URL_SAFE_NO_PAD)
+}
+
+fn decode(data: &str) -> Option<Vec<u8>> {
+    base64::decode_config(data, base64::URL_SAFE_NO_PAD).ok()
+}
+
+/// A secret key, used to sign and encrypt cookies.
+///
+/// Separate signing and encryption keys are derived from a master key, so the same `Key` can be
+/// used for both [`SignedCookies`] and [`PrivateCookies`].
+///
+/// [`SignedCookies`]: struct.SignedCookies.html
+/// [`PrivateCookies`]: struct.PrivateCookies.html
+#[derive(Clone)]
+pub struct Key {
+    signing: [u8; KEY_LEN],
+    encryption: [u8; KEY_LEN],
+}
+
+impl Key {
+    /// Derives a key from a master key, which should be at least 32 bytes of random data.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the master key is shorter than 32 bytes.
+    pub fn derive_from(master: &[u8]) -> Key {
+        assert!(
+            master.len() >= KEY_LEN,
+            "Key: master key must be at least {} bytes",
+            KEY_LEN
+        );
+        let mut signing = [0; KEY_LEN];
+        let mut encryption = [0; KEY_LEN];
+        signing.copy_from_slice(
+            &hmac(master, b"aitch cookie signing")
+                .finalize()
+                .into_bytes(),
+        );
+        encryption.copy_from_slice(
+            &hmac(master, b"aitch cookie encryption")
+                .finalize()
+                .into_bytes(),
+        );
+        Key {
+            signing,
+            encryption,
+        }
+    }
+
+    /// Generates a new random key.
+    ///
+    /// Cookies signed or encrypted with a generated key cannot be read once the process exits, so
+    /// most applications should use [`derive_from`] with a master key loaded from configuration.
+    ///
+    /// [`derive_from`]: #method.derive_from
+    pub fn generate() -> Key {
+        Key::derive_from(&random::bytes(64))
+    }
+
+    fn sign(&self, name: &str, value: &str) -> String {
+        let mac = hmac(&self.signing, format!("{}={}", name, value).as_bytes());
+        encode(&mac.finalize().into_bytes())
+    }
+
+    fn verify(&self, name: &str, value: &str, signature: &[u8]) -> bool {
+        let mac = hmac(&self.signing, format!("{}={}", name, value).as_bytes());
+        mac.verify_slice(signature).is_ok()
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new_from_slice(&self.encryption).expect("key has the correct length")
+    }
+
+    fn encrypt(&self, name: &str, value: &str) -> String {
+        let nonce = random::bytes(NONCE_LEN);
+        let payload = Payload {
+            msg: value.as_bytes(),
+            aad: name.as_bytes(),
+        };
+        let ciphertext = self
+            .cipher()
+            .encrypt(Nonce::from_slice(&nonce), payload)
+            .expect("encryption does not fail");
+
+        let mut data = nonce;
+        data.extend_from_slice(&ciphertext);
+        encode(&data)
+    }
+
+    fn decrypt(&self, name: &str, data: &[u8]) -> Option<String> {
+        if data.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        let payload = Payload {
+            msg: ciphertext,
+            aad: name.as_bytes(),
+        };
+        let plaintext = self
+            .cipher()
+            .decrypt(Nonce::from_slice(nonce), payload)
+            .ok()?;
+        String::from_utf8(plaintext).ok()
+    }
+}
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Key { .. }")
+    }
+}
+
+/// The keys used to sign and encrypt cookies, which allows keys to be rotated.
+///
+/// New cookies are always signed or encrypted using the primary key. Cookies are accepted if they
+/// were signed or encrypted using the primary key, or any of the fallback keys. To rotate keys,
+/// make the current primary key a fallback key, and add a new primary key. Once all cookies using
+/// the old key have expired, it can be removed.
+///
+/// A `CookieKeys` can be cheaply cloned.
+///
+/// # Example
+///
+/// ```
+/// # extern crate aitch;
+/// #
+/// use aitch::cookies::{CookieKeys, Key};
+///
+/// # fn main() {
+/// # let new_secret = [1; 32];
+/// # let old_secret = [2; 32];
+/// let keys = CookieKeys::new(Key::derive_from(&new_secret))
+///     .fallback(Key::derive_from(&old_secret));
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct CookieKeys {
+    // The primary key is first, followed by the fallback keys in the order they were added.
+    keys: Arc<Vec<Key>>,
+}
+
+impl CookieKeys {
+    /// Creates a `CookieKeys` with the given primary key.
+    pub fn new(primary: Key) -> Self {
+        CookieKeys {
+            keys: Arc::new(vec![primary]),
+        }
+    }
+
+    /// Adds a fallback key, which is used to verify or decrypt (but not create) cookies.
+    pub fn fallback(mut self, key: Key) -> Self {
+        Arc::make_mut(&mut self.keys).push(key);
+        self
+    }
+
+    fn primary(&self) -> &Key {
+        &self.keys[0]
+    }
+}
+
+/// A view of a [`CookieJar`], which signs the values of cookies so that they cannot be tampered
+/// with.
+///
+/// Signed cookies can be read by the client, but cannot be changed without invalidating the
+/// signature. Each value is signed using HMAC-SHA256, together with the cookie's name (so that
+/// the value of one signed cookie cannot be copied to another).
+///
+/// The [`with_cookie_keys`] middleware stores a `SignedCookies` in the extensions of each request.
+///
+/// [`CookieJar`]: struct.CookieJar.html
+/// [`with_cookie_keys`]: ../middlewares/fn.with_cookie_keys.html
+#[derive(Clone, Debug)]
+pub struct SignedCookies {
+    jar: CookieJar,
+    keys: CookieKeys,
+}
+
+impl SignedCookies {
+    /// Creates a view of the jar, which signs cookies using the given keys.
+    pub fn new(jar: CookieJar, keys: CookieKeys) -> SignedCookies {
+        SignedCookies { jar, keys }
+    }
+
+    /// Returns the cookie with the given name, if its signature is valid.
+    ///
+    /// The value of the returned cookie does not include the signature.
+    pub fn get(&self, name: &str) -> Option<Cookie> {
+        let mut cookie = self.jar.get(name)?;
+        let value = {
+            let mut parts = cookie.value().rsplitn(2, '.');
+            let signature = decode(parts.next()?)?;
+            let value = parts.next()?;
+            let valid = self
+                .keys
+                .keys
+                .iter()
+                .any(|key| key.verify(name, value, &signature));
+            if !valid {
+                return None;
+            }
+            value.to_owned()
+        };
+        cookie.set_value(value);
+        Some(cookie)
+    }
+
+    /// Signs the cookie's value using the primary key, and adds it to the jar.
+    pub fn add(&self, mut cookie: Cookie) {
+        let signature = self.keys.primary().sign(cookie.name(), cookie.value());
+        let value = format!("{}.{}", cookie.value(), signature);
+        cookie.set_value(value);
+        self.jar.add(cookie);
+    }
+
+    /// Removes a cookie from the jar. See [`CookieJar::remove`].
+    ///
+    /// [`CookieJar::remove`]: struct.CookieJar.html#method.remove
+    pub fn remove(&self, cookie: Cookie) {
+        self.jar.remove(cookie);
+    }
+}
+
+/// A view of a [`CookieJar`], which encrypts the values of cookies so that they can be neither
+/// read nor tampered with.
+///
+/// Each value is encrypted using AES-256-GCM, with the cookie's name as associated data (so that
+/// the value of one private cookie cannot be copied to another).
+///
+/// The [`with_cookie_keys`] middleware stores a `PrivateCookies` in the extensions of each request.
+///
+/// [`CookieJar`]: struct.CookieJar.html
+/// [`with_cookie_keys`]: ../middlewares/fn.with_cookie_keys.html
+#[derive(Clone, Debug)]
+pub struct PrivateCookies {
+    jar: CookieJar,
+    keys: CookieKeys,
+}
+
+impl PrivateCookies {
+    /// Creates a view of the jar, which encrypts cookies using the given keys.
+    pub fn new(jar: CookieJar, keys: CookieKeys) -> PrivateCookies {
+        PrivateCookies { jar, keys }
+    }
+
+    /// Returns the cookie with the given name, if it can be decrypted.
+    ///
+    /// The value of the returned cookie is the decrypted value.
+    pub fn get(&self, name: &str) -> Option<Cookie> {
+        let mut cookie = self.jar.get(name)?;
+        let data = decode(cookie.value())?;
+        let value = self
+            .keys
+            .keys
+            .iter()
+            .filter_map(|key| key.decrypt(name, &data))
+            .next()?;
+        cookie.set_value(value);
+        Some(cookie)
+    }
+
+    /// Encrypts the cookie's value using the primary key, and adds it to the jar.
+    pub fn add(&self, mut cookie: Cookie) {
+        let value = self.keys.primary().encrypt(cookie.name(), cookie.value());
+        cookie.set_value(value);
+        self.jar.add(cookie);
+    }
+
+    /// Removes a cookie from the jar. See [`CookieJar::remove`].
+    ///
+    /// [`CookieJar::remove`]: struct.CookieJar.html#method.remove
+    pub fn remove(&self, cookie: Cookie) {
+        self.jar.remove(cookie);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CookieKeys, Key, PrivateCookies, SignedCookies};
+    use cookies::{Cookie, CookieJar};
+
+    // Returns a jar containing the changes made to another jar, as if the client had sent them.
+    fn round_trip(jar: &CookieJar) -> CookieJar {
+        let round_tripped = CookieJar::new();
+        for cookie in jar.delta() {
+            round_tripped.add(cookie);
+        }
+        round_tripped
+    }
+
+    fn tamper(jar: &CookieJar, name: &str) {
+        let mut cookie = jar.get(name).unwrap();
+        let mut value = cookie.value().to_owned();
+        let last = if value.ends_with('A') { "B" } else { "A" };
+        value.pop();
+        value.push_str(last);
+        cookie.set_value(value);
+        jar.add(cookie);
+    }
+
+    #[test]
+    fn signed() {
+        let keys = CookieKeys::new(Key::generate());
+        let jar = CookieJar::new();
+        SignedCookies::new(jar.clone(), keys.clone()).add(Cookie::new("user", "alice"));
+        assert!(jar.get("user").unwrap().value().starts_with("alice."));
+
+        let jar = round_trip(&jar);
+        let signed = SignedCookies::new(jar.clone(), keys.clone());
+        assert_eq!(signed.get("user").unwrap().value(), "alice");
+
+        // Unsigned cookies, and cookies with invalid signatures, are rejected.
+        jar.add(Cookie::new("unsigned", "bob"));
+        assert!(signed.get("unsigned").is_none());
+        tamper(&jar, "user");
+        assert!(signed.get("user").is_none());
+
+        // Signatures are bound to the cookie's name.
+        let original = jar.get("user").unwrap();
+        jar.add(Cookie::new("admin", original.value()));
+        assert!(signed.get("admin").is_none());
+    }
+
+    #[test]
+    fn private() {
+        let keys = CookieKeys::new(Key::generate());
+        let jar = CookieJar::new();
+        PrivateCookies::new(jar.clone(), keys.clone()).add(Cookie::new("secret", "hunter2"));
+        assert!(!jar.get("secret").unwrap().value().contains("hunter2"));
+
+        let jar = round_trip(&jar);
+        let private = PrivateCookies::new(jar.clone(), keys.clone());
+        assert_eq!(private.get("secret").unwrap().value(), "hunter2");
+
+        let encrypted = jar.get("secret").unwrap();
+        jar.add(Cookie::new("other", encrypted.value()));
+        assert!(private.get("other").is_none());
+
+        tamper(&jar, "secret");
+        assert!(private.get("secret").is_none());
+        jar.add(Cookie::new("short", "abc"));
+        assert!(private.get("short").is_none());
+    }
+
+    #[test]
+    fn key_rotation() {
+        let old = Key::derive_from(&[1; 32]);
+        let new = Key::derive_from(&[2; 32]);
+
+        let jar = CookieJar::new();
+        SignedCookies::new(jar.clone(), CookieKeys::new(old.clone())).add(Cookie::new("s", "1"));
+        PrivateCookies::new(jar.clone(), CookieKeys::new(old.clone())).add(Cookie::new("p", "2"));
+        let jar = round_trip(&jar);
+
+        // Without the old key, the cookies are rejected.
+        let keys = CookieKeys::new(new.clone());
+        assert!(SignedCookies::new(jar.clone(), keys.clone())
+            .get("s")
+            .is_none());
+        assert!(PrivateCookies::new(jar.clone(), keys).get("p").is_none());
+
+        let keys = CookieKeys::new(new.clone()).fallback(old);
+        let signed = SignedCookies::new(jar.clone(), keys.clone());
+        let private = PrivateCookies::new(jar.clone(), keys);
+        assert_eq!(signed.get("s").unwrap().value(), "1");
+        assert_eq!(private.get("p").unwrap().value(), "2");
+
+        // New cookies use the primary key.
+        signed.add(Cookie::new("s", "3"));
+        let only_new = CookieKeys::new(new);
+        assert_eq!(
+            SignedCookies::new(jar, only_new).get("s").unwrap().value(),
+            "3"
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn short_master_key() {
+        Key::derive_from(b"too short");
+    }
+}
@@ -1,3 +1,5 @@
 //! A collection of useful HTTP handlers.
 
+#[cfg(feature = "proxy")]
+pub mod proxy;
 pub mod static_files;
@@ -0,0 +1,518 @@
+//! A handler which forwards requests to upstream HTTP servers.
+//!
+//! [`proxy_handler(proxy)`] returns a handler which acts as a reverse proxy: each request is sent
+//! to one of the upstream servers configured in a [`Proxy`], and the upstream's response is
+//! returned to the client. Request and response bodies are streamed in both directions, so neither
+//! is buffered in memory by the proxy.
+//!
+//! [`proxy_handler(proxy)`]: fn.proxy_handler.html
+//! [`Proxy`]: struct.Proxy.html
+//!
+//! # Load Balancing
+//!
+//! Requests are distributed across the upstreams in a round-robin fashion. If a health check is
+//! configured (using [`Proxy::health_check`]), each upstream is periodically sent a `GET` request,
+//! and upstreams which fail to respond with a `2xx` status are skipped until they recover.
+//!
+//! [`Proxy::health_check`]: struct.Proxy.html#method.health_check
+//!
+//! # Headers
+//!
+//! Hop-by-hop headers (such as `Connection` and `Transfer-Encoding`, and any listed in the
+//! `Connection` header) are removed from both requests and responses. The `Host` header is
+//! replaced by that of the upstream, and the original host, client address and protocol are passed
+//! to the upstream in the `X-Forwarded-Host`, `X-Forwarded-For` and `X-Forwarded-Proto` headers,
+//! and in the `Forwarded` header ([RFC 7239]). The client address and protocol are only known when
+//! the request has a [`ConnectionInfo`] extension, as is added by the provided servers.
+//!
+//! Protocol upgrades (such as WebSockets) are not supported.
+//!
+//! [RFC 7239]: https://tools.ietf.org/html/rfc7239
+//! [`ConnectionInfo`]: ../../servers/struct.ConnectionInfo.html
+//!
+//! # Errors
+//!
+//! If no upstream is healthy, the handler responds with `503 Service Unavailable`. If the upstream
+//! cannot be reached, the handler responds with `502 Bad Gateway`, and if it does not respond
+//! within its timeout, with `504 Gateway Timeout`.
+
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::future::{self, Either};
+use futures::sync::oneshot;
+use futures::{stream, Future, Stream};
+use futures_timer::{Delay, Interval};
+use http;
+use http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use hyper;
+use hyper::client::HttpConnector;
+use tokio::runtime::{self, Runtime};
+
+use servers::ConnectionInfo;
+use {Body, BodyStream, BoxedResponse, Error, Handler, ResponseBuilder, Result};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Headers which only apply to a single connection, and so must not be forwarded.
+const HOP_BY_HOP: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// An upstream HTTP server, to which a [`proxy_handler`] forwards requests.
+///
+/// [`proxy_handler`]: fn.proxy_handler.html
+#[derive(Clone, Debug)]
+pub struct Upstream {
+    authority: http::uri::Authority,
+    prefix: String,
+    timeout: Duration,
+}
+
+impl Upstream {
+    /// Creates an upstream from an absolute `http://` URI, such as `http://127.0.0.1:8080`.
+    ///
+    /// The path of each request is appended to the path of the URI, so that an upstream of
+    /// `http://127.0.0.1:8080/api` receives a request for `/users` as `/api/users`. Any query in
+    /// the URI is ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the URI is invalid, or does not use the `http` scheme.
+    pub fn new(uri: &str) -> Result<Upstream> {
+        let uri = uri.parse::<http::Uri>()?;
+        let authority = match uri.authority_part() {
+            Some(authority) if uri.scheme_str() == Some("http") => authority.clone(),
+            _ => return Err("upstream URI must be an absolute http:// URI".into()),
+        };
+        let prefix = uri.path().trim_end_matches('/').to_owned();
+        Ok(Upstream {
+            authority,
+            prefix,
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// Sets the time allowed for the upstream to respond to a request (including health checks).
+    ///
+    /// The timeout applies to the time taken to receive the response headers. It does not apply to
+    /// the time taken to stream the response body. Defaults to 30 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn uri(&self, path_and_query: &str) -> Result<http::Uri> {
+        let uri = format!("http://{}{}{}", self.authority, self.prefix, path_and_query);
+        Ok(uri.parse()?)
+    }
+}
+
+struct HealthCheck {
+    path: String,
+    interval: Duration,
+}
+
+/// Configuration for [`proxy_handler`].
+///
+/// [`proxy_handler`]: fn.proxy_handler.html
+#[derive(Default)]
+pub struct Proxy {
+    upstreams: Vec<Upstream>,
+    health_check: Option<HealthCheck>,
+}
+
+impl Proxy {
+    /// Creates a configuration with no upstreams.
+    pub fn new() -> Self {
+        Proxy::default()
+    }
+
+    /// Adds an upstream, to which requests are forwarded.
+    pub fn upstream(mut self, upstream: Upstream) -> Self {
+        self.upstreams.push(upstream);
+        self
+    }
+
+    /// Periodically checks the health of each upstream, by sending it a `GET` request for `path`.
+    ///
+    /// Upstreams are checked when the handler is created, and then once every `interval`. An
+    /// upstream which fails to respond with a `2xx` status within its timeout is not sent any
+    /// requests until a later check succeeds. By default, no health checks are made, and all
+    /// upstreams are assumed to be healthy.
+    pub fn health_check(mut self, path: &str, interval: Duration) -> Self {
+        self.health_check = Some(HealthCheck {
+            path: path.to_owned(),
+            interval,
+        });
+        self
+    }
+}
+
+struct Target {
+    upstream: Upstream,
+    healthy: AtomicBool,
+}
+
+struct Shared {
+    targets: Arc<Vec<Target>>,
+    next: AtomicUsize,
+    client: hyper::Client<HttpConnector>,
+    // Requests to upstreams are made on a separate runtime, so that the handler can be served by
+    // any server (or called from tests) without needing a `tokio` executor.
+    runtime: Runtime,
+}
+
+impl Shared {
+    // Selects the next healthy upstream, in round-robin order.
+    fn select(&self) -> Option<&Target> {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let len = self.targets.len();
+        (0..len)
+            .map(|i| &self.targets[(start + i) % len])
+            .find(|target| target.healthy.load(Ordering::Relaxed))
+    }
+}
+
+/// Returns a handler which forwards requests to the upstreams configured in a [`Proxy`].
+///
+/// See the [module level] documentation for details of how requests are forwarded.
+///
+/// [`Proxy`]: struct.Proxy.html
+/// [module level]: ./index.html
+///
+/// # Errors
+///
+/// Returns an error if no upstreams are configured, if the health check path is invalid, or if the
+/// runtime used to make requests to the upstreams could not be started.
+///
+/// # Example
+///
+/// ```no_run
+/// extern crate aitch;
+///
+/// use std::time::Duration;
+///
+/// use aitch::handlers::proxy::{proxy_handler, Proxy, Upstream};
+/// use aitch::servers::hyper::Server;
+/// use aitch::Result;
+///
+/// fn main() -> Result<()> {
+///     let proxy = Proxy::new()
+///         .upstream(Upstream::new("http://127.0.0.1:8080")?)
+///         .upstream(Upstream::new("http://127.0.0.1:8081")?.timeout(Duration::from_secs(5)))
+///         .health_check("/health", Duration::from_secs(10));
+///
+///     let addr = "127.0.0.1:3000".parse()?;
+///     Server::new(addr, proxy_handler(proxy)?)?.run()
+/// }
+/// ```
+pub fn proxy_handler(proxy: Proxy) -> Result<impl Handler<BodyStream>> {
+    if proxy.upstreams.is_empty() {
+        return Err("proxy has no upstreams".into());
+    }
+
+    let runtime = runtime::Builder::new()
+        .name_prefix("aitch-proxy-")
+        .build()?;
+    let client = hyper::Client::new();
+    let targets = proxy
+        .upstreams
+        .into_iter()
+        .map(|upstream| Target {
+            upstream,
+            healthy: AtomicBool::new(true),
+        })
+        .collect::<Vec<_>>();
+    let targets = Arc::new(targets);
+
+    if let Some(check) = proxy.health_check {
+        let uris = targets
+            .iter()
+            .map(|target| target.upstream.uri(&check.path))
+            .collect::<Result<Vec<_>>>()?;
+        let checks = health_checks(check.interval, uris, targets.clone(), client.clone());
+        runtime.executor().spawn(checks);
+    }
+
+    let shared = Arc::new(Shared {
+        targets,
+        next: AtomicUsize::new(0),
+        client,
+        runtime,
+    });
+
+    Ok(
+        move |req: http::Request<BodyStream>, _resp: ResponseBuilder| -> BoxedResponse {
+            let upstream = match shared.select() {
+                Some(target) => &target.upstream,
+                None => {
+                    return Box::new(future::ok(simple_response(
+                        http::StatusCode::SERVICE_UNAVAILABLE,
+                        "no healthy upstream",
+                    )))
+                }
+            };
+            let req = match forward_request(req, upstream) {
+                Ok(req) => req,
+                Err(err) => return Box::new(future::err(err)),
+            };
+
+            let (tx, rx) = oneshot::channel();
+            let fut = with_deadline(shared.client.request(req), upstream.timeout).then(|result| {
+                let _ = tx.send(result);
+                Ok(())
+            });
+            shared.runtime.executor().spawn(fut);
+
+            let fut = rx.then(|result| {
+                let resp = match result {
+                    Ok(Ok(resp)) => forward_response(resp),
+                    Ok(Err(Failure::Timeout)) => {
+                        simple_response(http::StatusCode::GATEWAY_TIMEOUT, "upstream timed out")
+                    }
+                    Ok(Err(Failure::Upstream)) | Err(_) => {
+                        simple_response(http::StatusCode::BAD_GATEWAY, "upstream unavailable")
+                    }
+                };
+                Ok(resp)
+            });
+            Box::new(fut)
+        },
+    )
+}
+
+fn simple_response(status: http::StatusCode, body: &'static str) -> http::Response<BodyStream> {
+    let mut resp = http::Response::new(Bytes::from_static(body.as_bytes()).into_stream());
+    *resp.status_mut() = status;
+    resp
+}
+
+enum Failure {
+    Timeout,
+    Upstream,
+}
+
+fn with_deadline<F>(fut: F, timeout: Duration) -> impl Future<Item = F::Item, Error = Failure>
+where
+    F: Future<Error = hyper::Error>,
+{
+    fut.select2(Delay::new(timeout))
+        .then(|result| match result {
+            Ok(Either::A((item, _))) => Ok(item),
+            Err(Either::A(_)) => Err(Failure::Upstream),
+            Ok(Either::B(_)) | Err(Either::B(_)) => Err(Failure::Timeout),
+        })
+}
+
+// Checks the health of every upstream immediately, and then once every `interval`.
+fn health_checks(
+    interval: Duration,
+    uris: Vec<http::Uri>,
+    targets: Arc<Vec<Target>>,
+    client: hyper::Client<HttpConnector>,
+) -> impl Future<Item = (), Error = ()> + Send {
+    stream::once(Ok(()))
+        .chain(Interval::new(interval))
+        .map_err(|_| ())
+        .for_each(move |()| {
+            let checks = uris.iter().enumerate().map(|(index, uri)| {
+                let targets = targets.clone();
+                let mut req = http::Request::new(hyper::Body::empty());
+                *req.uri_mut() = uri.clone();
+                with_deadline(client.request(req), targets[index].upstream.timeout).then(
+                    move |result| {
+                        let healthy = match result {
+                            Ok(resp) => resp.status().is_success(),
+                            Err(_) => false,
+                        };
+                        targets[index].healthy.store(healthy, Ordering::Relaxed);
+                        Ok(())
+                    },
+                )
+            });
+            future::join_all(checks.collect::<Vec<_>>()).map(|_| ())
+        })
+}
+
+fn remove_hop_by_hop(headers: &mut HeaderMap) {
+    let listed = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+        .collect::<Vec<_>>();
+    for name in listed {
+        headers.remove(name);
+    }
+    for name in HOP_BY_HOP {
+        headers.remove(*name);
+    }
+}
+
+// Appends `value` to the comma-separated list in any existing `name` headers.
+fn append_to_list(headers: &mut HeaderMap, name: HeaderName, value: &str) -> Result<()> {
+    let mut list = headers
+        .get_all(&name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .collect::<Vec<_>>();
+    list.push(value);
+    let value = HeaderValue::from_str(&list.join(", "))?;
+    headers.insert(name, value);
+    Ok(())
+}
+
+// Quotes a `Forwarded` parameter value, unless it is a valid token.
+fn forwarded_value(value: &str) -> String {
+    let is_token = value
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b));
+    if is_token {
+        value.to_owned()
+    } else {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+fn add_forwarded_headers(
+    headers: &mut HeaderMap,
+    host: Option<HeaderValue>,
+    info: Option<ConnectionInfo>,
+) -> Result<()> {
+    let mut forwarded = Vec::new();
+
+    if let Some(info) = info {
+        let ip = info.remote_addr.ip();
+        let proto = if info.secure { "https" } else { "http" };
+        append_to_list(
+            headers,
+            HeaderName::from_static("x-forwarded-for"),
+            &ip.to_string(),
+        )?;
+        headers.insert(
+            HeaderName::from_static("x-forwarded-proto"),
+            HeaderValue::from_static(proto),
+        );
+        let node = match ip {
+            IpAddr::V4(ip) => ip.to_string(),
+            IpAddr::V6(ip) => format!("[{}]", ip),
+        };
+        forwarded.push(format!("for={}", forwarded_value(&node)));
+        forwarded.push(format!("proto={}", proto));
+    }
+
+    if let Some(host) = host {
+        if let Ok(value) = host.to_str() {
+            forwarded.push(format!("host={}", forwarded_value(value)));
+        }
+        headers.insert(HeaderName::from_static("x-forwarded-host"), host);
+    }
+
+    if !forwarded.is_empty() {
+        append_to_list(headers, header::FORWARDED, &forwarded.join(";"))?;
+    }
+    Ok(())
+}
+
+fn forward_request(
+    req: http::Request<BodyStream>,
+    upstream: &Upstream,
+) -> Result<http::Request<hyper::Body>> {
+    let (mut parts, body) = req.into_parts();
+
+    // Requests without a body (e.g. most GET requests) must not be sent a chunked empty body.
+    let has_body = parts.headers.contains_key(header::CONTENT_LENGTH)
+        || parts.headers.contains_key(header::TRANSFER_ENCODING);
+    remove_hop_by_hop(&mut parts.headers);
+
+    let host = parts.headers.remove(header::HOST).or_else(|| {
+        parts
+            .uri
+            .authority_part()
+            .and_then(|authority| HeaderValue::from_str(authority.as_str()).ok())
+    });
+    let info = parts.extensions.get::<ConnectionInfo>().cloned();
+    add_forwarded_headers(&mut parts.headers, host, info)?;
+
+    let path_and_query = parts
+        .uri
+        .path_and_query()
+        .map(|path_and_query| path_and_query.as_str())
+        .unwrap_or("/");
+    parts.uri = upstream.uri(path_and_query)?;
+    parts.version = http::Version::HTTP_11;
+
+    let body = if has_body {
+        hyper::Body::wrap_stream(body)
+    } else {
+        hyper::Body::empty()
+    };
+    Ok(http::Request::from_parts(parts, body))
+}
+
+fn forward_response(resp: http::Response<hyper::Body>) -> http::Response<BodyStream> {
+    let (mut parts, body) = resp.into_parts();
+    remove_hop_by_hop(&mut parts.headers);
+    let body = body.map(hyper::Chunk::into_bytes).map_err(Error::from);
+    http::Response::from_parts(parts, Box::new(body) as BodyStream)
+}
+
+#[cfg(test)]
+mod test {
+    use http::header::{self, HeaderMap, HeaderValue};
+
+    use super::{forwarded_value, remove_hop_by_hop, Upstream};
+
+    #[test]
+    fn upstream_uris() {
+        let upstream = Upstream::new("http://127.0.0.1:8080").unwrap();
+        let uri = upstream.uri("/users?page=2").unwrap();
+        assert_eq!(uri.to_string(), "http://127.0.0.1:8080/users?page=2");
+
+        let upstream = Upstream::new("http://localhost/api/").unwrap();
+        let uri = upstream.uri("/users").unwrap();
+        assert_eq!(uri.to_string(), "http://localhost/api/users");
+
+        assert!(Upstream::new("https://localhost").is_err());
+        assert!(Upstream::new("/relative").is_err());
+    }
+
+    #[test]
+    fn hop_by_hop_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONNECTION,
+            HeaderValue::from_static("close, x-secret"),
+        );
+        headers.insert(
+            header::TRANSFER_ENCODING,
+            HeaderValue::from_static("chunked"),
+        );
+        headers.insert("x-secret", HeaderValue::from_static("1"));
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        remove_hop_by_hop(&mut headers);
+
+        assert_eq!(headers.len(), 1);
+        assert!(headers.contains_key(header::CONTENT_TYPE));
+    }
+
+    #[test]
+    fn forwarded_values() {
+        assert_eq!(forwarded_value("192.0.2.43"), "192.0.2.43");
+        assert_eq!(forwarded_value("[2001:db8::1]"), "\"[2001:db8::1]\"");
+        assert_eq!(forwarded_value("example.com:8080"), "\"example.com:8080\"");
+    }
+}
@@ -154,10 +154,24 @@
 //! third-party applications can create their own middleware.
 //!
 //! [middlewares]: ./middlewares/index.html
+//!
+//! # Testing Handlers
+//!
+//! Handlers can be tested without starting a server, using the in-process [`TestClient`] provided
+//! by the [`testing`] module.
+//!
+//! [`TestClient`]: ./testing/struct.TestClient.html
+//! [`testing`]: ./testing/index.html
 
+extern crate base64;
 extern crate bytes;
 extern crate futures;
+extern crate futures_timer;
 extern crate http;
+extern crate httpdate;
+extern crate rand;
+extern crate sha2;
+extern crate tokio_executor;
 
 #[cfg(feature = "json")]
 extern crate serde;
@@ -167,22 +181,45 @@ extern crate serde_json;
 #[cfg(feature = "mime_guess")]
 extern crate mime_guess;
 
-#[cfg(feature = "server-hyper")]
+#[cfg(feature = "secure-cookies")]
+extern crate aes_gcm;
+#[cfg(feature = "secure-cookies")]
+extern crate hmac;
+
+#[cfg(feature = "jwt")]
+extern crate jsonwebtoken;
+
+#[cfg(feature = "compression")]
+extern crate brotli;
+#[cfg(feature = "compression")]
+extern crate flate2;
+
+#[cfg(any(feature = "server-hyper", feature = "proxy"))]
 extern crate hyper;
+#[cfg(feature = "proxy")]
+extern crate tokio;
 
 #[cfg(feature = "server-tiny-http")]
 extern crate tiny_http;
 #[cfg(feature = "server-tiny-http")]
 extern crate tokio_threadpool;
 
+#[cfg(test)]
+extern crate quickcheck;
+
 mod body;
+pub mod cookies;
 mod handler;
 pub mod handlers;
 #[cfg(feature = "json")]
 mod json;
 pub mod middlewares;
+mod random;
 mod responder;
 pub mod servers;
+#[cfg(feature = "sessions")]
+pub mod sessions;
+pub mod testing;
 
 use std::error::Error as StdError;
 
@@ -0,0 +1,481 @@
+use std::fmt::Write as FmtWrite;
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use futures::{Async, Future, Poll, Stream};
+use http;
+use httpdate;
+
+#[cfg(feature = "json")]
+use serde_json;
+
+use super::RequestId;
+use servers::ConnectionInfo;
+use {Body, BodyStream, Error, Handler, Responder, ResponseBuilder};
+
+/// The details of a single request/response, which are written to an access log.
+#[derive(Clone, Debug)]
+pub struct AccessLogEntry {
+    /// The address of the client, if it is known.
+    pub remote_addr: Option<SocketAddr>,
+    /// The time at which the request was received.
+    pub time: SystemTime,
+    /// The HTTP method of the request.
+    pub method: http::Method,
+    /// The URI of the request.
+    pub uri: http::Uri,
+    /// The HTTP version of the request.
+    pub version: http::Version,
+    /// The ID of the request, if it has been assigned one by [`with_request_id`].
+    ///
+    /// [`with_request_id`]: fn.with_request_id.html
+    pub request_id: Option<String>,
+    /// The value of the request's `Referer` header, if present.
+    pub referer: Option<String>,
+    /// The value of the request's `User-Agent` header, if present.
+    pub user_agent: Option<String>,
+    /// The status code of the response.
+    pub status: http::StatusCode,
+    /// The number of bytes sent in the response body.
+    pub bytes_sent: u64,
+    /// The time taken for the handler to return the response headers.
+    pub time_to_headers: Duration,
+    /// The time taken for the handler to return the response headers, and for the response body
+    /// to be fully sent.
+    pub total_time: Duration,
+}
+
+/// The format in which an [`AccessLog`] writes its entries.
+///
+/// [`AccessLog`]: struct.AccessLog.html
+#[derive(Clone)]
+pub enum LogFormat {
+    /// The [Common Log Format], as used by many web servers.
+    ///
+    /// [Common Log Format]: https://httpd.apache.org/docs/2.4/logs.html#common
+    Common,
+    /// The [Combined Log Format], which extends the Common Log Format with the `Referer` and
+    /// `User-Agent` request headers.
+    ///
+    /// [Combined Log Format]: https://httpd.apache.org/docs/2.4/logs.html#combined
+    Combined,
+    /// A JSON object per line, containing all of the fields of [`AccessLogEntry`] (including
+    /// timings, in milliseconds).
+    ///
+    /// [`AccessLogEntry`]: struct.AccessLogEntry.html
+    #[cfg(feature = "json")]
+    Json,
+    /// A custom format. The function should return a single line, without a trailing newline.
+    Custom(Arc<Fn(&AccessLogEntry) -> String + Send + Sync>),
+}
+
+/// Configuration for the [`with_access_log`] middleware.
+///
+/// By default, entries are written to stdout in the [Combined Log Format].
+///
+/// [`with_access_log`]: fn.with_access_log.html
+/// [Combined Log Format]: enum.LogFormat.html#variant.Combined
+#[derive(Clone)]
+pub struct AccessLog {
+    format: LogFormat,
+    sink: Arc<Mutex<Write + Send>>,
+}
+
+impl Default for AccessLog {
+    fn default() -> Self {
+        AccessLog::new(io::stdout())
+    }
+}
+
+impl AccessLog {
+    /// Creates an `AccessLog` which writes to the given sink.
+    pub fn new<W: Write + Send + 'static>(sink: W) -> Self {
+        AccessLog {
+            format: LogFormat::Combined,
+            sink: Arc::new(Mutex::new(sink)),
+        }
+    }
+
+    /// Sets the format in which entries are written.
+    pub fn format(mut self, format: LogFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Formats an entry, and writes it to the sink.
+    ///
+    /// Errors writing to the sink are ignored, so that a failing log does not cause requests to
+    /// fail.
+    pub fn write(&self, entry: &AccessLogEntry) {
+        let mut line = match self.format {
+            LogFormat::Common => format_common(entry),
+            LogFormat::Combined => format_combined(entry),
+            #[cfg(feature = "json")]
+            LogFormat::Json => format_json(entry),
+            LogFormat::Custom(ref func) => func(entry),
+        };
+        line.push('\n');
+
+        if let Ok(mut sink) = self.sink.lock() {
+            let _ = sink.write_all(line.as_bytes());
+            let _ = sink.flush();
+        }
+    }
+}
+
+/// Middleware which writes an access log entry for each HTTP request.
+///
+/// Unlike [`with_stdout_logging`], this middleware records when the response body has been fully
+/// sent (or the client has gone away), so that it can log the number of bytes sent and the total
+/// time taken to respond. Requests which result in the handler returning an error are logged with a
+/// `500` status.
+///
+/// If the request has a [`ConnectionInfo`] extension (which is added by both of the provided
+/// servers), the client's address is included in the log. If the request has a [`RequestId`], it is
+/// included in JSON logs, and is available to custom formats.
+///
+/// [`with_stdout_logging`]: fn.with_stdout_logging.html
+/// [`RequestId`]: struct.RequestId.html
+/// [`ConnectionInfo`]: ../servers/struct.ConnectionInfo.html
+///
+/// # Example
+///
+/// ```no_run
+/// extern crate aitch;
+/// extern crate http;
+///
+/// use std::fs::OpenOptions;
+///
+/// use aitch::servers::hyper::Server;
+/// use aitch::{middlewares, Responder, ResponseBuilder, Result};
+/// use aitch::middlewares::{AccessLog, LogFormat};
+/// use http::Request;
+///
+/// fn handler(_req: Request<()>, mut resp: ResponseBuilder) -> impl Responder {
+///     resp.body("Hello, world!".to_owned())
+/// }
+///
+/// fn main() -> Result<()> {
+///     let file = OpenOptions::new().create(true).append(true).open("access.log")?;
+///     let log = AccessLog::new(file).format(LogFormat::Common);
+///     let wrapped = middlewares::with_access_log(log, handler);
+///
+///     let addr = "127.0.0.1:3000".parse()?;
+///     Server::new(addr, wrapped)?.run()
+/// }
+/// ```
+pub fn with_access_log<B: Body>(log: AccessLog, handler: impl Handler<B>) -> impl Handler<B> {
+    move |req: http::Request<B>, resp: ResponseBuilder| {
+        let log = log.clone();
+        let start = Instant::now();
+        let mut entry = AccessLogEntry {
+            remote_addr: req
+                .extensions()
+                .get::<ConnectionInfo>()
+                .map(|info| info.remote_addr),
+            time: SystemTime::now(),
+            method: req.method().clone(),
+            uri: req.uri().clone(),
+            version: req.version(),
+            request_id: req
+                .extensions()
+                .get::<RequestId>()
+                .map(|id| id.as_str().to_owned()),
+            referer: header_string(&req, http::header::REFERER),
+            user_agent: header_string(&req, http::header::USER_AGENT),
+            status: http::StatusCode::INTERNAL_SERVER_ERROR,
+            bytes_sent: 0,
+            time_to_headers: Duration::from_secs(0),
+            total_time: Duration::from_secs(0),
+        };
+
+        handler
+            .handle(req, resp)
+            .into_response()
+            .then(move |result| {
+                entry.time_to_headers = start.elapsed();
+                match result {
+                    Ok(resp) => {
+                        entry.status = resp.status();
+                        Ok(resp.map(|body| {
+                            let body = LoggedBody {
+                                inner: body,
+                                log,
+                                entry: Some(entry),
+                                start,
+                            };
+                            Box::new(body) as BodyStream
+                        }))
+                    }
+                    Err(err) => {
+                        entry.total_time = entry.time_to_headers;
+                        log.write(&entry);
+                        Err(err)
+                    }
+                }
+            })
+    }
+}
+
+fn header_string<B>(req: &http::Request<B>, name: http::header::HeaderName) -> Option<String> {
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_owned())
+}
+
+// Wraps a response body, counting the bytes sent and writing the log entry once the body has
+// finished (or has been dropped, if the client disconnects).
+struct LoggedBody {
+    inner: BodyStream,
+    log: AccessLog,
+    entry: Option<AccessLogEntry>,
+    start: Instant,
+}
+
+impl LoggedBody {
+    fn finish(&mut self) {
+        if let Some(mut entry) = self.entry.take() {
+            entry.total_time = self.start.elapsed();
+            self.log.write(&entry);
+        }
+    }
+}
+
+impl Stream for LoggedBody {
+    type Item = Bytes;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Bytes>, Error> {
+        match self.inner.poll() {
+            Ok(Async::Ready(Some(chunk))) => {
+                if let Some(ref mut entry) = self.entry {
+                    entry.bytes_sent += chunk.len() as u64;
+                }
+                Ok(Async::Ready(Some(chunk)))
+            }
+            Ok(Async::Ready(None)) => {
+                self.finish();
+                Ok(Async::Ready(None))
+            }
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(err) => {
+                self.finish();
+                Err(err)
+            }
+        }
+    }
+}
+
+impl Drop for LoggedBody {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+fn format_common(entry: &AccessLogEntry) -> String {
+    let host = entry
+        .remote_addr
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_else(|| "-".to_owned());
+    let bytes = match entry.bytes_sent {
+        0 => "-".to_owned(),
+        n => n.to_string(),
+    };
+    format!(
+        "{} - - [{}] \"{} {} {:?}\" {} {}",
+        host,
+        format_clf_time(entry.time),
+        entry.method,
+        request_target(&entry.uri),
+        entry.version,
+        entry.status.as_u16(),
+        bytes
+    )
+}
+
+fn format_combined(entry: &AccessLogEntry) -> String {
+    format!(
+        "{} \"{}\" \"{}\"",
+        format_common(entry),
+        escape(entry.referer.as_ref().map_or("-", |s| s.as_str())),
+        escape(entry.user_agent.as_ref().map_or("-", |s| s.as_str()))
+    )
+}
+
+#[cfg(feature = "json")]
+fn format_json(entry: &AccessLogEntry) -> String {
+    use serde_json::{Map, Value};
+
+    fn millis(duration: Duration) -> Value {
+        let millis = duration.as_secs() as f64 * 1000.0 + f64::from(duration.subsec_nanos()) / 1e6;
+        Value::from(millis)
+    }
+
+    let mut obj = Map::new();
+    obj.insert(
+        "remote_addr".to_owned(),
+        entry
+            .remote_addr
+            .map_or(Value::Null, |addr| Value::from(addr.to_string())),
+    );
+    obj.insert("time".to_owned(), Value::from(format_rfc3339(entry.time)));
+    obj.insert("method".to_owned(), Value::from(entry.method.as_str()));
+    obj.insert("uri".to_owned(), Value::from(entry.uri.to_string()));
+    obj.insert(
+        "version".to_owned(),
+        Value::from(format!("{:?}", entry.version)),
+    );
+    obj.insert(
+        "request_id".to_owned(),
+        entry.request_id.clone().map_or(Value::Null, Value::from),
+    );
+    obj.insert("status".to_owned(), Value::from(entry.status.as_u16()));
+    obj.insert("bytes_sent".to_owned(), Value::from(entry.bytes_sent));
+    obj.insert(
+        "referer".to_owned(),
+        entry.referer.clone().map_or(Value::Null, Value::from),
+    );
+    obj.insert(
+        "user_agent".to_owned(),
+        entry.user_agent.clone().map_or(Value::Null, Value::from),
+    );
+    obj.insert(
+        "time_to_headers_ms".to_owned(),
+        millis(entry.time_to_headers),
+    );
+    obj.insert("total_time_ms".to_owned(), millis(entry.total_time));
+    serde_json::to_string(&Value::Object(obj)).unwrap_or_default()
+}
+
+fn request_target(uri: &http::Uri) -> &str {
+    uri.path_and_query().map_or("/", |pq| pq.as_str())
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\x{:02x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+// The latest time which `httpdate` can format (9999-12-31T23:59:59Z).
+const MAX_SECS: u64 = 253_402_300_799;
+
+// The UTC date and time of a `SystemTime`, as formatted by `httpdate`.
+struct CivilTime {
+    year: String,
+    month: usize,
+    day: String,
+    time: String,
+}
+
+// Splits a time into its UTC date and time, clamping it to the range which `httpdate` supports
+// (1970 to 9999), and truncating it to the second.
+fn civil_time(time: SystemTime) -> CivilTime {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+        .min(MAX_SECS);
+    // Always of the form `Sun, 06 Nov 1994 08:49:37 GMT`.
+    let formatted = httpdate::fmt_http_date(UNIX_EPOCH + Duration::from_secs(secs));
+    let parts = formatted.split(' ').collect::<Vec<_>>();
+    let month = MONTHS
+        .iter()
+        .position(|month| *month == parts[2])
+        .expect("httpdate formats months in English");
+    CivilTime {
+        day: parts[1].to_owned(),
+        month: month + 1,
+        year: parts[3].to_owned(),
+        time: parts[4].to_owned(),
+    }
+}
+
+fn format_clf_time(time: SystemTime) -> String {
+    let civil = civil_time(time);
+    format!(
+        "{}/{}/{}:{} +0000",
+        civil.day,
+        MONTHS[civil.month - 1],
+        civil.year,
+        civil.time
+    )
+}
+
+#[cfg(feature = "json")]
+fn format_rfc3339(time: SystemTime) -> String {
+    let civil = civil_time(time);
+    format!(
+        "{}-{:02}-{}T{}Z",
+        civil.year, civil.month, civil.day, civil.time
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[cfg(feature = "json")]
+    use super::format_rfc3339;
+    use super::{escape, format_clf_time};
+
+    #[test]
+    fn clf_time() {
+        let time = UNIX_EPOCH + Duration::from_secs(971_186_136);
+        assert_eq!(format_clf_time(time), "10/Oct/2000:13:55:36 +0000");
+
+        let time = UNIX_EPOCH + Duration::from_secs(951_782_400);
+        assert_eq!(format_clf_time(time), "29/Feb/2000:00:00:00 +0000");
+        let time = UNIX_EPOCH + Duration::from_secs(1_709_251_199);
+        assert_eq!(format_clf_time(time), "29/Feb/2024:23:59:59 +0000");
+        // 2100 is not a leap year.
+        let time = UNIX_EPOCH + Duration::from_secs(4_107_542_399);
+        assert_eq!(format_clf_time(time), "28/Feb/2100:23:59:59 +0000");
+        let time = UNIX_EPOCH + Duration::from_secs(4_107_542_400);
+        assert_eq!(format_clf_time(time), "01/Mar/2100:00:00:00 +0000");
+
+        assert_eq!(format_clf_time(UNIX_EPOCH), "01/Jan/1970:00:00:00 +0000");
+        let time = UNIX_EPOCH + Duration::from_millis(946_684_799_999);
+        assert_eq!(format_clf_time(time), "31/Dec/1999:23:59:59 +0000");
+
+        // Times outside the supported range are clamped.
+        let time = UNIX_EPOCH - Duration::from_secs(1);
+        assert_eq!(format_clf_time(time), "01/Jan/1970:00:00:00 +0000");
+        let time = UNIX_EPOCH + Duration::from_secs(1 << 40);
+        assert_eq!(format_clf_time(time), "31/Dec/9999:23:59:59 +0000");
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn rfc3339_time() {
+        let time = UNIX_EPOCH + Duration::from_secs(951_782_400);
+        assert_eq!(format_rfc3339(time), "2000-02-29T00:00:00Z");
+        assert_eq!(format_rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+    }
+
+    #[test]
+    fn escape_quotes_and_control_characters() {
+        assert_eq!(escape("Mozilla/5.0"), "Mozilla/5.0");
+        assert_eq!(
+            escape("a \"quoted\" \\ value\n"),
+            "a \\\"quoted\\\" \\\\ value\\x0a"
+        );
+    }
+}
@@ -0,0 +1,335 @@
+use std::sync::Arc;
+
+use base64;
+use bytes::Bytes;
+use futures::{future, Future, IntoFuture};
+use http;
+use http::header::{self, HeaderValue};
+
+use {Body, BoxedResponse, Error, Handler, Responder, ResponseBuilder};
+
+/// A future returned by the verifier of [`BasicAuth`] or [`BearerAuth`], which resolves to the
+/// authenticated principal, or `None` if the credentials are not valid.
+///
+/// [`BasicAuth`]: struct.BasicAuth.html
+/// [`BearerAuth`]: struct.BearerAuth.html
+pub type AuthFuture<P> = Box<Future<Item = Option<P>, Error = Error> + Send>;
+
+type BasicFn<P> = Fn(&str, &str) -> AuthFuture<P> + Send + Sync;
+type BearerFn<P> = Fn(&str) -> AuthFuture<P> + Send + Sync;
+
+fn boxed<R, P>(result: R) -> AuthFuture<P>
+where
+    R: IntoFuture<Item = Option<P>, Error = Error>,
+    R::Future: Send + 'static,
+{
+    Box::new(result.into_future())
+}
+
+// Formats a value as a quoted-string, for use in a challenge's parameters.
+fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+// Returns the credentials from the `Authorization` header, if it uses the given scheme.
+fn credentials<'a>(headers: &'a http::HeaderMap, scheme: &str) -> Option<&'a str> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?.trim();
+    let mut iter = value.splitn(2, ' ');
+    if !iter.next()?.eq_ignore_ascii_case(scheme) {
+        return None;
+    }
+    let credentials = iter.next()?.trim();
+    if credentials.is_empty() {
+        None
+    } else {
+        Some(credentials)
+    }
+}
+
+// Decodes the credentials of a Basic `Authorization` header into a username and password.
+fn decode_basic(credentials: &str) -> Option<(String, String)> {
+    let decoded =
+        String::from_utf8(base64::decode_config(credentials, base64::STANDARD).ok()?).ok()?;
+    let mut iter = decoded.splitn(2, ':');
+    let username = iter.next()?.to_owned();
+    let password = iter.next()?.to_owned();
+    Some((username, password))
+}
+
+fn unauthorized(challenge: String) -> BoxedResponse {
+    let mut resp = http::Response::new(Bytes::from_static(b"unauthorized").into_stream());
+    *resp.status_mut() = http::StatusCode::UNAUTHORIZED;
+    if let Ok(value) = HeaderValue::from_str(&challenge) {
+        resp.headers_mut().insert(header::WWW_AUTHENTICATE, value);
+    }
+    Box::new(future::ok(resp))
+}
+
+// Calls the handler with the principal in the request's extensions, or responds with the
+// challenge if there is no principal.
+fn authenticate<B, P, H>(
+    verified: AuthFuture<P>,
+    req: http::Request<B>,
+    resp: ResponseBuilder,
+    handler: Arc<H>,
+    challenge: String,
+) -> BoxedResponse
+where
+    B: Body,
+    P: Clone + Send + Sync + 'static,
+    H: Handler<B>,
+{
+    let fut = verified.and_then(move |principal| -> BoxedResponse {
+        let principal = match principal {
+            Some(principal) => principal,
+            None => return unauthorized(challenge),
+        };
+        let mut req = req;
+        req.extensions_mut().insert(principal);
+        handler.handle(req, resp).into_response()
+    });
+    Box::new(fut)
+}
+
+/// Configuration for the [`with_basic_auth`] middleware.
+///
+/// The verifier is called with the username and password sent by the client, and returns the
+/// authenticated principal (of any type `P`), or `None` if the credentials are not valid. It may
+/// return either a `Result<Option<P>>`, or a future which resolves to an `Option<P>`.
+///
+/// [`with_basic_auth`]: fn.with_basic_auth.html
+pub struct BasicAuth<P> {
+    realm: String,
+    verify: Arc<BasicFn<P>>,
+}
+
+impl<P> Clone for BasicAuth<P> {
+    fn clone(&self) -> Self {
+        BasicAuth {
+            realm: self.realm.clone(),
+            verify: self.verify.clone(),
+        }
+    }
+}
+
+impl<P: Send + 'static> BasicAuth<P> {
+    /// Creates a `BasicAuth` for the given realm, which verifies credentials using `verify`.
+    pub fn new<F, R>(realm: &str, verify: F) -> Self
+    where
+        F: Fn(&str, &str) -> R + Send + Sync + 'static,
+        R: IntoFuture<Item = Option<P>, Error = Error>,
+        R::Future: Send + 'static,
+    {
+        BasicAuth {
+            realm: realm.to_owned(),
+            verify: Arc::new(move |username, password| boxed(verify(username, password))),
+        }
+    }
+
+    fn challenge(&self) -> String {
+        format!("Basic realm={}, charset=\"UTF-8\"", quote(&self.realm))
+    }
+}
+
+/// Middleware which authenticates requests using HTTP Basic authentication (RFC 7617).
+///
+/// The username and password are read from the request's `Authorization` header, and passed to
+/// the verifier set on the provided [`BasicAuth`]. If it returns a principal, the principal is
+/// stored in the extensions of the request, and the wrapped handler is called. Otherwise, the
+/// request is rejected with `401 Unauthorized` and a `WWW-Authenticate` challenge, without calling
+/// the handler. Errors returned by the verifier cause the request to fail.
+///
+/// Basic authentication sends the password with every request, so should only be used over HTTPS.
+///
+/// [`BasicAuth`]: struct.BasicAuth.html
+///
+/// # Example
+///
+/// ```no_run
+/// extern crate aitch;
+/// extern crate http;
+///
+/// use aitch::middlewares::{self, BasicAuth};
+/// use aitch::servers::hyper::Server;
+/// use aitch::{Responder, ResponseBuilder, Result};
+/// use http::Request;
+///
+/// #[derive(Clone)]
+/// struct User(String);
+///
+/// fn handler(req: Request<()>, mut resp: ResponseBuilder) -> impl Responder {
+///     let user = req.extensions().get::<User>().unwrap();
+///     resp.body(format!("Hello, {}!", user.0))
+/// }
+///
+/// fn main() -> Result<()> {
+///     let auth = BasicAuth::new("internal", |username: &str, password: &str| {
+///         let valid = username == "admin" && password == "hunter2";
+///         Ok(if valid { Some(User(username.to_owned())) } else { None })
+///     });
+///     let wrapped = middlewares::with_basic_auth(auth, handler);
+///
+///     let addr = "127.0.0.1:3000".parse()?;
+///     Server::new(addr, wrapped)?.run()
+/// }
+/// ```
+pub fn with_basic_auth<B, P>(auth: BasicAuth<P>, handler: impl Handler<B>) -> impl Handler<B>
+where
+    B: Body,
+    P: Clone + Send + Sync + 'static,
+{
+    let handler = Arc::new(handler);
+    move |req: http::Request<B>, resp: ResponseBuilder| -> BoxedResponse {
+        let credentials = credentials(req.headers(), "Basic").and_then(decode_basic);
+        let (username, password) = match credentials {
+            Some(credentials) => credentials,
+            None => return unauthorized(auth.challenge()),
+        };
+
+        let verified = (auth.verify)(&username, &password);
+        authenticate(verified, req, resp, handler.clone(), auth.challenge())
+    }
+}
+
+/// Configuration for the [`with_bearer_auth`] middleware.
+///
+/// The verifier is called with the token sent by the client, and returns the authenticated
+/// principal (of any type `P`), or `None` if the token is not valid. It may return either a
+/// `Result<Option<P>>`, or a future which resolves to an `Option<P>`.
+///
+/// [`with_bearer_auth`]: fn.with_bearer_auth.html
+pub struct BearerAuth<P> {
+    realm: String,
+    verify: Arc<BearerFn<P>>,
+}
+
+impl<P> Clone for BearerAuth<P> {
+    fn clone(&self) -> Self {
+        BearerAuth {
+            realm: self.realm.clone(),
+            verify: self.verify.clone(),
+        }
+    }
+}
+
+impl<P: Send + 'static> BearerAuth<P> {
+    /// Creates a `BearerAuth` for the given realm, which verifies tokens using `verify`.
+    pub fn new<F, R>(realm: &str, verify: F) -> Self
+    where
+        F: Fn(&str) -> R + Send + Sync + 'static,
+        R: IntoFuture<Item = Option<P>, Error = Error>,
+        R::Future: Send + 'static,
+    {
+        BearerAuth {
+            realm: realm.to_owned(),
+            verify: Arc::new(move |token| boxed(verify(token))),
+        }
+    }
+
+    fn challenge(&self, error: Option<&str>) -> String {
+        let mut challenge = format!("Bearer realm={}", quote(&self.realm));
+        if let Some(error) = error {
+            challenge.push_str(&format!(", error={}", quote(error)));
+        }
+        challenge
+    }
+}
+
+/// Middleware which authenticates requests using bearer tokens (RFC 6750).
+///
+/// The token is read from the request's `Authorization` header, and passed to the verifier set on
+/// the provided [`BearerAuth`]. If it returns a principal, the principal is stored in the
+/// extensions of the request, and the wrapped handler is called. Otherwise, the request is rejected
+/// with `401 Unauthorized` and a `WWW-Authenticate` challenge, without calling the handler. The
+/// challenge includes `error="invalid_token"` when the client sent a token which was not valid.
+/// Errors returned by the verifier cause the request to fail.
+///
+/// [`BearerAuth`]: struct.BearerAuth.html
+///
+/// # Example
+///
+/// ```no_run
+/// extern crate aitch;
+/// extern crate http;
+///
+/// use aitch::middlewares::{self, BearerAuth};
+/// use aitch::servers::hyper::Server;
+/// use aitch::{Responder, ResponseBuilder, Result};
+/// use http::Request;
+///
+/// #[derive(Clone)]
+/// struct ApiClient(String);
+///
+/// fn handler(req: Request<()>, mut resp: ResponseBuilder) -> impl Responder {
+///     let client = req.extensions().get::<ApiClient>().unwrap();
+///     resp.body(format!("Hello, {}!", client.0))
+/// }
+///
+/// fn main() -> Result<()> {
+///     let auth = BearerAuth::new("api", |token: &str| match token {
+///         "secret-token" => Ok(Some(ApiClient("reporting".to_owned()))),
+///         _ => Ok(None),
+///     });
+///     let wrapped = middlewares::with_bearer_auth(auth, handler);
+///
+///     let addr = "127.0.0.1:3000".parse()?;
+///     Server::new(addr, wrapped)?.run()
+/// }
+/// ```
+pub fn with_bearer_auth<B, P>(auth: BearerAuth<P>, handler: impl Handler<B>) -> impl Handler<B>
+where
+    B: Body,
+    P: Clone + Send + Sync + 'static,
+{
+    let handler = Arc::new(handler);
+    move |req: http::Request<B>, resp: ResponseBuilder| -> BoxedResponse {
+        let verified = match credentials(req.headers(), "Bearer") {
+            Some(token) => (auth.verify)(token),
+            None => return unauthorized(auth.challenge(None)),
+        };
+        let challenge = auth.challenge(Some("invalid_token"));
+        authenticate(verified, req, resp, handler.clone(), challenge)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode_basic, quote};
+
+    #[test]
+    fn basic_credentials() {
+        assert_eq!(
+            decode_basic("QWxhZGRpbjpvcGVuIHNlc2FtZQ=="),
+            Some(("Aladdin".to_owned(), "open sesame".to_owned()))
+        );
+        // Passwords may contain colons, and either part may be empty.
+        assert_eq!(
+            decode_basic("dXNlcjpwYTpzcw=="),
+            Some(("user".to_owned(), "pa:ss".to_owned()))
+        );
+        assert_eq!(decode_basic("Og=="), Some(("".to_owned(), "".to_owned())));
+        // No colon.
+        assert_eq!(decode_basic("dXNlcg=="), None);
+        // Not base64.
+        assert_eq!(decode_basic("dXNl*jpwYXNz"), None);
+        // Malformed base64: an impossible length, non-zero trailing bits, and too much padding.
+        assert_eq!(decode_basic("Og==O"), None);
+        assert_eq!(decode_basic("Oh=="), None);
+        assert_eq!(decode_basic("Og==="), None);
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(quote("api"), "\"api\"");
+        assert_eq!(quote("a \"b\" \\c"), "\"a \\\"b\\\" \\\\c\"");
+    }
+}
@@ -0,0 +1,63 @@
+use std::mem;
+
+use bytes::Bytes;
+use futures::{Async, Future, Poll, Stream};
+
+use {BodyStream, Error};
+
+pub(super) enum Buffered {
+    // The whole body, which was no larger than the maximum size.
+    Complete(Bytes),
+    // The start of a body which is larger than the maximum size, and the remainder of the body.
+    TooLarge(Bytes, BodyStream),
+}
+
+// Buffers a request or response body, up to a maximum size.
+pub(super) struct Buffer {
+    body: Option<BodyStream>,
+    buffered: Vec<u8>,
+    max_size: usize,
+}
+
+impl Buffer {
+    pub(super) fn new(body: BodyStream, max_size: usize) -> Self {
+        Buffer {
+            body: Some(body),
+            buffered: Vec::new(),
+            max_size,
+        }
+    }
+}
+
+impl Future for Buffer {
+    type Item = Buffered;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Buffered, Error> {
+        loop {
+            let polled = self
+                .body
+                .as_mut()
+                .expect("Buffer polled after completion")
+                .poll()?;
+            match polled {
+                Async::Ready(Some(chunk)) => {
+                    self.buffered.extend_from_slice(&chunk);
+                    if self.buffered.len() > self.max_size {
+                        let buffered = mem::take(&mut self.buffered);
+                        let body = self.body.take().expect("Buffer polled after completion");
+                        return Ok(Async::Ready(Buffered::TooLarge(
+                            Bytes::from(buffered),
+                            body,
+                        )));
+                    }
+                }
+                Async::Ready(None) => {
+                    let buffered = mem::take(&mut self.buffered);
+                    return Ok(Async::Ready(Buffered::Complete(Bytes::from(buffered))));
+                }
+                Async::NotReady => return Ok(Async::NotReady),
+            }
+        }
+    }
+}
@@ -0,0 +1,645 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use bytes::Bytes;
+use futures::future::Executor;
+use futures::{future, stream, Future, Stream};
+use http;
+use http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use httpdate;
+use tokio_executor::DefaultExecutor;
+
+use super::buffer::{Buffer, Buffered};
+use {Body, BodyStream, BoxedResponse, Handler, Responder, ResponseBuilder};
+
+type BackgroundTask = Box<Future<Item = (), Error = ()> + Send>;
+
+// The statuses of responses which may be cached (RFC 7231, section 6.1).
+const CACHEABLE_STATUSES: &[u16] = &[200, 203, 204, 300, 301, 404, 405, 410, 414, 501];
+
+// Larger lifetimes are treated as this many seconds (RFC 7234, section 1.2.1).
+const MAX_DELTA_SECONDS: u64 = 1 << 31;
+
+#[derive(Debug, Default)]
+struct CacheControl {
+    no_store: bool,
+    no_cache: bool,
+    private: bool,
+    public: bool,
+    max_age: Option<u64>,
+    s_maxage: Option<u64>,
+    stale_while_revalidate: Option<u64>,
+}
+
+impl CacheControl {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let mut directives = CacheControl::default();
+        let values = headers
+            .get_all(header::CACHE_CONTROL)
+            .iter()
+            .filter_map(|value| value.to_str().ok());
+        for directive in values.flat_map(|value| value.split(',')) {
+            let mut iter = directive.splitn(2, '=');
+            let name = iter.next().unwrap_or("").trim().to_lowercase();
+            let value = iter
+                .next()
+                .and_then(|value| value.trim().trim_matches('"').parse::<u64>().ok())
+                .map(|secs| secs.min(MAX_DELTA_SECONDS));
+            match name.as_str() {
+                "no-store" => directives.no_store = true,
+                "no-cache" => directives.no_cache = true,
+                "private" => directives.private = true,
+                "public" => directives.public = true,
+                "max-age" => directives.max_age = value,
+                "s-maxage" => directives.s_maxage = value,
+                "stale-while-revalidate" => directives.stale_while_revalidate = value,
+                _ => {}
+            }
+        }
+        directives
+    }
+}
+
+struct Entry {
+    status: http::StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    stored: Instant,
+    fresh_for: Duration,
+    stale_for: Duration,
+    size: usize,
+    revalidating: bool,
+    last_used: u64,
+}
+
+impl Entry {
+    fn response(&self, now: Instant) -> http::Response<BodyStream> {
+        let mut resp = http::Response::new(self.body.clone().into_stream());
+        *resp.status_mut() = self.status;
+        *resp.headers_mut() = self.headers.clone();
+        let age = now.duration_since(self.stored).as_secs();
+        resp.headers_mut()
+            .insert(header::AGE, HeaderValue::from(age));
+        resp
+    }
+}
+
+enum Lookup {
+    Miss,
+    Fresh(http::Response<BodyStream>),
+    // A stale response which can be served while it is revalidated, and whether this request
+    // should revalidate it.
+    Stale(http::Response<BodyStream>, bool),
+}
+
+// The cached responses, in least-recently-used order.
+#[derive(Default)]
+struct Lru {
+    entries: HashMap<String, Entry>,
+    order: BTreeMap<u64, String>,
+    tick: u64,
+    size: usize,
+}
+
+impl Lru {
+    fn lookup(&mut self, key: &str, now: Instant) -> Lookup {
+        let (age, fresh_for, stale_for) = match self.entries.get(key) {
+            Some(entry) => (
+                now.duration_since(entry.stored),
+                entry.fresh_for,
+                entry.stale_for,
+            ),
+            None => return Lookup::Miss,
+        };
+        // Lifetimes are clamped when they're parsed, but `stale_for` may have been configured.
+        let expired = fresh_for
+            .checked_add(stale_for)
+            .is_some_and(|lifetime| age >= lifetime);
+        if expired {
+            self.remove(key);
+            return Lookup::Miss;
+        }
+
+        self.tick += 1;
+        let tick = self.tick;
+        let entry = self.entries.get_mut(key).expect("entry exists");
+        self.order.remove(&entry.last_used);
+        self.order.insert(tick, key.to_owned());
+        entry.last_used = tick;
+
+        if age < fresh_for {
+            return Lookup::Fresh(entry.response(now));
+        }
+        let revalidate = !entry.revalidating;
+        entry.revalidating = true;
+        Lookup::Stale(entry.response(now), revalidate)
+    }
+
+    fn insert(&mut self, key: String, mut entry: Entry, capacity: usize) {
+        self.remove(&key);
+        self.tick += 1;
+        entry.last_used = self.tick;
+        self.size += entry.size;
+        self.order.insert(self.tick, key.clone());
+        self.entries.insert(key, entry);
+
+        while self.size > capacity {
+            let oldest = match self.order.values().next() {
+                Some(key) => key.clone(),
+                None => break,
+            };
+            self.remove(&oldest);
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.order.remove(&entry.last_used);
+            self.size -= entry.size;
+        }
+    }
+
+    // Removes the responses cached for a resource, for every value of the varying headers.
+    fn remove_resource(&mut self, resource: &str) {
+        let keys = self
+            .entries
+            .keys()
+            .filter(|key| {
+                key.starts_with(resource)
+                    && (key.len() == resource.len() || key[resource.len()..].starts_with('\n'))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        for key in keys {
+            self.remove(&key);
+        }
+    }
+}
+
+/// Configuration for the [`with_response_cache`] middleware.
+///
+/// By default, up to 64 MiB of responses are cached, and responses with bodies larger than 1 MiB
+/// are not cached. Responses are cached separately for each host and URI, but not for different
+/// request headers unless they are added using [`vary`]. Stale responses are revalidated on the
+/// default executor of the task handling the request, unless another is set using [`executor`].
+///
+/// A `ResponseCache` can be cloned, and all clones share the same cached responses.
+///
+/// [`with_response_cache`]: fn.with_response_cache.html
+/// [`vary`]: #method.vary
+/// [`executor`]: #method.executor
+#[derive(Clone)]
+pub struct ResponseCache {
+    state: Arc<Mutex<Lru>>,
+    capacity: usize,
+    max_size: usize,
+    vary: Vec<HeaderName>,
+    stale_while_revalidate: Option<Duration>,
+    executor: Arc<Executor<BackgroundTask> + Send + Sync>,
+}
+
+impl Default for ResponseCache {
+    fn default() -> Self {
+        ResponseCache {
+            state: Arc::new(Mutex::new(Lru::default())),
+            capacity: 64 * 1024 * 1024,
+            max_size: 1024 * 1024,
+            vary: Vec::new(),
+            stale_while_revalidate: None,
+            executor: Arc::new(DefaultExecutor::current()),
+        }
+    }
+}
+
+impl ResponseCache {
+    /// Creates an empty `ResponseCache` with the default configuration.
+    pub fn new() -> Self {
+        ResponseCache::default()
+    }
+
+    /// Sets the total size of the responses which are cached, in bytes.
+    ///
+    /// When the cache is full, the least recently used responses are removed.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Sets the maximum size of the body of a response which is cached, in bytes.
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Caches responses separately for each value of the given request header.
+    ///
+    /// Responses with a `Vary` header are only cached if every header it lists has been added
+    /// using this method.
+    pub fn vary(mut self, name: HeaderName) -> Self {
+        self.vary.push(name);
+        self
+    }
+
+    /// Sets how long stale responses are served while they are revalidated, for responses without
+    /// a `stale-while-revalidate` directive.
+    pub fn stale_while_revalidate(mut self, duration: Duration) -> Self {
+        self.stale_while_revalidate = Some(duration);
+        self
+    }
+
+    /// Sets the executor on which stale responses are revalidated.
+    ///
+    /// By default, revalidation is spawned onto the default executor of the task handling the
+    /// request (such as the runtime of the `hyper` server, or the thread-pool of the `tiny_http`
+    /// server). If it can't be spawned, the stale response is served without being revalidated.
+    pub fn executor<E>(mut self, executor: E) -> Self
+    where
+        E: Executor<BackgroundTask> + Send + Sync + 'static,
+    {
+        self.executor = Arc::new(executor);
+        self
+    }
+
+    /// Returns the number of cached responses.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().entries.len()
+    }
+
+    /// Returns `true` if no responses are cached.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all cached responses.
+    pub fn clear(&self) {
+        *self.state.lock().unwrap() = Lru::default();
+    }
+
+    // Identifies the resource requested, by its host and URI.
+    fn resource<B>(&self, req: &http::Request<B>) -> String {
+        let host = req
+            .headers()
+            .get(header::HOST)
+            .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+            .or_else(|| req.uri().authority_part().map(|a| a.as_str().to_owned()))
+            .unwrap_or_default();
+        format!("{} {}", host.to_lowercase(), req.uri())
+    }
+
+    fn key<B>(&self, req: &http::Request<B>) -> String {
+        let mut key = format!("GET {}", self.resource(req));
+        for name in &self.vary {
+            key.push('\n');
+            key.push_str(name.as_str());
+            key.push(':');
+            for value in req.headers().get_all(name) {
+                key.push_str(&String::from_utf8_lossy(value.as_bytes()));
+                key.push(',');
+            }
+        }
+        key
+    }
+
+    // Returns how long the response is fresh, and for how long after that it can be served while
+    // it is revalidated, or `None` if it can't be cached.
+    fn lifetime(
+        &self,
+        parts: &http::response::Parts,
+        authorized: bool,
+    ) -> Option<(Duration, Duration)> {
+        if !CACHEABLE_STATUSES.contains(&parts.status.as_u16())
+            || parts.headers.contains_key(header::SET_COOKIE)
+        {
+            return None;
+        }
+
+        let directives = CacheControl::from_headers(&parts.headers);
+        if directives.no_store || directives.no_cache || directives.private {
+            return None;
+        }
+        // Responses to authorized requests may contain private data (RFC 7234, section 3.2).
+        if authorized && !directives.public && directives.s_maxage.is_none() {
+            return None;
+        }
+
+        let vary = parts
+            .headers
+            .get_all(header::VARY)
+            .iter()
+            .flat_map(|value| value.to_str().unwrap_or("*").split(','))
+            .map(|name| name.trim())
+            .filter(|name| !name.is_empty());
+        for name in vary {
+            if !self
+                .vary
+                .iter()
+                .any(|n| n.as_str().eq_ignore_ascii_case(name))
+            {
+                return None;
+            }
+        }
+
+        let fresh_for = match directives.s_maxage.or(directives.max_age) {
+            Some(secs) => Duration::from_secs(secs),
+            None => {
+                let expires = parts
+                    .headers
+                    .get(header::EXPIRES)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| httpdate::parse_http_date(value).ok())?;
+                expires
+                    .duration_since(SystemTime::now())
+                    .ok()?
+                    .min(Duration::from_secs(MAX_DELTA_SECONDS))
+            }
+        };
+        if fresh_for == Duration::from_secs(0) {
+            return None;
+        }
+        let stale_for = directives
+            .stale_while_revalidate
+            .map(Duration::from_secs)
+            .or(self.stale_while_revalidate)
+            .unwrap_or_default();
+        Some((fresh_for, stale_for))
+    }
+
+    // Stores the response once its body has been buffered, if it can be cached. The returned future
+    // resolves to the response, with the same body.
+    fn store(
+        &self,
+        key: String,
+        authorized: bool,
+        resp: http::Response<BodyStream>,
+    ) -> BoxedResponse {
+        let (parts, body) = resp.into_parts();
+        let (fresh_for, stale_for) = match self.lifetime(&parts, authorized) {
+            Some(lifetime) => lifetime,
+            None => {
+                self.state.lock().unwrap().remove(&key);
+                return Box::new(future::ok(http::Response::from_parts(parts, body)));
+            }
+        };
+
+        let cache = self.clone();
+        let fut = Buffer::new(body, self.max_size).map(move |buffered| {
+            let body = match buffered {
+                Buffered::Complete(body) => body,
+                Buffered::TooLarge(start, rest) => {
+                    cache.state.lock().unwrap().remove(&key);
+                    let body = Box::new(stream::once(Ok(start)).chain(rest)) as BodyStream;
+                    return http::Response::from_parts(parts, body);
+                }
+            };
+
+            let headers_size: usize = parts
+                .headers
+                .iter()
+                .map(|(name, value)| name.as_str().len() + value.len())
+                .sum();
+            let entry = Entry {
+                status: parts.status,
+                headers: parts.headers.clone(),
+                body: body.clone(),
+                stored: Instant::now(),
+                fresh_for,
+                stale_for,
+                size: key.len() + headers_size + body.len(),
+                revalidating: false,
+                last_used: 0,
+            };
+            cache
+                .state
+                .lock()
+                .unwrap()
+                .insert(key, entry, cache.capacity);
+            http::Response::from_parts(parts, body.into_stream())
+        });
+        Box::new(fut)
+    }
+
+    // Allows a later request to revalidate a stale response, after revalidating it failed.
+    fn revalidation_failed(&self, key: &str) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(entry) = state.entries.get_mut(key) {
+            entry.revalidating = false;
+        }
+    }
+}
+
+/// Middleware which caches responses to `GET` requests in memory.
+///
+/// Responses are cached according to the `Cache-Control` header set by the wrapped handler: they
+/// are cached for the time given by the `s-maxage` or `max-age` directive (or until the time in the
+/// `Expires` header), and are not cached if the `no-store`, `no-cache` or `private` directives are
+/// present. Responses which set cookies, and responses to requests with an `Authorization` header
+/// (unless marked as `public`), are not cached.
+///
+/// Cached responses are served without calling the handler, with an `Age` header. Once a response
+/// is stale, it continues to be served for the time given by its `stale-while-revalidate`
+/// directive, while the handler is called in the background to revalidate it (see
+/// [`ResponseCache::executor`]).
+///
+/// When the handler successfully responds to a request with an unsafe method (such as `PUT` or
+/// `DELETE`), the responses cached for its URI are removed.
+///
+/// [`ResponseCache::executor`]: struct.ResponseCache.html#method.executor
+///
+/// The size of the cache is limited as configured on the provided [`ResponseCache`].
+///
+/// [`ResponseCache`]: struct.ResponseCache.html
+///
+/// # Example
+///
+/// ```no_run
+/// extern crate aitch;
+/// extern crate http;
+///
+/// use aitch::middlewares::{self, ResponseCache};
+/// use aitch::servers::hyper::Server;
+/// use aitch::{Responder, ResponseBuilder, Result};
+/// use http::Request;
+///
+/// fn handler(_req: Request<()>, mut resp: ResponseBuilder) -> impl Responder {
+///     // An expensive response, which can be cached for a minute.
+///     resp.header(http::header::CACHE_CONTROL, "max-age=60, stale-while-revalidate=30")
+///         .body("Hello, world!".to_owned())
+/// }
+///
+/// fn main() -> Result<()> {
+///     let cache = ResponseCache::new().vary(http::header::ACCEPT_LANGUAGE);
+///     let wrapped = middlewares::with_response_cache(cache, handler);
+///
+///     let addr = "127.0.0.1:3000".parse()?;
+///     Server::new(addr, wrapped)?.run()
+/// }
+/// ```
+pub fn with_response_cache<B: Body>(
+    cache: ResponseCache,
+    handler: impl Handler<B>,
+) -> impl Handler<B> {
+    let handler = Arc::new(handler);
+    move |req: http::Request<B>, resp: ResponseBuilder| -> BoxedResponse {
+        if !req.method().is_safe() {
+            let cache = cache.clone();
+            let resource = format!("GET {}", cache.resource(&req));
+            let fut = handler.handle(req, resp).into_response().map(move |resp| {
+                if !resp.status().is_client_error() && !resp.status().is_server_error() {
+                    cache.state.lock().unwrap().remove_resource(&resource);
+                }
+                resp
+            });
+            return Box::new(fut);
+        }
+        if req.method() != http::Method::GET {
+            return handler.handle(req, resp).into_response();
+        }
+
+        let key = cache.key(&req);
+        let authorized = req.headers().contains_key(header::AUTHORIZATION);
+        let lookup = cache.state.lock().unwrap().lookup(&key, Instant::now());
+        let stale = match lookup {
+            Lookup::Fresh(cached) => return Box::new(future::ok(cached)),
+            Lookup::Stale(cached, false) => return Box::new(future::ok(cached)),
+            Lookup::Stale(cached, true) => Some(cached),
+            Lookup::Miss => None,
+        };
+
+        // The handler is called lazily, so that revalidation calls it on the executor.
+        let fut = {
+            let cache = cache.clone();
+            let handler = handler.clone();
+            let key = key.clone();
+            future::lazy(move || handler.handle(req, resp).into_response())
+                .and_then(move |resp| cache.store(key, authorized, resp))
+        };
+        match stale {
+            Some(cached) => {
+                // Revalidate in the background. The body must be consumed, so that it is stored.
+                let task = {
+                    let cache = cache.clone();
+                    let key = key.clone();
+                    fut.and_then(|resp| resp.into_body().concat2())
+                        .then(move |result| {
+                            if result.is_err() {
+                                cache.revalidation_failed(&key);
+                            }
+                            Ok(())
+                        })
+                };
+                if cache.executor.execute(Box::new(task)).is_err() {
+                    cache.revalidation_failed(&key);
+                }
+                Box::new(future::ok(cached))
+            }
+            None => Box::new(fut),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use bytes::Bytes;
+    use http::header::{self, HeaderValue};
+    use http::{HeaderMap, StatusCode};
+
+    use super::{CacheControl, Entry, Lookup, Lru, MAX_DELTA_SECONDS};
+
+    fn entry(size: usize, stored: Instant) -> Entry {
+        Entry {
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+            body: Bytes::new(),
+            stored,
+            fresh_for: Duration::from_secs(10),
+            stale_for: Duration::from_secs(5),
+            size,
+            revalidating: false,
+            last_used: 0,
+        }
+    }
+
+    fn is_miss(lookup: Lookup) -> bool {
+        matches!(lookup, Lookup::Miss)
+    }
+
+    #[test]
+    fn eviction() {
+        let now = Instant::now();
+        let mut lru = Lru::default();
+        lru.insert("a".to_owned(), entry(40, now), 100);
+        lru.insert("b".to_owned(), entry(40, now), 100);
+        assert!(!is_miss(lru.lookup("a", now)));
+
+        // "b" is the least recently used.
+        lru.insert("c".to_owned(), entry(40, now), 100);
+        assert!(is_miss(lru.lookup("b", now)));
+        assert!(!is_miss(lru.lookup("a", now)));
+        assert!(!is_miss(lru.lookup("c", now)));
+        assert_eq!(lru.size, 80);
+
+        // Replacing an entry doesn't count it twice.
+        lru.insert("c".to_owned(), entry(50, now), 100);
+        assert_eq!(lru.size, 90);
+        assert_eq!(lru.entries.len(), 2);
+        assert_eq!(lru.order.len(), 2);
+    }
+
+    #[test]
+    fn staleness() {
+        let now = Instant::now();
+        let mut lru = Lru::default();
+        lru.insert("a".to_owned(), entry(1, now), 100);
+
+        assert!(matches!(lru.lookup("a", now), Lookup::Fresh(_)));
+        let stale = now + Duration::from_secs(12);
+        assert!(matches!(lru.lookup("a", stale), Lookup::Stale(_, true)));
+        // Only one request revalidates the response.
+        assert!(matches!(lru.lookup("a", stale), Lookup::Stale(_, false)));
+        assert!(is_miss(lru.lookup("a", now + Duration::from_secs(15))));
+        assert!(lru.entries.is_empty());
+        assert_eq!(lru.size, 0);
+    }
+
+    #[test]
+    fn long_lifetimes() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CACHE_CONTROL,
+            HeaderValue::from_static("max-age=18446744073709551615, stale-while-revalidate=1"),
+        );
+        let directives = CacheControl::from_headers(&headers);
+        assert_eq!(directives.max_age, Some(MAX_DELTA_SECONDS));
+        assert_eq!(directives.stale_while_revalidate, Some(1));
+
+        // Lifetimes which overflow when added never expire.
+        let now = Instant::now();
+        let mut lru = Lru::default();
+        let mut long = entry(1, now);
+        long.fresh_for = Duration::new(u64::MAX, 0);
+        long.stale_for = Duration::from_secs(1);
+        lru.insert("a".to_owned(), long, 100);
+        assert!(matches!(
+            lru.lookup("a", now + Duration::from_secs(60)),
+            Lookup::Fresh(_)
+        ));
+    }
+
+    #[test]
+    fn remove_resource() {
+        let now = Instant::now();
+        let mut lru = Lru::default();
+        for key in &["GET h /a", "GET h /a\naccept:x,", "GET h /ab", "GET h /b"] {
+            lru.insert(key.to_string(), entry(1, now), 100);
+        }
+        lru.remove_resource("GET h /a");
+
+        let mut keys = lru.entries.keys().cloned().collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(keys, vec!["GET h /ab", "GET h /b"]);
+        assert_eq!(lru.size, 2);
+    }
+}
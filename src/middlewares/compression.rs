@@ -0,0 +1,612 @@
+use std::io::{self, Write};
+use std::mem;
+
+use brotli;
+use bytes::Bytes;
+use flate2;
+use flate2::write::{GzEncoder, ZlibEncoder};
+use futures::{future, stream, Async, Future, Poll, Stream};
+use http;
+use http::header::{self, HeaderMap, HeaderValue};
+
+use {Body, BodyStream, BoxedResponse, Error, Handler, Responder, ResponseBuilder};
+
+/// A content-coding which can be used to compress responses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// The `br` content-coding ([Brotli]).
+    ///
+    /// [Brotli]: https://tools.ietf.org/html/rfc7932
+    Brotli,
+    /// The `gzip` content-coding.
+    Gzip,
+    /// The `deflate` content-coding (which is zlib-wrapped DEFLATE, despite the name).
+    Deflate,
+}
+
+impl Encoding {
+    /// Returns the name of the content-coding, as used in the `Accept-Encoding` and
+    /// `Content-Encoding` headers.
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+}
+
+/// How much effort is spent compressing responses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompressionLevel {
+    /// The fastest compression, at the expense of compression ratio.
+    Fastest,
+    /// A balance between speed and compression ratio, suitable for dynamic responses.
+    Default,
+    /// The best compression ratio, at the expense of speed.
+    Best,
+}
+
+impl CompressionLevel {
+    fn flate2(self) -> flate2::Compression {
+        match self {
+            CompressionLevel::Fastest => flate2::Compression::fast(),
+            CompressionLevel::Default => flate2::Compression::default(),
+            CompressionLevel::Best => flate2::Compression::best(),
+        }
+    }
+
+    fn brotli_quality(self) -> u32 {
+        // Brotli's highest quality is very slow, so isn't suitable for compressing responses on the
+        // fly, except when asked for explicitly.
+        match self {
+            CompressionLevel::Fastest => 1,
+            CompressionLevel::Default => 4,
+            CompressionLevel::Best => 11,
+        }
+    }
+}
+
+/// Configuration for the [`with_compression`] middleware.
+///
+/// By default, the `br`, `gzip` and `deflate` encodings are all enabled (preferred in that order,
+/// when the client has no preference), [`CompressionLevel::Default`] is used, and responses
+/// smaller than 1KiB are not compressed.
+///
+/// [`with_compression`]: fn.with_compression.html
+/// [`CompressionLevel::Default`]: enum.CompressionLevel.html#variant.Default
+#[derive(Clone, Debug)]
+pub struct Compression {
+    encodings: Vec<Encoding>,
+    level: CompressionLevel,
+    min_size: usize,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression {
+            encodings: vec![Encoding::Brotli, Encoding::Gzip, Encoding::Deflate],
+            level: CompressionLevel::Default,
+            min_size: 1024,
+        }
+    }
+}
+
+impl Compression {
+    /// Creates a `Compression` with the default settings.
+    pub fn new() -> Self {
+        Compression::default()
+    }
+
+    /// Sets the encodings which may be used, in order of preference.
+    ///
+    /// The server's preference is only used to choose between encodings which the client
+    /// considers equally acceptable.
+    pub fn encodings<I>(mut self, encodings: I) -> Self
+    where
+        I: IntoIterator<Item = Encoding>,
+    {
+        self.encodings = encodings.into_iter().collect();
+        self
+    }
+
+    /// Sets the compression level.
+    pub fn level(mut self, level: CompressionLevel) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Sets the size (in bytes) below which responses are not compressed.
+    ///
+    /// Small responses gain little from compression, and may even grow.
+    pub fn min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    // Chooses the encoding with the highest q-value in the request's `Accept-Encoding` header,
+    // breaking ties using the configured order of preference.
+    fn negotiate(&self, headers: &HeaderMap) -> Option<Encoding> {
+        let accepted = accepted_encodings(headers);
+        let quality = |encoding: Encoding| {
+            accepted
+                .iter()
+                .find(|(name, _)| name == encoding.as_str())
+                .or_else(|| accepted.iter().find(|(name, _)| name == "*"))
+                .map_or(0, |&(_, q)| q)
+        };
+
+        let mut best = None;
+        let mut best_q = 0;
+        for &encoding in &self.encodings {
+            let q = quality(encoding);
+            if q > best_q {
+                best = Some(encoding);
+                best_q = q;
+            }
+        }
+        best
+    }
+}
+
+// Parses the `Accept-Encoding` header into (lowercase coding, q-value * 1000) pairs.
+fn accepted_encodings(headers: &HeaderMap) -> Vec<(String, u16)> {
+    headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let name = parts.next()?.trim().to_lowercase();
+            if name.is_empty() {
+                return None;
+            }
+            let mut q = 1000;
+            for param in parts {
+                let param = param.trim();
+                if param.starts_with("q=") || param.starts_with("Q=") {
+                    q = parse_qvalue(&param[2..])?;
+                }
+            }
+            Some((name, q))
+        })
+        .collect()
+}
+
+fn parse_qvalue(value: &str) -> Option<u16> {
+    let value: f32 = value.trim().parse().ok()?;
+    if !(0.0..=1.0).contains(&value) {
+        return None;
+    }
+    Some((value * 1000.0).round() as u16)
+}
+
+// Content types which are (usually) already compressed, so shouldn't be compressed again.
+fn is_compressed_content_type(headers: &HeaderMap) -> bool {
+    let content_type = match headers.get(header::CONTENT_TYPE) {
+        Some(value) => value.to_str().unwrap_or("").to_lowercase(),
+        None => return false,
+    };
+    let mime = content_type.split(';').next().unwrap_or("").trim();
+
+    match mime.split('/').next().unwrap_or("") {
+        "image" => mime != "image/svg+xml" && mime != "image/bmp",
+        "audio" | "video" => true,
+        _ => [
+            "application/gzip",
+            "application/x-gzip",
+            "application/zip",
+            "application/x-bzip2",
+            "application/x-xz",
+            "application/x-7z-compressed",
+            "application/x-rar-compressed",
+            "application/zstd",
+            "font/woff",
+            "font/woff2",
+        ]
+        .contains(&mime),
+    }
+}
+
+fn content_length(headers: &HeaderMap) -> Option<usize> {
+    headers
+        .get(header::CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+}
+
+/// Middleware which compresses response bodies.
+///
+/// The encoding is negotiated using the request's `Accept-Encoding` header. The response body is
+/// compressed chunk by chunk as it is produced, with each chunk flushed through the encoder, so
+/// streaming responses continue to stream.
+///
+/// Responses are left uncompressed if:
+///
+///  - the client doesn't accept any of the configured encodings.
+///  - the response already has a `Content-Encoding`.
+///  - the response's `Content-Type` is already compressed (e.g. most images, audio, video and
+///    archives).
+///  - the response is smaller than the configured minimum size. If the response has no
+///    `Content-Length`, this middleware waits until it has received enough of the body to decide,
+///    or until the handler stops producing body chunks for the moment (in which case the response
+///    is treated as a stream, and compressed).
+///  - the response has no body (e.g. responses to `HEAD` requests, or `204 No Content`).
+///
+/// When a response is compressed, its `Content-Encoding` header is set, its `Content-Length` header
+/// is removed, and any strong `ETag` is made weak. The `Vary` header of all responses which could
+/// have been compressed is updated to include `Accept-Encoding`.
+///
+/// This middleware is only available when the `compression` feature is enabled.
+///
+/// # Example
+///
+/// ```no_run
+/// extern crate aitch;
+/// extern crate http;
+///
+/// use aitch::middlewares::{self, Compression, CompressionLevel};
+/// use aitch::servers::hyper::Server;
+/// use aitch::{Responder, ResponseBuilder, Result};
+/// use http::Request;
+///
+/// fn handler(_req: Request<()>, mut resp: ResponseBuilder) -> impl Responder {
+///     resp.header(http::header::CONTENT_TYPE, "text/plain")
+///         .body("Hello, world! ".repeat(1000))
+/// }
+///
+/// fn main() -> Result<()> {
+///     let compression = Compression::new().level(CompressionLevel::Fastest);
+///     let wrapped = middlewares::with_compression(compression, handler);
+///
+///     let addr = "127.0.0.1:3000".parse()?;
+///     Server::new(addr, wrapped)?.run()
+/// }
+/// ```
+pub fn with_compression<B: Body>(
+    compression: Compression,
+    handler: impl Handler<B>,
+) -> impl Handler<B> {
+    move |req: http::Request<B>, resp: ResponseBuilder| -> BoxedResponse {
+        let encoding = compression.negotiate(req.headers());
+        let is_head = req.method() == http::Method::HEAD;
+        let min_size = compression.min_size;
+        let level = compression.level;
+
+        let fut =
+            handler
+                .handle(req, resp)
+                .into_response()
+                .and_then(move |resp| -> BoxedResponse {
+                    let status = resp.status();
+                    if is_head
+                        || status.is_informational()
+                        || status == http::StatusCode::NO_CONTENT
+                        || status == http::StatusCode::NOT_MODIFIED
+                        || resp.headers().contains_key(header::CONTENT_ENCODING)
+                        || is_compressed_content_type(resp.headers())
+                    {
+                        return Box::new(future::ok(resp));
+                    }
+
+                    let (mut parts, body) = resp.into_parts();
+                    let is_small = match content_length(&parts.headers) {
+                        Some(len) => len < min_size,
+                        None => false,
+                    };
+                    if is_small {
+                        return Box::new(future::ok(http::Response::from_parts(parts, body)));
+                    }
+
+                    parts
+                        .headers
+                        .append(header::VARY, HeaderValue::from_static("accept-encoding"));
+                    let encoding = match encoding {
+                        Some(encoding) => encoding,
+                        None => {
+                            return Box::new(future::ok(http::Response::from_parts(parts, body)))
+                        }
+                    };
+
+                    let fut = Peek::new(body, min_size).map(move |peeked| {
+                        let (buffered, rest) = match peeked {
+                            Peeked::Small(body) => {
+                                // The `Vary` header added above is harmless, as this response is the
+                                // same for all clients.
+                                let body = Box::new(stream::once(Ok(body))) as BodyStream;
+                                return http::Response::from_parts(parts, body);
+                            }
+                            Peeked::Large(buffered, rest) => (buffered, rest),
+                        };
+
+                        parts.headers.remove(header::CONTENT_LENGTH);
+                        parts.headers.insert(
+                            header::CONTENT_ENCODING,
+                            HeaderValue::from_static(encoding.as_str()),
+                        );
+                        weaken_etag(&mut parts.headers);
+
+                        let body = CompressedBody {
+                            inner: Box::new(stream::once(Ok(buffered)).chain(rest)),
+                            encoder: Some(Encoder::new(encoding, level)),
+                        };
+                        http::Response::from_parts(parts, Box::new(body) as BodyStream)
+                    });
+                    Box::new(fut)
+                });
+        Box::new(fut) as BoxedResponse
+    }
+}
+
+// The compressed representation of a resource must not share a strong ETag with the
+// uncompressed representation, as they are not byte-for-byte identical.
+fn weaken_etag(headers: &mut HeaderMap) {
+    let weak = match headers.get(header::ETAG) {
+        Some(etag) if !etag.as_bytes().starts_with(b"W/") => {
+            let mut weak = b"W/".to_vec();
+            weak.extend_from_slice(etag.as_bytes());
+            HeaderValue::from_bytes(&weak).ok()
+        }
+        _ => None,
+    };
+    if let Some(weak) = weak {
+        headers.insert(header::ETAG, weak);
+    }
+}
+
+enum Peeked {
+    // The body ended before reaching the minimum size.
+    Small(Bytes),
+    // Either the minimum size was reached, or the body stopped producing chunks before it ended.
+    Large(Bytes, BodyStream),
+}
+
+// Buffers the start of a response body, until it is known whether the body is large enough to be
+// worth compressing.
+struct Peek {
+    body: Option<BodyStream>,
+    buffered: Vec<u8>,
+    min_size: usize,
+}
+
+impl Peek {
+    fn new(body: BodyStream, min_size: usize) -> Self {
+        Peek {
+            body: Some(body),
+            buffered: Vec::new(),
+            min_size,
+        }
+    }
+}
+
+impl Future for Peek {
+    type Item = Peeked;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Peeked, Error> {
+        loop {
+            let polled = self
+                .body
+                .as_mut()
+                .expect("Peek polled after completion")
+                .poll()?;
+            match polled {
+                Async::Ready(Some(chunk)) => {
+                    self.buffered.extend_from_slice(&chunk);
+                    if self.buffered.len() >= self.min_size {
+                        break;
+                    }
+                }
+                Async::Ready(None) => {
+                    let buffered = mem::take(&mut self.buffered);
+                    return Ok(Async::Ready(Peeked::Small(Bytes::from(buffered))));
+                }
+                Async::NotReady if self.buffered.is_empty() => return Ok(Async::NotReady),
+                Async::NotReady => break,
+            }
+        }
+
+        let buffered = mem::take(&mut self.buffered);
+        let body = self.body.take().expect("Peek polled after completion");
+        Ok(Async::Ready(Peeked::Large(Bytes::from(buffered), body)))
+    }
+}
+
+enum Encoder {
+    Brotli(Box<brotli::CompressorWriter<Vec<u8>>>),
+    Gzip(GzEncoder<Vec<u8>>),
+    Deflate(ZlibEncoder<Vec<u8>>),
+}
+
+impl Encoder {
+    fn new(encoding: Encoding, level: CompressionLevel) -> Self {
+        match encoding {
+            Encoding::Brotli => Encoder::Brotli(Box::new(brotli::CompressorWriter::new(
+                Vec::new(),
+                4096,
+                level.brotli_quality(),
+                22,
+            ))),
+            Encoding::Gzip => Encoder::Gzip(GzEncoder::new(Vec::new(), level.flate2())),
+            Encoding::Deflate => Encoder::Deflate(ZlibEncoder::new(Vec::new(), level.flate2())),
+        }
+    }
+
+    // Compresses a chunk, and flushes the encoder so that the chunk can be decoded by the client
+    // without waiting for the rest of the body.
+    fn compress(&mut self, chunk: &[u8]) -> io::Result<Bytes> {
+        let buf = match *self {
+            Encoder::Brotli(ref mut encoder) => {
+                encoder.write_all(chunk)?;
+                encoder.flush()?;
+                encoder.get_mut()
+            }
+            Encoder::Gzip(ref mut encoder) => {
+                encoder.write_all(chunk)?;
+                encoder.flush()?;
+                encoder.get_mut()
+            }
+            Encoder::Deflate(ref mut encoder) => {
+                encoder.write_all(chunk)?;
+                encoder.flush()?;
+                encoder.get_mut()
+            }
+        };
+        Ok(Bytes::from(mem::take(buf)))
+    }
+
+    fn finish(self) -> io::Result<Bytes> {
+        let buf = match self {
+            Encoder::Brotli(encoder) => encoder.into_inner(),
+            Encoder::Gzip(encoder) => encoder.finish()?,
+            Encoder::Deflate(encoder) => encoder.finish()?,
+        };
+        Ok(Bytes::from(buf))
+    }
+}
+
+struct CompressedBody {
+    inner: BodyStream,
+    encoder: Option<Encoder>,
+}
+
+impl Stream for CompressedBody {
+    type Item = Bytes;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Bytes>, Error> {
+        loop {
+            if self.encoder.is_none() {
+                return Ok(Async::Ready(None));
+            }
+            let chunk = match self.inner.poll()? {
+                Async::Ready(chunk) => chunk,
+                Async::NotReady => return Ok(Async::NotReady),
+            };
+            match chunk {
+                Some(ref chunk) if chunk.is_empty() => {}
+                Some(chunk) => {
+                    let encoder = self.encoder.as_mut().expect("encoder is present");
+                    let compressed = encoder.compress(&chunk)?;
+                    if !compressed.is_empty() {
+                        return Ok(Async::Ready(Some(compressed)));
+                    }
+                }
+                None => {
+                    let encoder = self.encoder.take().expect("encoder is present");
+                    return Ok(Async::Ready(Some(encoder.finish()?)));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use bytes::Bytes;
+    use flate2::write::ZlibDecoder;
+    use futures::{stream, Async, Future, Poll, Stream};
+    use http::header::{HeaderMap, HeaderValue, ACCEPT_ENCODING};
+
+    use super::{CompressedBody, Compression, CompressionLevel, Encoder, Encoding, Peek, Peeked};
+    use {BodyStream, Error};
+
+    fn negotiate(accept: &str) -> Option<Encoding> {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT_ENCODING, HeaderValue::from_str(accept).unwrap());
+        Compression::default().negotiate(&headers)
+    }
+
+    #[test]
+    fn negotiate_qvalues() {
+        assert_eq!(negotiate("gzip"), Some(Encoding::Gzip));
+        assert_eq!(
+            negotiate("GZIP;Q=0.5, deflate;q=0.6"),
+            Some(Encoding::Deflate)
+        );
+        assert_eq!(negotiate("gzip;q=0.001"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("gzip;q=2"), None);
+        assert_eq!(negotiate("gzip;q=nonsense"), None);
+        assert_eq!(negotiate(""), None);
+    }
+
+    #[test]
+    fn chunks_are_flushed() {
+        let chunks: Vec<Bytes> = vec!["first chunk".into(), "second chunk".into()];
+        let mut body = CompressedBody {
+            inner: Box::new(stream::iter_ok(chunks)),
+            encoder: Some(Encoder::new(Encoding::Deflate, CompressionLevel::Default)),
+        };
+        let mut decoder = ZlibDecoder::new(Vec::new());
+
+        for expected in &["first chunk", "first chunksecond chunk"] {
+            let chunk = match body.poll().unwrap() {
+                Async::Ready(Some(chunk)) => chunk,
+                other => panic!("unexpected poll result: {:?}", other),
+            };
+            decoder.write_all(&chunk).unwrap();
+            decoder.flush().unwrap();
+            assert_eq!(decoder.get_ref().as_slice(), expected.as_bytes());
+        }
+
+        let last = body.collect().wait().unwrap();
+        assert_eq!(last.len(), 1);
+        decoder.write_all(&last[0]).unwrap();
+        assert_eq!(
+            decoder.finish().unwrap(),
+            b"first chunksecond chunk".to_vec()
+        );
+    }
+
+    // A stream which yields a single chunk, and then is never ready again.
+    struct Stalled(Option<Bytes>);
+
+    impl Stream for Stalled {
+        type Item = Bytes;
+        type Error = Error;
+
+        fn poll(&mut self) -> Poll<Option<Bytes>, Error> {
+            match self.0.take() {
+                Some(chunk) => Ok(Async::Ready(Some(chunk))),
+                None => Ok(Async::NotReady),
+            }
+        }
+    }
+
+    #[test]
+    fn peek_small_body() {
+        let body =
+            Box::new(stream::iter_ok(vec![Bytes::from("a"), Bytes::from("b")])) as BodyStream;
+        match Peek::new(body, 10).wait().unwrap() {
+            Peeked::Small(body) => assert_eq!(body, "ab"),
+            Peeked::Large(..) => panic!("expected small body"),
+        }
+    }
+
+    #[test]
+    fn peek_large_body() {
+        let chunks = vec![Bytes::from("abc"), Bytes::from("def"), Bytes::from("g")];
+        let body = Box::new(stream::iter_ok(chunks)) as BodyStream;
+        match Peek::new(body, 5).wait().unwrap() {
+            Peeked::Large(buffered, rest) => {
+                assert_eq!(buffered, "abcdef");
+                assert_eq!(rest.concat2().wait().unwrap(), "g");
+            }
+            Peeked::Small(_) => panic!("expected large body"),
+        }
+    }
+
+    #[test]
+    fn peek_stalled_body() {
+        let body = Box::new(Stalled(Some(Bytes::from("abc")))) as BodyStream;
+        match Peek::new(body, 1024).wait().unwrap() {
+            Peeked::Large(buffered, _) => assert_eq!(buffered, "abc"),
+            Peeked::Small(_) => panic!("expected body to be treated as a stream"),
+        }
+    }
+}
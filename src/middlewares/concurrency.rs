@@ -0,0 +1,300 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::task::{self, Task};
+use futures::{future, Async, Future, Poll, Stream};
+use futures_timer::Delay;
+use http;
+use http::header::{self, HeaderValue};
+
+use {Body, BodyStream, BoxedResponse, Error, Handler, Responder, ResponseBuilder};
+
+#[derive(Default)]
+struct State {
+    in_flight: usize,
+    next_id: u64,
+    // Requests waiting for a slot, in the order they arrived.
+    waiting: VecDeque<(u64, Task)>,
+}
+
+impl State {
+    fn notify_front(&self) {
+        if let Some((_, task)) = self.waiting.front() {
+            task.notify();
+        }
+    }
+}
+
+/// Configuration for the [`with_concurrency_limit`] middleware, which also exposes the current
+/// concurrency for metrics.
+///
+/// A `ConcurrencyLimit` can be cloned, and all clones share the same state. This allows a clone to
+/// be kept, after the original is passed to [`with_concurrency_limit`], to report metrics. If the
+/// same `ConcurrencyLimit` is used to wrap several handlers, the limit applies to all of them
+/// combined.
+///
+/// By default, requests are rejected immediately when the limit is reached, with a `Retry-After`
+/// of one second.
+///
+/// [`with_concurrency_limit`]: fn.with_concurrency_limit.html
+#[derive(Clone)]
+pub struct ConcurrencyLimit {
+    max_concurrency: usize,
+    max_queued: usize,
+    max_wait: Duration,
+    retry_after: Duration,
+    state: Arc<Mutex<State>>,
+}
+
+impl ConcurrencyLimit {
+    /// Creates a `ConcurrencyLimit` which allows at most `max_concurrency` requests to be handled
+    /// at once.
+    pub fn new(max_concurrency: usize) -> Self {
+        ConcurrencyLimit {
+            max_concurrency,
+            max_queued: 0,
+            max_wait: Duration::from_secs(0),
+            retry_after: Duration::from_secs(1),
+            state: Arc::new(Mutex::new(State::default())),
+        }
+    }
+
+    /// Allows up to `max_queued` requests to wait for up to `max_wait` for a slot, when the limit
+    /// has been reached. Requests are admitted in the order they arrived.
+    ///
+    /// Requests which arrive when the queue is full, or which wait for longer than `max_wait`, are
+    /// rejected.
+    pub fn queue(mut self, max_queued: usize, max_wait: Duration) -> Self {
+        self.max_queued = max_queued;
+        self.max_wait = max_wait;
+        self
+    }
+
+    /// Sets the value of the `Retry-After` header sent with rejected requests.
+    ///
+    /// The header is sent in whole seconds, so the duration is rounded up, to at least one second.
+    pub fn retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// Returns the maximum number of requests which may be handled at once.
+    pub fn max_concurrency(&self) -> usize {
+        self.max_concurrency
+    }
+
+    /// Returns the number of requests which are currently being handled.
+    ///
+    /// A request is counted until its response body has been fully sent.
+    pub fn in_flight(&self) -> usize {
+        self.state.lock().unwrap().in_flight
+    }
+
+    /// Returns the number of requests which are currently waiting for a slot.
+    pub fn queued(&self) -> usize {
+        self.state.lock().unwrap().waiting.len()
+    }
+
+    fn acquire(&self) -> Acquire {
+        Acquire {
+            limit: self.clone(),
+            id: None,
+            delay: None,
+        }
+    }
+
+    fn rejection(&self) -> http::Response<BodyStream> {
+        let mut resp =
+            http::Response::new(Bytes::from_static(b"server is overloaded").into_stream());
+        *resp.status_mut() = http::StatusCode::SERVICE_UNAVAILABLE;
+        let rounded_up =
+            self.retry_after.as_secs() + u64::from(self.retry_after.subsec_nanos() > 0);
+        resp.headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(rounded_up.max(1)));
+        resp
+    }
+}
+
+// Holds a slot, releasing it when dropped.
+struct Permit {
+    state: Arc<Mutex<State>>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap();
+        state.in_flight -= 1;
+        state.notify_front();
+    }
+}
+
+// A future which resolves to a `Permit` once a slot is available, or to `None` if the request
+// should be rejected.
+struct Acquire {
+    limit: ConcurrencyLimit,
+    // Set once this request has joined the queue.
+    id: Option<u64>,
+    delay: Option<Delay>,
+}
+
+impl Future for Acquire {
+    type Item = Option<Permit>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Permit>, Error> {
+        let limit = &self.limit;
+        let mut state = limit.state.lock().unwrap();
+
+        let is_next = match (self.id, state.waiting.front()) {
+            (_, None) => true,
+            (Some(id), Some(&(front, _))) => id == front,
+            (None, Some(_)) => false,
+        };
+        if is_next && state.in_flight < limit.max_concurrency {
+            state.in_flight += 1;
+            if self.id.take().is_some() {
+                state.waiting.pop_front();
+                // There may be more than one free slot.
+                state.notify_front();
+            }
+            return Ok(Async::Ready(Some(Permit {
+                state: limit.state.clone(),
+            })));
+        }
+
+        match self.id {
+            Some(id) => {
+                if let Some(entry) = state.waiting.iter_mut().find(|entry| entry.0 == id) {
+                    entry.1 = task::current();
+                }
+            }
+            None => {
+                if state.waiting.len() >= limit.max_queued {
+                    return Ok(Async::Ready(None));
+                }
+                let id = state.next_id;
+                state.next_id += 1;
+                state.waiting.push_back((id, task::current()));
+                self.id = Some(id);
+                self.delay = Some(Delay::new(limit.max_wait));
+            }
+        }
+        drop(state);
+
+        if let Some(ref mut delay) = self.delay {
+            if let Async::Ready(()) = delay.poll()? {
+                self.leave_queue();
+                return Ok(Async::Ready(None));
+            }
+        }
+        Ok(Async::NotReady)
+    }
+}
+
+impl Acquire {
+    fn leave_queue(&mut self) {
+        if let Some(id) = self.id.take() {
+            let mut state = self.limit.state.lock().unwrap();
+            let was_front = state.waiting.front().map(|entry| entry.0) == Some(id);
+            state.waiting.retain(|entry| entry.0 != id);
+            if was_front {
+                state.notify_front();
+            }
+        }
+    }
+}
+
+impl Drop for Acquire {
+    fn drop(&mut self) {
+        self.leave_queue();
+    }
+}
+
+// Wraps a response body, holding the request's slot until the body has been fully sent (or has
+// been dropped, if the client disconnects).
+struct PermitBody {
+    inner: BodyStream,
+    permit: Option<Permit>,
+}
+
+impl Stream for PermitBody {
+    type Item = Bytes;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Bytes>, Error> {
+        let polled = self.inner.poll();
+        match polled {
+            Ok(Async::Ready(None)) | Err(_) => self.permit = None,
+            _ => {}
+        }
+        polled
+    }
+}
+
+/// Middleware which limits the number of requests that are handled at once.
+///
+/// When the limit set by the provided [`ConcurrencyLimit`] is reached, new requests either wait in
+/// a bounded queue, or are rejected with a `503 Service Unavailable` response which includes a
+/// `Retry-After` header. Shedding load in this way keeps latency predictable for the requests
+/// which are handled, when the server receives more work than it can handle.
+///
+/// A request occupies a slot from when it is admitted until its response body has been fully sent.
+///
+/// [`ConcurrencyLimit`]: struct.ConcurrencyLimit.html
+///
+/// # Example
+///
+/// ```no_run
+/// extern crate aitch;
+/// extern crate http;
+///
+/// use std::time::Duration;
+///
+/// use aitch::middlewares::{self, ConcurrencyLimit};
+/// use aitch::servers::hyper::Server;
+/// use aitch::{Responder, ResponseBuilder, Result};
+/// use http::Request;
+///
+/// fn handler(_req: Request<()>, mut resp: ResponseBuilder) -> impl Responder {
+///     resp.body("Hello, world!".to_owned())
+/// }
+///
+/// fn main() -> Result<()> {
+///     let limit = ConcurrencyLimit::new(64).queue(256, Duration::from_millis(500));
+///     let wrapped = middlewares::with_concurrency_limit(limit.clone(), handler);
+///
+///     // `limit.in_flight()` and `limit.queued()` can be reported as metrics.
+///
+///     let addr = "127.0.0.1:3000".parse()?;
+///     Server::new(addr, wrapped)?.run()
+/// }
+/// ```
+pub fn with_concurrency_limit<B: Body>(
+    limit: ConcurrencyLimit,
+    handler: impl Handler<B>,
+) -> impl Handler<B> {
+    let handler = Arc::new(handler);
+    move |req: http::Request<B>, resp: ResponseBuilder| -> BoxedResponse {
+        let handler = handler.clone();
+        let limit = limit.clone();
+        let fut = limit.acquire().and_then(move |permit| -> BoxedResponse {
+            let permit = match permit {
+                Some(permit) => permit,
+                None => return Box::new(future::ok(limit.rejection())),
+            };
+            let fut = handler.handle(req, resp).into_response().map(move |resp| {
+                resp.map(|body| {
+                    let body = PermitBody {
+                        inner: body,
+                        permit: Some(permit),
+                    };
+                    Box::new(body) as BodyStream
+                })
+            });
+            Box::new(fut)
+        });
+        Box::new(fut)
+    }
+}
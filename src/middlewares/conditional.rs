@@ -0,0 +1,427 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use futures::{future, stream, Future, Stream};
+use http;
+use http::header::{self, HeaderMap, HeaderValue};
+use httpdate;
+use sha2::{Digest, Sha256};
+
+use {Body, BodyStream, BoxedResponse, Handler, Responder, ResponseBuilder};
+
+use super::buffer::{Buffer, Buffered};
+
+// The headers which are copied from a `200 OK` response to the `304 Not Modified` response which
+// replaces it.
+const NOT_MODIFIED_HEADERS: &[header::HeaderName] = &[
+    header::CACHE_CONTROL,
+    header::CONTENT_LOCATION,
+    header::DATE,
+    header::ETAG,
+    header::EXPIRES,
+    header::LAST_MODIFIED,
+    header::VARY,
+];
+
+// Splits a list of entity-tags (e.g. from an `If-None-Match` header) into the individual tags,
+// including any `W/` prefix. Entity-tags may contain commas, so the list can't simply be split.
+fn parse_etags(value: &str) -> Vec<&str> {
+    let mut etags = Vec::new();
+    let mut rest = value;
+    loop {
+        rest = rest.trim_start_matches(&[',', ' ', '\t'][..]);
+        if rest.is_empty() {
+            return etags;
+        }
+        let start = if rest.starts_with("W/") { 2 } else { 0 };
+        if !rest[start..].starts_with('"') {
+            // Not a valid entity-tag: skip to the next element.
+            rest = rest.find(',').map(|i| &rest[i..]).unwrap_or("");
+            continue;
+        }
+        match rest[start + 1..].find('"') {
+            Some(end) => {
+                let len = start + end + 2;
+                etags.push(&rest[..len]);
+                rest = &rest[len..];
+            }
+            None => return etags,
+        }
+    }
+}
+
+fn opaque_tag(etag: &str) -> &str {
+    etag.trim_start_matches("W/")
+}
+
+fn strong_match(a: &str, b: &str) -> bool {
+    !a.starts_with("W/") && !b.starts_with("W/") && a == b
+}
+
+fn weak_match(a: &str, b: &str) -> bool {
+    opaque_tag(a) == opaque_tag(b)
+}
+
+// HTTP dates have a resolution of one second, so modification times must be truncated before they
+// are compared with them.
+fn truncate(time: SystemTime) -> SystemTime {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => UNIX_EPOCH + Duration::from_secs(duration.as_secs()),
+        Err(_) => time,
+    }
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &header::HeaderName) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+fn header_date(headers: &HeaderMap, name: &header::HeaderName) -> Option<SystemTime> {
+    header_str(headers, name).and_then(|value| httpdate::parse_http_date(value).ok())
+}
+
+/// The preconditions of a conditional request (RFC 7232).
+///
+/// For `GET` and `HEAD` requests, the [`with_conditional_requests`] middleware evaluates the
+/// preconditions against the handler's response. For other methods, the preconditions must be
+/// evaluated before the request's changes are made, so the middleware instead stores the
+/// `Preconditions` in the extensions of the request. Handlers should call [`evaluate`] with the
+/// current validators of the resource, and respond with the returned status if there is one.
+///
+/// [`with_conditional_requests`]: fn.with_conditional_requests.html
+/// [`evaluate`]: #method.evaluate
+#[derive(Clone, Debug, PartialEq)]
+pub struct Preconditions {
+    method: http::Method,
+    if_match: Option<String>,
+    if_none_match: Option<String>,
+    if_modified_since: Option<SystemTime>,
+    if_unmodified_since: Option<SystemTime>,
+}
+
+impl Preconditions {
+    /// Reads the preconditions from the headers of a request.
+    ///
+    /// Dates which cannot be parsed are ignored, as required by RFC 7232.
+    pub fn from_request(method: &http::Method, headers: &HeaderMap) -> Self {
+        Preconditions {
+            method: method.clone(),
+            if_match: header_str(headers, &header::IF_MATCH).map(|value| value.to_owned()),
+            if_none_match: header_str(headers, &header::IF_NONE_MATCH)
+                .map(|value| value.to_owned()),
+            if_modified_since: header_date(headers, &header::IF_MODIFIED_SINCE),
+            if_unmodified_since: header_date(headers, &header::IF_UNMODIFIED_SINCE),
+        }
+    }
+
+    /// Returns `true` if the request has no preconditions.
+    pub fn is_empty(&self) -> bool {
+        self.if_match.is_none()
+            && self.if_none_match.is_none()
+            && self.if_modified_since.is_none()
+            && self.if_unmodified_since.is_none()
+    }
+
+    /// Evaluates the preconditions against the current validators of an existing resource.
+    ///
+    /// Returns `None` if the request should be performed, or the status with which it should be
+    /// rejected: `304 Not Modified` (for `GET` and `HEAD` requests) or `412 Precondition Failed`.
+    pub fn evaluate(
+        &self,
+        etag: Option<&str>,
+        last_modified: Option<SystemTime>,
+    ) -> Option<http::StatusCode> {
+        let last_modified = last_modified.map(truncate);
+
+        if let Some(ref if_match) = self.if_match {
+            let matched = if_match.trim() == "*"
+                || etag.is_some_and(|etag| {
+                    parse_etags(if_match)
+                        .into_iter()
+                        .any(|tag| strong_match(tag, etag))
+                });
+            if !matched {
+                return Some(http::StatusCode::PRECONDITION_FAILED);
+            }
+        } else if let (Some(since), Some(modified)) = (self.if_unmodified_since, last_modified) {
+            if modified > since {
+                return Some(http::StatusCode::PRECONDITION_FAILED);
+            }
+        }
+
+        let is_safe = self.method == http::Method::GET || self.method == http::Method::HEAD;
+        if let Some(ref if_none_match) = self.if_none_match {
+            let matched = if_none_match.trim() == "*"
+                || etag.is_some_and(|etag| {
+                    parse_etags(if_none_match)
+                        .into_iter()
+                        .any(|tag| weak_match(tag, etag))
+                });
+            if matched {
+                return Some(if is_safe {
+                    http::StatusCode::NOT_MODIFIED
+                } else {
+                    http::StatusCode::PRECONDITION_FAILED
+                });
+            }
+        } else if let (true, Some(since), Some(modified)) =
+            (is_safe, self.if_modified_since, last_modified)
+        {
+            if modified <= since {
+                return Some(http::StatusCode::NOT_MODIFIED);
+            }
+        }
+
+        None
+    }
+}
+
+/// Configuration for the [`with_conditional_requests`] middleware.
+///
+/// By default, response bodies of up to 1 MiB are buffered to compute their `ETag`.
+///
+/// [`with_conditional_requests`]: fn.with_conditional_requests.html
+#[derive(Clone, Debug)]
+pub struct ConditionalRequests {
+    max_size: usize,
+}
+
+impl Default for ConditionalRequests {
+    fn default() -> Self {
+        ConditionalRequests {
+            max_size: 1024 * 1024,
+        }
+    }
+}
+
+impl ConditionalRequests {
+    /// Creates a `ConditionalRequests` with the default configuration.
+    pub fn new() -> Self {
+        ConditionalRequests::default()
+    }
+
+    /// Sets the maximum size of a response body which is buffered to compute its `ETag`.
+    ///
+    /// Responses with larger bodies are sent without an `ETag`, unless the handler sets one.
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+}
+
+fn compute_etag(body: &[u8]) -> HeaderValue {
+    let digest = Sha256::digest(body);
+    let hex: String = digest[..16].iter().map(|b| format!("{:02x}", b)).collect();
+    HeaderValue::from_str(&format!("\"{}\"", hex)).expect("hex is a valid header value")
+}
+
+// Evaluates the preconditions against a response, replacing it if they fail.
+fn evaluate(
+    preconditions: &Preconditions,
+    resp: http::Response<BodyStream>,
+) -> http::Response<BodyStream> {
+    let status = {
+        let etag = header_str(resp.headers(), &header::ETAG);
+        let last_modified = header_date(resp.headers(), &header::LAST_MODIFIED);
+        match preconditions.evaluate(etag, last_modified) {
+            Some(status) => status,
+            None => return resp,
+        }
+    };
+
+    let mut replaced = http::Response::new(Bytes::new().into_stream());
+    *replaced.status_mut() = status;
+    if status == http::StatusCode::NOT_MODIFIED {
+        for name in NOT_MODIFIED_HEADERS {
+            for value in resp.headers().get_all(name) {
+                replaced.headers_mut().append(name, value.clone());
+            }
+        }
+    }
+    replaced
+}
+
+/// Middleware which adds an `ETag` to responses, and handles conditional requests.
+///
+/// For `GET` and `HEAD` requests which result in a `200 OK` response, the response body is buffered
+/// to compute a strong `ETag`, unless the handler has already set one. Bodies larger than the
+/// maximum size set on the provided [`ConditionalRequests`] are sent without an `ETag`.
+///
+/// The request's `If-Match`, `If-None-Match`, `If-Modified-Since` and `If-Unmodified-Since` headers
+/// are then evaluated against the response's `ETag` and `Last-Modified` headers. If they fail, the
+/// response is replaced with `304 Not Modified` or `412 Precondition Failed`, as appropriate.
+///
+/// For other methods, the preconditions must be checked before the handler makes any changes, so
+/// are stored in the request's extensions as [`Preconditions`] for the handler to evaluate.
+///
+/// When used with [`with_compression`], this middleware should be wrapped by it, so that the
+/// `ETag` is computed from the uncompressed body.
+///
+/// [`ConditionalRequests`]: struct.ConditionalRequests.html
+/// [`Preconditions`]: struct.Preconditions.html
+/// [`with_compression`]: fn.with_compression.html
+///
+/// # Example
+///
+/// ```no_run
+/// extern crate aitch;
+/// extern crate http;
+///
+/// use aitch::middlewares::{self, ConditionalRequests};
+/// use aitch::servers::hyper::Server;
+/// use aitch::{Responder, ResponseBuilder, Result};
+/// use http::Request;
+///
+/// fn handler(_req: Request<()>, mut resp: ResponseBuilder) -> impl Responder {
+///     resp.header(http::header::CONTENT_TYPE, "application/json")
+///         .body(r#"{"products": []}"#.to_owned())
+/// }
+///
+/// fn main() -> Result<()> {
+///     let conditional = ConditionalRequests::new().max_size(64 * 1024);
+///     let wrapped = middlewares::with_conditional_requests(conditional, handler);
+///
+///     let addr = "127.0.0.1:3000".parse()?;
+///     Server::new(addr, wrapped)?.run()
+/// }
+/// ```
+pub fn with_conditional_requests<B: Body>(
+    conditional: ConditionalRequests,
+    handler: impl Handler<B>,
+) -> impl Handler<B> {
+    move |mut req: http::Request<B>, resp: ResponseBuilder| -> BoxedResponse {
+        let preconditions = Preconditions::from_request(req.method(), req.headers());
+        let is_safe = req.method() == http::Method::GET || req.method() == http::Method::HEAD;
+        if !is_safe {
+            if !preconditions.is_empty() {
+                req.extensions_mut().insert(preconditions);
+            }
+            return handler.handle(req, resp).into_response();
+        }
+
+        let max_size = conditional.max_size;
+        let fut =
+            handler
+                .handle(req, resp)
+                .into_response()
+                .and_then(move |resp| -> BoxedResponse {
+                    if resp.status() != http::StatusCode::OK {
+                        return Box::new(future::ok(resp));
+                    }
+                    if resp.headers().contains_key(header::ETAG) {
+                        return Box::new(future::ok(evaluate(&preconditions, resp)));
+                    }
+
+                    let (mut parts, body) = resp.into_parts();
+                    let fut = Buffer::new(body, max_size).map(move |buffered| {
+                        let body = match buffered {
+                            Buffered::Complete(body) => body,
+                            Buffered::TooLarge(start, rest) => {
+                                let body =
+                                    Box::new(stream::once(Ok(start)).chain(rest)) as BodyStream;
+                                return http::Response::from_parts(parts, body);
+                            }
+                        };
+                        parts.headers.insert(header::ETAG, compute_etag(&body));
+                        let resp = http::Response::from_parts(parts, body.into_stream());
+                        evaluate(&preconditions, resp)
+                    });
+                    Box::new(fut)
+                });
+        Box::new(fut)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use http::{HeaderMap, Method, StatusCode};
+
+    use super::{parse_etags, Preconditions};
+
+    #[test]
+    fn etag_lists() {
+        assert_eq!(parse_etags(r#""a""#), vec![r#""a""#]);
+        assert_eq!(
+            parse_etags(r#""a", W/"b",  "c,d" ,"e""#),
+            vec![r#""a""#, r#"W/"b""#, r#""c,d""#, r#""e""#]
+        );
+        assert_eq!(parse_etags(r#"bad, "a""#), vec![r#""a""#]);
+        assert_eq!(parse_etags(r#""a", "unterminated"#), vec![r#""a""#]);
+        assert!(parse_etags("").is_empty());
+    }
+
+    fn preconditions(method: Method, headers: &[(&'static str, &str)]) -> Preconditions {
+        let mut map = HeaderMap::new();
+        for &(name, value) in headers {
+            map.insert(name, value.parse().unwrap());
+        }
+        Preconditions::from_request(&method, &map)
+    }
+
+    #[test]
+    fn evaluation() {
+        let modified = UNIX_EPOCH + Duration::from_millis(784_111_777_500);
+        let before = "Sun, 06 Nov 1994 08:49:36 GMT";
+        let at = "Sun, 06 Nov 1994 08:49:37 GMT";
+        let etag = Some(r#""abc""#);
+
+        let get = |headers: &[(&'static str, &str)]| {
+            preconditions(Method::GET, headers).evaluate(etag, Some(modified))
+        };
+        let put = |headers: &[(&'static str, &str)]| {
+            preconditions(Method::PUT, headers).evaluate(etag, Some(modified))
+        };
+
+        assert_eq!(get(&[]), None);
+        assert_eq!(
+            get(&[("if-none-match", r#""x", W/"abc""#)]),
+            Some(StatusCode::NOT_MODIFIED)
+        );
+        assert_eq!(
+            get(&[("if-none-match", "*")]),
+            Some(StatusCode::NOT_MODIFIED)
+        );
+        assert_eq!(get(&[("if-none-match", r#""x""#)]), None);
+        assert_eq!(
+            put(&[("if-none-match", "*")]),
+            Some(StatusCode::PRECONDITION_FAILED)
+        );
+
+        // `If-Modified-Since` is ignored when `If-None-Match` is present.
+        assert_eq!(
+            get(&[("if-modified-since", at)]),
+            Some(StatusCode::NOT_MODIFIED)
+        );
+        assert_eq!(get(&[("if-modified-since", before)]), None);
+        assert_eq!(
+            get(&[("if-modified-since", at), ("if-none-match", r#""x""#)]),
+            None
+        );
+        assert_eq!(get(&[("if-modified-since", "yesterday")]), None);
+        assert_eq!(put(&[("if-modified-since", at)]), None);
+
+        // `If-Match` requires a strong match.
+        assert_eq!(put(&[("if-match", r#""abc""#)]), None);
+        assert_eq!(put(&[("if-match", "*")]), None);
+        assert_eq!(
+            put(&[("if-match", r#"W/"abc""#)]),
+            Some(StatusCode::PRECONDITION_FAILED)
+        );
+        assert_eq!(
+            preconditions(Method::PUT, &[("if-match", r#""abc""#)]).evaluate(None, None),
+            Some(StatusCode::PRECONDITION_FAILED)
+        );
+
+        // `If-Unmodified-Since` is ignored when `If-Match` is present.
+        assert_eq!(put(&[("if-unmodified-since", at)]), None);
+        assert_eq!(
+            put(&[("if-unmodified-since", before)]),
+            Some(StatusCode::PRECONDITION_FAILED)
+        );
+        assert_eq!(
+            put(&[("if-unmodified-since", before), ("if-match", "*")]),
+            None
+        );
+    }
+}
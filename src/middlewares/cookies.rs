@@ -0,0 +1,158 @@
+use futures::Future;
+use http;
+use http::header;
+
+use cookies::{CookieJar, InvalidCookie};
+#[cfg(feature = "secure-cookies")]
+use cookies::{CookieKeys, PrivateCookies, SignedCookies};
+use {Body, BoxedResponse, Handler, Responder, ResponseBuilder};
+
+fn add_set_cookie_headers(
+    jar: &CookieJar,
+    headers: &mut http::HeaderMap,
+) -> Result<(), InvalidCookie> {
+    for cookie in jar.delta() {
+        headers.append(header::SET_COOKIE, cookie.to_header_value()?);
+    }
+    Ok(())
+}
+
+/// Middleware which provides a [`CookieJar`] to the wrapped handler.
+///
+/// The cookies sent by the client are parsed into a [`CookieJar`], which is stored in the
+/// extensions of the request. Once the handler has responded, a `Set-Cookie` header is added to
+/// the response for each cookie which the handler added to or removed from the jar. Cookies which
+/// were not changed are not sent. If any of these cookies is invalid (see [`Cookie::validate`]),
+/// the middleware fails with an [`InvalidCookie`] error, rather than silently not setting it.
+///
+/// See the [`cookies`] module for more details.
+///
+/// [`CookieJar`]: ../cookies/struct.CookieJar.html
+/// [`Cookie::validate`]: ../cookies/struct.Cookie.html#method.validate
+/// [`InvalidCookie`]: ../cookies/struct.InvalidCookie.html
+/// [`cookies`]: ../cookies/index.html
+///
+/// # Example
+///
+/// ```no_run
+/// extern crate aitch;
+/// extern crate http;
+///
+/// use aitch::cookies::{Cookie, CookieJar};
+/// use aitch::servers::hyper::Server;
+/// use aitch::{middlewares, Responder, ResponseBuilder, Result};
+/// use http::Request;
+///
+/// fn handler(req: Request<()>, mut resp: ResponseBuilder) -> impl Responder {
+///     let jar = req.extensions().get::<CookieJar>().unwrap();
+///     let message = match jar.get("name") {
+///         Some(cookie) => format!("Welcome back, {}!", cookie.value()),
+///         None => {
+///             jar.add(Cookie::new("name", "stranger").path("/"));
+///             "Hello, stranger!".to_owned()
+///         }
+///     };
+///     resp.body(message)
+/// }
+///
+/// fn main() -> Result<()> {
+///     let wrapped = middlewares::with_cookies(handler);
+///
+///     let addr = "127.0.0.1:3000".parse()?;
+///     Server::new(addr, wrapped)?.run()
+/// }
+/// ```
+pub fn with_cookies<B: Body>(handler: impl Handler<B>) -> impl Handler<B> {
+    move |mut req: http::Request<B>, resp: ResponseBuilder| -> BoxedResponse {
+        let jar = CookieJar::from_headers(req.headers());
+        req.extensions_mut().insert(jar.clone());
+
+        let fut = handler
+            .handle(req, resp)
+            .into_response()
+            .and_then(move |mut resp| {
+                add_set_cookie_headers(&jar, resp.headers_mut())?;
+                Ok(resp)
+            });
+        Box::new(fut)
+    }
+}
+
+/// Middleware which provides [`SignedCookies`] and [`PrivateCookies`] to the wrapped handler.
+///
+/// Both are stored in the extensions of each request, and use the provided [`CookieKeys`] to sign
+/// and encrypt cookies. If the request already has a [`CookieJar`] (i.e. this middleware is wrapped
+/// by [`with_cookies`]), they share it. Otherwise, this middleware creates a jar and sends any
+/// changes to it in the response, in the same way as [`with_cookies`].
+///
+/// This middleware requires the `secure-cookies` feature, which is enabled by default.
+///
+/// [`SignedCookies`]: ../cookies/struct.SignedCookies.html
+/// [`PrivateCookies`]: ../cookies/struct.PrivateCookies.html
+/// [`CookieKeys`]: ../cookies/struct.CookieKeys.html
+/// [`CookieJar`]: ../cookies/struct.CookieJar.html
+/// [`with_cookies`]: fn.with_cookies.html
+///
+/// # Example
+///
+/// ```no_run
+/// extern crate aitch;
+/// extern crate http;
+///
+/// use std::env;
+///
+/// use aitch::cookies::{Cookie, CookieKeys, Key, SignedCookies};
+/// use aitch::servers::hyper::Server;
+/// use aitch::{middlewares, Responder, ResponseBuilder, Result};
+/// use http::Request;
+///
+/// fn handler(req: Request<()>, mut resp: ResponseBuilder) -> impl Responder {
+///     let signed = req.extensions().get::<SignedCookies>().unwrap();
+///     let message = match signed.get("user") {
+///         Some(cookie) => format!("Hello, {}!", cookie.value()),
+///         None => {
+///             signed.add(Cookie::new("user", "alice").path("/").http_only(true));
+///             "Logged in".to_owned()
+///         }
+///     };
+///     resp.body(message)
+/// }
+///
+/// fn main() -> Result<()> {
+///     let secret = env::var("COOKIE_SECRET")?;
+///     let keys = CookieKeys::new(Key::derive_from(secret.as_bytes()));
+///     let wrapped = middlewares::with_cookie_keys(keys, handler);
+///
+///     let addr = "127.0.0.1:3000".parse()?;
+///     Server::new(addr, wrapped)?.run()
+/// }
+/// ```
+#[cfg(feature = "secure-cookies")]
+pub fn with_cookie_keys<B: Body>(keys: CookieKeys, handler: impl Handler<B>) -> impl Handler<B> {
+    move |mut req: http::Request<B>, resp: ResponseBuilder| -> BoxedResponse {
+        let existing = req.extensions().get::<CookieJar>().cloned();
+        let (jar, owned) = match existing {
+            Some(jar) => (jar, false),
+            None => {
+                let jar = CookieJar::from_headers(req.headers());
+                req.extensions_mut().insert(jar.clone());
+                (jar, true)
+            }
+        };
+        req.extensions_mut()
+            .insert(SignedCookies::new(jar.clone(), keys.clone()));
+        req.extensions_mut()
+            .insert(PrivateCookies::new(jar.clone(), keys.clone()));
+
+        let fut = handler
+            .handle(req, resp)
+            .into_response()
+            .and_then(move |mut resp| {
+                if owned {
+                    add_set_cookie_headers(&jar, resp.headers_mut())?;
+                }
+                Ok(resp)
+            });
+        Box::new(fut)
+    }
+}
@@ -0,0 +1,378 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::Future;
+use http;
+use http::header::{self, HeaderMap, HeaderName, HeaderValue};
+
+use {Body, BoxedResponse, Handler, Responder, ResponseBuilder};
+
+#[derive(Clone)]
+enum Origins {
+    Any,
+    List(Vec<String>),
+    Predicate(Arc<Fn(&str) -> bool + Send + Sync>),
+}
+
+/// Configuration for the [`with_cors`] middleware.
+///
+/// By default, requests from any origin are allowed, using the `GET`, `HEAD` and `POST` methods.
+/// No request headers (other than the [CORS-safelisted request headers]) are allowed, no response
+/// headers are exposed, and credentials are not allowed.
+///
+/// [`with_cors`]: fn.with_cors.html
+/// [CORS-safelisted request headers]: https://fetch.spec.whatwg.org/#cors-safelisted-request-header
+#[derive(Clone)]
+pub struct Cors {
+    origins: Origins,
+    methods: Vec<http::Method>,
+    headers: Option<Vec<HeaderName>>,
+    expose_headers: Vec<HeaderName>,
+    credentials: bool,
+    max_age: Option<Duration>,
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Cors {
+            origins: Origins::Any,
+            methods: vec![http::Method::GET, http::Method::HEAD, http::Method::POST],
+            headers: Some(Vec::new()),
+            expose_headers: Vec::new(),
+            credentials: false,
+            max_age: None,
+        }
+    }
+}
+
+impl Cors {
+    /// Creates a `Cors` with the default settings.
+    pub fn new() -> Self {
+        Cors::default()
+    }
+
+    /// Allows requests from any origin. This is the default.
+    pub fn allow_any_origin(mut self) -> Self {
+        self.origins = Origins::Any;
+        self
+    }
+
+    /// Allows requests from the given origin (e.g. `https://example.com`).
+    ///
+    /// This can be called multiple times to allow a list of origins. Origins are compared exactly,
+    /// so should include the scheme, and the port if it isn't the default.
+    pub fn allow_origin<S: Into<String>>(mut self, origin: S) -> Self {
+        match self.origins {
+            Origins::List(ref mut origins) => origins.push(origin.into()),
+            _ => self.origins = Origins::List(vec![origin.into()]),
+        }
+        self
+    }
+
+    /// Allows requests from each of the given origins.
+    ///
+    /// See [`allow_origin`] for details.
+    ///
+    /// [`allow_origin`]: #method.allow_origin
+    pub fn allow_origins<I, S>(self, origins: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        origins
+            .into_iter()
+            .fold(self, |cors, origin| cors.allow_origin(origin))
+    }
+
+    /// Allows requests from any origin for which the predicate returns `true`.
+    ///
+    /// This replaces any origins allowed by [`allow_origin`].
+    ///
+    /// [`allow_origin`]: #method.allow_origin
+    pub fn allow_origin_fn<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.origins = Origins::Predicate(Arc::new(predicate));
+        self
+    }
+
+    /// Sets the methods which may be used in cross-origin requests.
+    pub fn allow_methods<I>(mut self, methods: I) -> Self
+    where
+        I: IntoIterator<Item = http::Method>,
+    {
+        self.methods = methods.into_iter().collect();
+        self
+    }
+
+    /// Sets the request headers which may be used in cross-origin requests.
+    pub fn allow_headers<I>(mut self, headers: I) -> Self
+    where
+        I: IntoIterator<Item = HeaderName>,
+    {
+        self.headers = Some(headers.into_iter().collect());
+        self
+    }
+
+    /// Allows any request headers to be used in cross-origin requests.
+    pub fn allow_any_header(mut self) -> Self {
+        self.headers = None;
+        self
+    }
+
+    /// Sets the response headers which the browser exposes to the requesting script.
+    pub fn expose_headers<I>(mut self, headers: I) -> Self
+    where
+        I: IntoIterator<Item = HeaderName>,
+    {
+        self.expose_headers = headers.into_iter().collect();
+        self
+    }
+
+    /// Sets whether cross-origin requests may include credentials (cookies, HTTP authentication).
+    ///
+    /// Credentials can only be allowed together with an explicit list of origins (see
+    /// [`allow_origin`]) or a predicate (see [`allow_origin_fn`]): allowing them from any origin
+    /// would let every site make authenticated requests on behalf of the user, so [`with_cors`]
+    /// panics if it is given such a configuration.
+    ///
+    /// [`allow_origin`]: #method.allow_origin
+    /// [`allow_origin_fn`]: #method.allow_origin_fn
+    /// [`with_cors`]: fn.with_cors.html
+    pub fn allow_credentials(mut self, credentials: bool) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    /// Sets how long browsers may cache the result of a preflight request.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    fn is_origin_allowed(&self, origin: &HeaderValue) -> bool {
+        let origin = match origin.to_str() {
+            Ok(origin) => origin,
+            Err(_) => return false,
+        };
+        match self.origins {
+            Origins::Any => true,
+            Origins::List(ref origins) => origins.iter().any(|allowed| allowed == origin),
+            Origins::Predicate(ref predicate) => predicate(origin),
+        }
+    }
+
+    fn is_method_allowed(&self, method: Option<&HeaderValue>) -> bool {
+        method.iter().any(|method| {
+            self.methods
+                .iter()
+                .any(|allowed| allowed.as_str().as_bytes() == method.as_bytes())
+        })
+    }
+
+    fn are_headers_allowed(&self, requested: Option<&Vec<HeaderName>>) -> bool {
+        match (requested, &self.headers) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(requested), Some(allowed)) => {
+                requested.iter().all(|header| allowed.contains(header))
+            }
+        }
+    }
+
+    fn add_origin_headers(&self, headers: &mut HeaderMap, origin: &HeaderValue) {
+        if let Origins::Any = self.origins {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_ORIGIN,
+                HeaderValue::from_static("*"),
+            );
+            return;
+        }
+
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+        headers.append(header::VARY, HeaderValue::from_static("origin"));
+        if self.credentials {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+    }
+
+    fn preflight(&self, req: &http::request::Parts, origin: &HeaderValue) -> BoxedResponse {
+        let method = req.headers.get(header::ACCESS_CONTROL_REQUEST_METHOD);
+        let requested_headers = requested_headers(&req.headers);
+        let allowed = self.is_origin_allowed(origin)
+            && self.is_method_allowed(method)
+            && self.are_headers_allowed(requested_headers.as_ref());
+        if !allowed {
+            return http::Response::builder()
+                .status(http::StatusCode::FORBIDDEN)
+                .body(())
+                .into_response();
+        }
+
+        let mut headers = HeaderMap::new();
+        self.add_origin_headers(&mut headers, origin);
+        headers.append(
+            header::VARY,
+            HeaderValue::from_static(
+                "access-control-request-method, access-control-request-headers",
+            ),
+        );
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_METHODS,
+            join(self.methods.iter().map(http::Method::as_str)),
+        );
+        // Either all requested headers are allowed, or none were requested: echoing them back is
+        // equivalent to listing every allowed header, and avoids sending a long list needlessly.
+        let requested_headers = requested_headers.unwrap_or_default();
+        if !requested_headers.is_empty() {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_HEADERS,
+                join(requested_headers.iter().map(HeaderName::as_str)),
+            );
+        }
+        if let Some(max_age) = self.max_age {
+            headers.insert(
+                header::ACCESS_CONTROL_MAX_AGE,
+                HeaderValue::from(max_age.as_secs()),
+            );
+        }
+
+        let mut resp = http::Response::new(());
+        *resp.status_mut() = http::StatusCode::NO_CONTENT;
+        *resp.headers_mut() = headers;
+        Ok::<_, http::Error>(resp).into_response()
+    }
+}
+
+// Returns the headers listed in the `Access-Control-Request-Headers` header, or `None` if any of
+// them are invalid.
+fn requested_headers(headers: &HeaderMap) -> Option<Vec<HeaderName>> {
+    let mut requested = Vec::new();
+    for value in headers.get_all(header::ACCESS_CONTROL_REQUEST_HEADERS) {
+        for name in value.to_str().ok()?.split(',') {
+            let name = name.trim();
+            if !name.is_empty() {
+                requested.push(HeaderName::from_bytes(name.as_bytes()).ok()?);
+            }
+        }
+    }
+    Some(requested)
+}
+
+fn join<'a, I: Iterator<Item = &'a str>>(values: I) -> HeaderValue {
+    let joined = values.collect::<Vec<_>>().join(", ");
+    HeaderValue::from_str(&joined).expect("method and header names are valid header values")
+}
+
+/// Middleware which implements [Cross-Origin Resource Sharing] (CORS).
+///
+/// This middleware allows browsers to make requests to the wrapped handler from other origins, as
+/// configured by the provided [`Cors`]:
+///
+///  - Preflight requests (`OPTIONS` requests with an `Access-Control-Request-Method` header) are
+///    answered directly, without calling the inner handler. If the origin, method or headers of the
+///    request are not allowed, a `403 Forbidden` response is returned.
+///  - Other requests with an `Origin` header are passed to the inner handler, and the appropriate
+///    CORS headers are added to its response if the origin is allowed.
+///  - Requests without an `Origin` header are passed to the inner handler unchanged.
+///
+/// As preflight requests never reach the inner handler, it is not necessary to register `OPTIONS`
+/// handlers when wrapping a [`SimpleRouter`].
+///
+/// [Cross-Origin Resource Sharing]: https://developer.mozilla.org/en-US/docs/Web/HTTP/CORS
+/// [`Cors`]: struct.Cors.html
+/// [`SimpleRouter`]: struct.SimpleRouter.html
+///
+/// # Panics
+///
+/// Panics if the [`Cors`] allows credentials from any origin.
+///
+/// # Example
+///
+/// ```no_run
+/// extern crate aitch;
+/// extern crate http;
+///
+/// use std::time::Duration;
+///
+/// use aitch::servers::hyper::Server;
+/// use aitch::{middlewares, Responder, ResponseBuilder, Result};
+/// use http::Request;
+///
+/// fn handler(_req: Request<()>, mut resp: ResponseBuilder) -> impl Responder {
+///     resp.body("Hello, world!".to_owned())
+/// }
+///
+/// fn main() -> Result<()> {
+///     let mut router = middlewares::SimpleRouter::new();
+///     router.register_handler("/", handler);
+///
+///     let cors = middlewares::Cors::new()
+///         .allow_origin("https://example.com")
+///         .allow_methods(vec![http::Method::GET, http::Method::PUT])
+///         .allow_headers(vec![http::header::CONTENT_TYPE])
+///         .allow_credentials(true)
+///         .max_age(Duration::from_secs(3600));
+///     let wrapped = middlewares::with_cors(cors, router);
+///
+///     let addr = "127.0.0.1:3000".parse()?;
+///     Server::new(addr, wrapped)?.run()
+/// }
+/// ```
+pub fn with_cors<B: Body>(cors: Cors, handler: impl Handler<B>) -> impl Handler<B> {
+    if let Origins::Any = cors.origins {
+        assert!(
+            !cors.credentials,
+            "Cors: credentials cannot be allowed from any origin"
+        );
+    }
+
+    move |req: http::Request<B>, resp: ResponseBuilder| -> BoxedResponse {
+        let origin = match req.headers().get(header::ORIGIN) {
+            Some(origin) => origin.clone(),
+            None => return handler.handle(req, resp).into_response(),
+        };
+
+        if req.method() == http::Method::OPTIONS
+            && req
+                .headers()
+                .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD)
+        {
+            let (parts, _) = req.into_parts();
+            return cors.preflight(&parts, &origin);
+        }
+
+        let mut headers = HeaderMap::new();
+        if cors.is_origin_allowed(&origin) {
+            cors.add_origin_headers(&mut headers, &origin);
+            if !cors.expose_headers.is_empty() {
+                headers.insert(
+                    header::ACCESS_CONTROL_EXPOSE_HEADERS,
+                    join(cors.expose_headers.iter().map(HeaderName::as_str)),
+                );
+            }
+        } else {
+            // The response varies by origin, even though it doesn't contain any CORS headers.
+            headers.insert(header::VARY, HeaderValue::from_static("origin"));
+        }
+
+        let fut = handler
+            .handle(req, resp)
+            .into_response()
+            .map(move |mut resp| {
+                for (name, value) in &headers {
+                    if name == header::VARY {
+                        resp.headers_mut().append(name, value.clone());
+                    } else {
+                        resp.headers_mut().insert(name, value.clone());
+                    }
+                }
+                resp
+            });
+        Box::new(fut) as BoxedResponse
+    }
+}
@@ -0,0 +1,431 @@
+use std::fmt;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures::{future, Future};
+use http;
+use http::header::{self, HeaderName};
+
+use cookies::{Cookie, CookieJar, SameSite};
+use random;
+#[cfg(feature = "sessions")]
+use sessions::Session;
+use {Body, BoxedResponse, Error, Handler, Responder, ResponseBuilder};
+
+use super::buffer::{Buffer, Buffered};
+
+/// The CSRF token of the client which made a request.
+///
+/// The [`with_csrf`] middleware stores a `CsrfToken` in the extensions of each request. Handlers
+/// should embed it in HTML forms, in a hidden field with the name configured on [`Csrf`] (by
+/// default, `csrf_token`), or send it from JavaScript in the configured header (by default,
+/// `X-CSRF-Token`).
+///
+/// [`with_csrf`]: fn.with_csrf.html
+/// [`Csrf`]: struct.Csrf.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CsrfToken(String);
+
+impl CsrfToken {
+    /// Returns the token as a string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for CsrfToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Storage {
+    // The token is stored in a cookie, which must be submitted back with the request.
+    Cookie(Cookie),
+    // The token is stored under the given key in the request's session.
+    #[cfg(feature = "sessions")]
+    Session(String),
+}
+
+/// Configuration for the [`with_csrf`] middleware.
+///
+/// By default, the token is stored in a cookie named `csrf`, with `Path=/` and `SameSite=Strict`
+/// (the "double-submit cookie" pattern). With the `sessions` feature, the token can instead be
+/// stored in the client's [`Session`], using [`session_key`].
+///
+/// Requests with unsafe methods must submit the token in the `X-CSRF-Token` header, or in a
+/// `csrf_token` field of an `application/x-www-form-urlencoded` body of at most 1 MiB.
+///
+/// [`with_csrf`]: fn.with_csrf.html
+/// [`Session`]: ../sessions/struct.Session.html
+/// [`session_key`]: #method.session_key
+#[derive(Clone, Debug)]
+pub struct Csrf {
+    storage: Storage,
+    header: HeaderName,
+    field: String,
+    max_form_size: usize,
+}
+
+impl Default for Csrf {
+    fn default() -> Self {
+        Csrf {
+            storage: Storage::Cookie(Cookie::named("csrf").path("/").same_site(SameSite::Strict)),
+            header: HeaderName::from_static("x-csrf-token"),
+            field: "csrf_token".to_owned(),
+            max_form_size: 1024 * 1024,
+        }
+    }
+}
+
+impl Csrf {
+    /// Creates a `Csrf` with the default configuration.
+    pub fn new() -> Self {
+        Csrf::default()
+    }
+
+    /// Stores the token in a cookie, using the provided cookie as a template.
+    ///
+    /// The cookie's name and attributes are used for the token cookie, and its value is ignored.
+    /// The cookie should not be `HttpOnly` if JavaScript needs to read the token to send it in a
+    /// header. Applications served over HTTPS should set `Secure`.
+    pub fn cookie(mut self, cookie: Cookie) -> Self {
+        self.storage = Storage::Cookie(cookie);
+        self
+    }
+
+    /// Stores the token under the given key in the client's [`Session`], instead of in a cookie.
+    ///
+    /// The handler must be wrapped by [`with_sessions`], so that a [`Session`] is available.
+    ///
+    /// This method requires the `sessions` feature, which is enabled by default.
+    ///
+    /// [`Session`]: ../sessions/struct.Session.html
+    /// [`with_sessions`]: fn.with_sessions.html
+    #[cfg(feature = "sessions")]
+    pub fn session_key(mut self, key: &str) -> Self {
+        self.storage = Storage::Session(key.to_owned());
+        self
+    }
+
+    /// Sets the header from which the token is read.
+    pub fn header(mut self, header: HeaderName) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Sets the name of the form field from which the token is read.
+    pub fn form_field(mut self, field: &str) -> Self {
+        self.field = field.to_owned();
+        self
+    }
+
+    /// Sets the maximum size of a form body which is buffered to read the token.
+    ///
+    /// Forms with larger bodies are rejected with `413 Payload Too Large`, without calling the
+    /// handler. This does not apply to requests which submit the token in a header.
+    pub fn max_form_size(mut self, max_form_size: usize) -> Self {
+        self.max_form_size = max_form_size;
+        self
+    }
+
+    // Returns the client's existing token, if it has one.
+    fn load(&self, req: &http::Request<impl Body>) -> Result<Option<String>, Error> {
+        match self.storage {
+            Storage::Cookie(ref template) => {
+                let token = match req.extensions().get::<CookieJar>() {
+                    Some(jar) => jar.get(template.name()),
+                    None => CookieJar::from_headers(req.headers()).get(template.name()),
+                };
+                Ok(token
+                    .map(|cookie| cookie.value().to_owned())
+                    .filter(|token| !token.is_empty()))
+            }
+            #[cfg(feature = "sessions")]
+            Storage::Session(ref key) => match req.extensions().get::<Session>() {
+                Some(session) => Ok(session.get::<String>(key)),
+                None => Err(From::from(
+                    "with_csrf: no Session in request extensions, wrap with with_sessions",
+                )),
+            },
+        }
+    }
+
+    // Stores a newly generated token. Returns the cookie which must be sent in the response, if the
+    // request has no `CookieJar` to add it to.
+    fn store(&self, req: &http::Request<impl Body>, token: &str) -> Result<Option<Cookie>, Error> {
+        match self.storage {
+            Storage::Cookie(ref template) => {
+                let mut cookie = template.clone();
+                cookie.set_value(token);
+                match req.extensions().get::<CookieJar>() {
+                    Some(jar) => {
+                        jar.add(cookie);
+                        Ok(None)
+                    }
+                    None => Ok(Some(cookie)),
+                }
+            }
+            #[cfg(feature = "sessions")]
+            Storage::Session(ref key) => {
+                if let Some(session) = req.extensions().get::<Session>() {
+                    session.insert(key, token)?;
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
+fn is_safe(method: &http::Method) -> bool {
+    matches!(
+        *method,
+        http::Method::GET | http::Method::HEAD | http::Method::OPTIONS | http::Method::TRACE
+    )
+}
+
+fn is_form(headers: &http::HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(|mime| {
+            mime.trim()
+                .eq_ignore_ascii_case("application/x-www-form-urlencoded")
+        })
+        .unwrap_or(false)
+}
+
+// Compares two tokens in time which depends only on their lengths, so that an attacker can't learn
+// the token a byte at a time.
+fn tokens_match(expected: &str, actual: &str) -> bool {
+    let (expected, actual) = (expected.as_bytes(), actual.as_bytes());
+    if expected.len() != actual.len() {
+        return false;
+    }
+    expected
+        .iter()
+        .zip(actual)
+        .fold(0, |diff, (a, b)| diff | (a ^ b))
+        == 0
+}
+
+fn percent_decode(input: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(input.len());
+    let mut iter = input.bytes();
+    while let Some(byte) = iter.next() {
+        match byte {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex = [iter.next()?, iter.next()?];
+                let hex = ::std::str::from_utf8(&hex).ok()?;
+                bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            }
+            byte => bytes.push(byte),
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+// Returns the first value of the given field in an `application/x-www-form-urlencoded` body.
+fn form_value(body: &[u8], field: &str) -> Option<String> {
+    let body = ::std::str::from_utf8(body).ok()?;
+    body.split('&')
+        .filter_map(|pair| {
+            let mut iter = pair.splitn(2, '=');
+            let name = percent_decode(iter.next()?)?;
+            let value = percent_decode(iter.next().unwrap_or(""))?;
+            Some((name, value))
+        })
+        .find(|(name, _)| name == field)
+        .map(|(_, value)| value)
+}
+
+fn forbidden() -> BoxedResponse {
+    let mut resp = http::Response::new(Bytes::from_static(b"invalid CSRF token").into_stream());
+    *resp.status_mut() = http::StatusCode::FORBIDDEN;
+    Box::new(future::ok(resp))
+}
+
+fn payload_too_large() -> BoxedResponse {
+    let mut resp = http::Response::new(Bytes::from_static(b"form too large").into_stream());
+    *resp.status_mut() = http::StatusCode::PAYLOAD_TOO_LARGE;
+    Box::new(future::ok(resp))
+}
+
+/// Middleware which protects the wrapped handler against cross-site request forgery (CSRF).
+///
+/// Each client is given a random token, which is stored as configured on the provided [`Csrf`]
+/// (by default, in a cookie). The token is stored in the extensions of each request as a
+/// [`CsrfToken`], so that handlers can embed it in forms.
+///
+/// Requests using methods other than `GET`, `HEAD`, `OPTIONS` and `TRACE` must submit the token,
+/// either in a header or in a field of an `application/x-www-form-urlencoded` body. Requests which
+/// do not are rejected with `403 Forbidden`, without calling the handler. When the token is read
+/// from a form, the body is buffered and then passed on to the handler unchanged, so that the
+/// handler can parse the form itself. Forms larger than the configured [`max_form_size`] are
+/// rejected with `413 Payload Too Large`.
+///
+/// [`Csrf`]: struct.Csrf.html
+/// [`CsrfToken`]: struct.CsrfToken.html
+/// [`max_form_size`]: struct.Csrf.html#method.max_form_size
+///
+/// # Example
+///
+/// ```no_run
+/// extern crate aitch;
+/// extern crate http;
+///
+/// use aitch::middlewares::{self, Csrf, CsrfToken};
+/// use aitch::servers::hyper::Server;
+/// use aitch::{Responder, ResponseBuilder, Result};
+/// use http::Request;
+///
+/// fn handler(req: Request<Vec<u8>>, mut resp: ResponseBuilder) -> impl Responder {
+///     if req.method() == http::Method::POST {
+///         return resp.body("Saved!".to_owned());
+///     }
+///
+///     let token = req.extensions().get::<CsrfToken>().unwrap();
+///     resp.header(http::header::CONTENT_TYPE, "text/html")
+///         .body(format!(
+///             r#"<form method="post">
+///                 <input type="hidden" name="csrf_token" value="{}">
+///                 <input type="submit">
+///             </form>"#,
+///             token
+///         ))
+/// }
+///
+/// fn main() -> Result<()> {
+///     let wrapped = middlewares::with_csrf(Csrf::new(), handler);
+///
+///     let addr = "127.0.0.1:3000".parse()?;
+///     Server::new(addr, wrapped)?.run()
+/// }
+/// ```
+pub fn with_csrf<B: Body>(csrf: Csrf, handler: impl Handler<B>) -> impl Handler<B> {
+    let handler = Arc::new(handler);
+    move |mut req: http::Request<B>, resp: ResponseBuilder| -> BoxedResponse {
+        let existing = match csrf.load(&req) {
+            Ok(existing) => existing,
+            Err(err) => return Box::new(future::err(err)),
+        };
+
+        let (token, cookie) = match existing {
+            Some(ref token) => (token.clone(), None),
+            None => {
+                let token = random::hex_token(32);
+                match csrf.store(&req, &token) {
+                    Ok(cookie) => (token, cookie),
+                    Err(err) => return Box::new(future::err(err)),
+                }
+            }
+        };
+        req.extensions_mut().insert(CsrfToken(token));
+
+        let set_cookie = move |mut resp: http::Response<_>| -> Result<_, Error> {
+            if let Some(cookie) = cookie {
+                resp.headers_mut()
+                    .append(header::SET_COOKIE, cookie.to_header_value()?);
+            }
+            Ok(resp)
+        };
+
+        if is_safe(req.method()) {
+            return Box::new(
+                handler
+                    .handle(req, resp)
+                    .into_response()
+                    .and_then(set_cookie),
+            );
+        }
+
+        // Clients without a token can't have submitted a valid one.
+        let expected = match existing {
+            Some(expected) => expected,
+            None => return Box::new(forbidden().and_then(set_cookie)),
+        };
+
+        let submitted = req
+            .headers()
+            .get(&csrf.header)
+            .and_then(|value| value.to_str().ok());
+        if let Some(submitted) = submitted {
+            if tokens_match(&expected, submitted) {
+                return Box::new(handler.handle(req, resp).into_response());
+            }
+            return forbidden();
+        }
+
+        if !is_form(req.headers()) {
+            return forbidden();
+        }
+
+        let max_form_size = csrf.max_form_size;
+        let content_length = req
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok());
+        if content_length.is_some_and(|len| len > max_form_size as u64) {
+            return payload_too_large();
+        }
+
+        let (parts, body) = req.into_parts();
+        let handler = handler.clone();
+        let field = csrf.field.clone();
+        let fut = Buffer::new(body.into_stream(), max_form_size).and_then(
+            move |buffered| -> BoxedResponse {
+                let bytes = match buffered {
+                    Buffered::Complete(bytes) => bytes,
+                    Buffered::TooLarge(..) => return payload_too_large(),
+                };
+                let valid = form_value(&bytes, &field)
+                    .map(|submitted| tokens_match(&expected, &submitted))
+                    .unwrap_or(false);
+                if !valid {
+                    return forbidden();
+                }
+
+                let fut = B::from_stream(bytes.into_stream()).and_then(move |body| {
+                    let req = http::Request::from_parts(parts, body);
+                    handler.handle(req, resp).into_response()
+                });
+                Box::new(fut)
+            },
+        );
+        Box::new(fut)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{form_value, tokens_match};
+
+    #[test]
+    fn parse_form() {
+        let body = b"name=J%C3%BCrgen+Smith&csrf_token=abc123&csrf_token=other";
+        assert_eq!(form_value(body, "csrf_token"), Some("abc123".to_owned()));
+        assert_eq!(
+            form_value(body, "name"),
+            Some("J\u{fc}rgen Smith".to_owned())
+        );
+        assert_eq!(
+            form_value(b"csrf%5Ftoken=abc", "csrf_token"),
+            Some("abc".to_owned())
+        );
+        assert_eq!(form_value(b"csrf_token", "csrf_token"), Some("".to_owned()));
+        assert_eq!(form_value(b"csrf_token=%zz", "csrf_token"), None);
+        assert_eq!(form_value(b"other=abc", "csrf_token"), None);
+    }
+
+    #[test]
+    fn compare_tokens() {
+        assert!(tokens_match("abc123", "abc123"));
+        assert!(!tokens_match("abc123", "abc124"));
+        assert!(!tokens_match("abc123", "abc12"));
+        assert!(!tokens_match("abc123", ""));
+    }
+}
@@ -0,0 +1,345 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::io::{self, Write};
+use std::mem;
+use std::sync::Arc;
+
+use brotli;
+use bytes::Bytes;
+use flate2::write::{GzDecoder, ZlibDecoder};
+use futures::{future, Async, Future, Poll, Stream};
+use http;
+use http::header::{self, HeaderValue};
+
+use {Body, BodyStream, BoxedResponse, Error, Handler, Responder, ResponseBuilder};
+
+/// Configuration for the [`with_decompression`] middleware.
+///
+/// By default, decompressed request bodies are limited to 10MiB.
+///
+/// [`with_decompression`]: fn.with_decompression.html
+#[derive(Clone, Debug)]
+pub struct Decompression {
+    max_size: u64,
+}
+
+impl Default for Decompression {
+    fn default() -> Self {
+        Decompression {
+            max_size: 10 * 1024 * 1024,
+        }
+    }
+}
+
+impl Decompression {
+    /// Creates a `Decompression` with the default settings.
+    pub fn new() -> Self {
+        Decompression::default()
+    }
+
+    /// Sets the maximum size (in bytes) of a decompressed request body.
+    pub fn max_size(mut self, max_size: u64) -> Self {
+        self.max_size = max_size;
+        self
+    }
+}
+
+/// An error decompressing a request body.
+///
+/// The [`with_decompression`] middleware responds to requests which fail with this error, so it is
+/// only seen by handlers which read the body themselves (e.g. those which use a [`BodyStream`]).
+///
+/// [`with_decompression`]: fn.with_decompression.html
+/// [`BodyStream`]: ../type.BodyStream.html
+#[derive(Debug)]
+pub enum DecompressionError {
+    /// The decompressed body was larger than the configured maximum size.
+    TooLarge(u64),
+    /// The body could not be decompressed.
+    Invalid(io::Error),
+}
+
+impl fmt::Display for DecompressionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecompressionError::TooLarge(max_size) => write!(
+                f,
+                "decompressed request body is larger than {} bytes",
+                max_size
+            ),
+            DecompressionError::Invalid(ref err) => {
+                write!(f, "invalid compressed request body: {}", err)
+            }
+        }
+    }
+}
+
+impl StdError for DecompressionError {
+    fn description(&self) -> &str {
+        match *self {
+            DecompressionError::TooLarge(_) => "decompressed request body is too large",
+            DecompressionError::Invalid(_) => "invalid compressed request body",
+        }
+    }
+
+    fn cause(&self) -> Option<&StdError> {
+        match *self {
+            DecompressionError::TooLarge(_) => None,
+            DecompressionError::Invalid(ref err) => Some(err),
+        }
+    }
+}
+
+// The output of a decoder, which refuses to grow beyond a limit. The decoders write their output
+// in small pieces, so this stops a small, highly-compressed body from using lots of memory before
+// it can be rejected.
+struct LimitedOutput {
+    buf: Vec<u8>,
+    written: u64,
+    limit: u64,
+    exceeded: bool,
+}
+
+impl LimitedOutput {
+    fn new(limit: u64) -> Self {
+        LimitedOutput {
+            buf: Vec::new(),
+            written: 0,
+            limit,
+            exceeded: false,
+        }
+    }
+}
+
+impl Write for LimitedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written += buf.len() as u64;
+        if self.written > self.limit {
+            self.exceeded = true;
+            return Err(io::Error::other("size limit exceeded"));
+        }
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+enum Decoder {
+    Brotli(Box<brotli::DecompressorWriter<LimitedOutput>>),
+    Gzip(GzDecoder<LimitedOutput>),
+    Deflate(ZlibDecoder<LimitedOutput>),
+}
+
+impl Decoder {
+    fn for_coding(coding: &str, limit: u64) -> Option<Self> {
+        let output = LimitedOutput::new(limit);
+        match coding {
+            "br" => Some(Decoder::Brotli(Box::new(brotli::DecompressorWriter::new(
+                output, 4096,
+            )))),
+            "gzip" | "x-gzip" => Some(Decoder::Gzip(GzDecoder::new(output))),
+            "deflate" => Some(Decoder::Deflate(ZlibDecoder::new(output))),
+            _ => None,
+        }
+    }
+
+    fn output(&mut self) -> &mut LimitedOutput {
+        match *self {
+            Decoder::Brotli(ref mut decoder) => decoder.get_mut(),
+            Decoder::Gzip(ref mut decoder) => decoder.get_mut(),
+            Decoder::Deflate(ref mut decoder) => decoder.get_mut(),
+        }
+    }
+
+    // Decodes some input, returning any output produced so far.
+    fn decode(&mut self, input: &[u8]) -> Result<Vec<u8>, DecompressionError> {
+        let result = match *self {
+            Decoder::Brotli(ref mut decoder) => {
+                decoder.write_all(input).and_then(|_| decoder.flush())
+            }
+            Decoder::Gzip(ref mut decoder) => {
+                decoder.write_all(input).and_then(|_| decoder.flush())
+            }
+            Decoder::Deflate(ref mut decoder) => {
+                decoder.write_all(input).and_then(|_| decoder.flush())
+            }
+        };
+        self.take_output(result)
+    }
+
+    // Checks that the input was complete, returning any remaining output.
+    fn finish(&mut self) -> Result<Vec<u8>, DecompressionError> {
+        let result = match *self {
+            Decoder::Brotli(ref mut decoder) => decoder.close(),
+            Decoder::Gzip(ref mut decoder) => decoder.try_finish(),
+            Decoder::Deflate(ref mut decoder) => decoder.try_finish(),
+        };
+        self.take_output(result)
+    }
+
+    fn take_output(&mut self, result: io::Result<()>) -> Result<Vec<u8>, DecompressionError> {
+        let output = self.output();
+        match result {
+            Ok(()) => Ok(mem::take(&mut output.buf)),
+            Err(_) if output.exceeded => Err(DecompressionError::TooLarge(output.limit)),
+            Err(err) => Err(DecompressionError::Invalid(err)),
+        }
+    }
+}
+
+// A request body which is decoded by a chain of decoders, in order.
+struct DecodedBody {
+    inner: BodyStream,
+    decoders: Vec<Decoder>,
+    finished: bool,
+}
+
+impl DecodedBody {
+    // Decodes a chunk of the body, or finishes decoding if the body has ended.
+    fn decode(&mut self, chunk: Option<&[u8]>) -> Result<Vec<u8>, DecompressionError> {
+        let mut data = chunk.unwrap_or(&[]).to_vec();
+        for decoder in &mut self.decoders {
+            let mut output = decoder.decode(&data)?;
+            if chunk.is_none() {
+                output.extend(decoder.finish()?);
+            }
+            data = output;
+        }
+        Ok(data)
+    }
+}
+
+impl Stream for DecodedBody {
+    type Item = Bytes;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Bytes>, Error> {
+        loop {
+            if self.finished {
+                return Ok(Async::Ready(None));
+            }
+            let chunk = match self.inner.poll()? {
+                Async::Ready(chunk) => chunk,
+                Async::NotReady => return Ok(Async::NotReady),
+            };
+            self.finished = chunk.is_none();
+            let decoded = self.decode(chunk.as_ref().map(|chunk| &chunk[..]))?;
+            if !decoded.is_empty() {
+                return Ok(Async::Ready(Some(Bytes::from(decoded))));
+            }
+        }
+    }
+}
+
+fn error_response(error: Error) -> BoxedResponse {
+    let status = match error.downcast_ref::<DecompressionError>() {
+        Some(&DecompressionError::TooLarge(_)) => http::StatusCode::PAYLOAD_TOO_LARGE,
+        Some(&DecompressionError::Invalid(_)) => http::StatusCode::BAD_REQUEST,
+        None => return Box::new(future::err(error)),
+    };
+    http::Response::builder()
+        .status(status)
+        .body(error.to_string())
+        .into_response()
+}
+
+/// Middleware which decompresses request bodies.
+///
+/// If a request has a `Content-Encoding` header, this middleware decodes the request body as it is
+/// read, before it is converted to the inner handler's body type. The `gzip`, `deflate` and `br`
+/// encodings are supported. The `Content-Encoding` and `Content-Length` headers are removed from
+/// the request passed to the inner handler.
+///
+/// To guard against "zip bombs", the size of the decompressed body is limited by the provided
+/// [`Decompression`]. Requests whose decompressed body is too large receive a
+/// `413 Payload Too Large` response, and requests whose body cannot be decompressed receive a
+/// `400 Bad Request` response. Requests with an unsupported `Content-Encoding` receive a
+/// `415 Unsupported Media Type` response.
+///
+/// This middleware is only available when the `compression` feature is enabled.
+///
+/// [`Decompression`]: struct.Decompression.html
+///
+/// # Example
+///
+/// ```no_run
+/// extern crate aitch;
+/// extern crate http;
+///
+/// use aitch::middlewares::{self, Decompression};
+/// use aitch::servers::hyper::Server;
+/// use aitch::{Responder, ResponseBuilder, Result};
+/// use http::Request;
+///
+/// fn handler(req: Request<String>, mut resp: ResponseBuilder) -> impl Responder {
+///     resp.body(format!("Received {} bytes", req.body().len()))
+/// }
+///
+/// fn main() -> Result<()> {
+///     let decompression = Decompression::new().max_size(1024 * 1024);
+///     let wrapped = middlewares::with_decompression(decompression, handler);
+///
+///     let addr = "127.0.0.1:3000".parse()?;
+///     Server::new(addr, wrapped)?.run()
+/// }
+/// ```
+pub fn with_decompression<B: Body>(
+    decompression: Decompression,
+    handler: impl Handler<B>,
+) -> impl Handler<BodyStream> {
+    let handler = Arc::new(handler);
+    move |req: http::Request<BodyStream>, mut resp: ResponseBuilder| -> BoxedResponse {
+        let (mut parts, body) = req.into_parts();
+
+        let codings = parts
+            .headers
+            .get_all(header::CONTENT_ENCODING)
+            .iter()
+            .flat_map(|value| value.to_str().unwrap_or("").split(','))
+            .map(|coding| coding.trim().to_lowercase())
+            .filter(|coding| !coding.is_empty() && coding != "identity")
+            .collect::<Vec<_>>();
+
+        // Codings are listed in the order they were applied, so must be decoded in reverse.
+        let mut decoders = Vec::new();
+        for coding in codings.iter().rev() {
+            match Decoder::for_coding(coding, decompression.max_size) {
+                Some(decoder) => decoders.push(decoder),
+                None => {
+                    return resp
+                        .status(http::StatusCode::UNSUPPORTED_MEDIA_TYPE)
+                        .header(
+                            header::ACCEPT_ENCODING,
+                            HeaderValue::from_static("gzip, deflate, br"),
+                        )
+                        .body(format!("unsupported Content-Encoding: {}", coding))
+                        .into_response();
+                }
+            }
+        }
+
+        parts.headers.remove(header::CONTENT_ENCODING);
+        let body = if decoders.is_empty() {
+            body
+        } else {
+            parts.headers.remove(header::CONTENT_LENGTH);
+            Box::new(DecodedBody {
+                inner: body,
+                decoders,
+                finished: false,
+            })
+        };
+
+        let handler = handler.clone();
+        let fut = B::from_stream(body)
+            .and_then(move |body| {
+                let req = http::Request::from_parts(parts, body);
+                handler.handle(req, resp).into_response()
+            })
+            .or_else(error_response);
+        Box::new(fut) as BoxedResponse
+    }
+}
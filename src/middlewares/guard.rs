@@ -0,0 +1,191 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures::future;
+use http;
+
+use {Body, BoxedResponse, Handler, Responder, ResponseBuilder};
+
+/// A principal (e.g. a user) which has roles and permissions, for the [`with_guard`] middleware.
+///
+/// This should be implemented by the type stored in request extensions by an authentication
+/// middleware, such as [`with_basic_auth`], [`with_bearer_auth`] or [`with_jwt`].
+///
+/// [`with_guard`]: fn.with_guard.html
+/// [`with_basic_auth`]: fn.with_basic_auth.html
+/// [`with_bearer_auth`]: fn.with_bearer_auth.html
+/// [`with_jwt`]: fn.with_jwt.html
+pub trait Principal: Send + Sync + 'static {
+    /// Returns whether the principal has the given role.
+    fn has_role(&self, role: &str) -> bool;
+
+    /// Returns whether the principal has the given permission.
+    ///
+    /// By default, principals have no permissions.
+    fn has_permission(&self, _permission: &str) -> bool {
+        false
+    }
+}
+
+type CheckFn<P> = Fn(&P) -> bool + Send + Sync;
+
+/// A check of a [`Principal`]'s roles or permissions, for the [`with_guard`] middleware.
+///
+/// Guards can be combined using [`any`] and [`all`], or [`or`] and [`and`].
+///
+/// [`Principal`]: trait.Principal.html
+/// [`with_guard`]: fn.with_guard.html
+/// [`any`]: #method.any
+/// [`all`]: #method.all
+/// [`or`]: #method.or
+/// [`and`]: #method.and
+pub struct Guard<P> {
+    check: Arc<CheckFn<P>>,
+}
+
+impl<P> Clone for Guard<P> {
+    fn clone(&self) -> Self {
+        Guard {
+            check: self.check.clone(),
+        }
+    }
+}
+
+impl<P: Principal> Guard<P> {
+    /// Creates a guard which checks the principal using the provided function.
+    pub fn new<F>(check: F) -> Self
+    where
+        F: Fn(&P) -> bool + Send + Sync + 'static,
+    {
+        Guard {
+            check: Arc::new(check),
+        }
+    }
+
+    /// Creates a guard which allows any principal.
+    pub fn authenticated() -> Self {
+        Guard::new(|_| true)
+    }
+
+    /// Creates a guard which requires the principal to have the given role.
+    pub fn role(role: &str) -> Self {
+        let role = role.to_owned();
+        Guard::new(move |principal: &P| principal.has_role(&role))
+    }
+
+    /// Creates a guard which requires the principal to have the given permission.
+    pub fn permission(permission: &str) -> Self {
+        let permission = permission.to_owned();
+        Guard::new(move |principal: &P| principal.has_permission(&permission))
+    }
+
+    /// Creates a guard which requires any of the provided guards to allow the principal.
+    ///
+    /// If no guards are provided, no principals are allowed.
+    pub fn any(guards: Vec<Guard<P>>) -> Self {
+        Guard::new(move |principal: &P| guards.iter().any(|guard| guard.check(principal)))
+    }
+
+    /// Creates a guard which requires all of the provided guards to allow the principal.
+    ///
+    /// If no guards are provided, all principals are allowed.
+    pub fn all(guards: Vec<Guard<P>>) -> Self {
+        Guard::new(move |principal: &P| guards.iter().all(|guard| guard.check(principal)))
+    }
+
+    /// Returns a guard which requires both this guard and `other` to allow the principal.
+    pub fn and(self, other: Guard<P>) -> Self {
+        Guard::all(vec![self, other])
+    }
+
+    /// Returns a guard which requires either this guard or `other` to allow the principal.
+    pub fn or(self, other: Guard<P>) -> Self {
+        Guard::any(vec![self, other])
+    }
+
+    /// Returns whether the guard allows the given principal.
+    pub fn check(&self, principal: &P) -> bool {
+        (self.check)(principal)
+    }
+}
+
+fn forbidden() -> BoxedResponse {
+    let mut resp = http::Response::new(Bytes::from_static(b"forbidden").into_stream());
+    *resp.status_mut() = http::StatusCode::FORBIDDEN;
+    Box::new(future::ok(resp))
+}
+
+/// Middleware which only calls the wrapped handler if the request's principal is allowed by the
+/// provided [`Guard`].
+///
+/// The principal is read from the extensions of the request, so this middleware must be wrapped by
+/// an authentication middleware which stores a `P` there. Requests without a principal, or whose
+/// principal is not allowed by the guard, are rejected with `403 Forbidden`.
+///
+/// To guard a single route of a [`SimpleRouter`], wrap the route's handler before registering it.
+///
+/// [`Guard`]: struct.Guard.html
+/// [`SimpleRouter`]: struct.SimpleRouter.html
+///
+/// # Example
+///
+/// ```no_run
+/// extern crate aitch;
+/// extern crate http;
+///
+/// use aitch::middlewares::{self, BasicAuth, Guard, Principal, SimpleRouter};
+/// use aitch::servers::hyper::Server;
+/// use aitch::{Responder, ResponseBuilder, Result};
+/// use http::Request;
+///
+/// #[derive(Clone)]
+/// struct User {
+///     roles: Vec<String>,
+/// }
+///
+/// impl Principal for User {
+///     fn has_role(&self, role: &str) -> bool {
+///         self.roles.iter().any(|r| r == role)
+///     }
+/// }
+///
+/// fn handler(_req: Request<()>, mut resp: ResponseBuilder) -> impl Responder {
+///     resp.body("Hello!".to_owned())
+/// }
+///
+/// fn main() -> Result<()> {
+///     let mut router = SimpleRouter::new();
+///     router.register_handler("/", handler);
+///     router.register_handler(
+///         "/admin/",
+///         middlewares::with_guard(Guard::<User>::role("admin"), handler),
+///     );
+///
+///     let auth = BasicAuth::new("internal", |username: &str, _password: &str| {
+///         // ...
+///         let roles = vec![username.to_owned()];
+///         Ok(Some(User { roles }))
+///     });
+///     let wrapped = middlewares::with_basic_auth(auth, router);
+///
+///     let addr = "127.0.0.1:3000".parse()?;
+///     Server::new(addr, wrapped)?.run()
+/// }
+/// ```
+pub fn with_guard<B, P>(guard: Guard<P>, handler: impl Handler<B>) -> impl Handler<B>
+where
+    B: Body,
+    P: Principal,
+{
+    move |req: http::Request<B>, resp: ResponseBuilder| -> BoxedResponse {
+        let allowed = req
+            .extensions()
+            .get::<P>()
+            .map(|principal| guard.check(principal))
+            .unwrap_or(false);
+        if !allowed {
+            return forbidden();
+        }
+        handler.handle(req, resp).into_response()
+    }
+}
@@ -0,0 +1,327 @@
+use std::fs;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm, PublicKeyUse,
+};
+use jsonwebtoken::{self, Algorithm, DecodingKey, Validation};
+use serde::de::DeserializeOwned;
+use serde_json;
+
+use super::{with_bearer_auth, BearerAuth};
+use {Body, Handler, Result};
+
+struct JwtKey {
+    id: Option<String>,
+    algorithm: Algorithm,
+    key: DecodingKey,
+}
+
+/// A set of keys used to verify the signatures of JWTs, for the [`with_jwt`] middleware.
+///
+/// Keys are loaded from a JSON Web Key Set (RFC 7517). Keys for the `HS256`, `RS256` and `ES256`
+/// algorithms are supported: each key's algorithm is taken from its `alg` parameter, or inferred
+/// from its type (`oct`, `RSA` or `EC` with the `P-256` curve) if it has none. Keys for other
+/// algorithms, and keys which are only for encryption, are ignored.
+///
+/// When a token's header has a `kid`, only the key with the same ID is used to verify it.
+/// Otherwise, every key for the token's algorithm is tried.
+///
+/// This type requires the `jwt` feature, which is enabled by default.
+///
+/// [`with_jwt`]: fn.with_jwt.html
+#[derive(Clone)]
+pub struct JwtKeys {
+    keys: Arc<Vec<JwtKey>>,
+}
+
+impl JwtKeys {
+    /// Loads keys from a JSON Web Key Set.
+    pub fn from_jwks(jwks: &[u8]) -> Result<JwtKeys> {
+        let set: JwkSet = serde_json::from_slice(jwks)?;
+        let mut keys = Vec::new();
+        for jwk in &set.keys {
+            if let Some(algorithm) = algorithm(jwk) {
+                keys.push(JwtKey {
+                    id: jwk.common.key_id.clone(),
+                    algorithm,
+                    key: DecodingKey::from_jwk(jwk)?,
+                });
+            }
+        }
+        Ok(JwtKeys {
+            keys: Arc::new(keys),
+        })
+    }
+
+    /// Loads keys from a file containing a JSON Web Key Set.
+    pub fn from_jwks_file<P: AsRef<Path>>(path: P) -> Result<JwtKeys> {
+        JwtKeys::from_jwks(&fs::read(path)?)
+    }
+
+    /// Returns the number of keys in the set.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Returns `true` if the set has no keys.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    fn candidates<'a>(
+        &'a self,
+        id: Option<&'a str>,
+        algorithm: Algorithm,
+    ) -> impl Iterator<Item = &'a JwtKey> + 'a {
+        self.keys.iter().filter(move |key| {
+            key.algorithm == algorithm && (id.is_none() || key.id.as_deref() == id)
+        })
+    }
+}
+
+// Returns the algorithm which a JWK is used with, or `None` if it is not supported.
+fn algorithm(jwk: &Jwk) -> Option<Algorithm> {
+    if let Some(PublicKeyUse::Encryption) = jwk.common.public_key_use {
+        return None;
+    }
+    let inferred = match jwk.algorithm {
+        AlgorithmParameters::OctetKey(_) => Algorithm::HS256,
+        AlgorithmParameters::RSA(_) => Algorithm::RS256,
+        AlgorithmParameters::EllipticCurve(ref params) if params.curve == EllipticCurve::P256 => {
+            Algorithm::ES256
+        }
+        _ => return None,
+    };
+    let declared = match jwk.common.key_algorithm {
+        None => return Some(inferred),
+        Some(KeyAlgorithm::HS256) => Algorithm::HS256,
+        Some(KeyAlgorithm::RS256) => Algorithm::RS256,
+        Some(KeyAlgorithm::ES256) => Algorithm::ES256,
+        Some(_) => return None,
+    };
+    if declared == inferred {
+        Some(declared)
+    } else {
+        None
+    }
+}
+
+/// Configuration for the [`with_jwt`] middleware.
+///
+/// The type `T` is the type into which the claims of each token are decoded.
+///
+/// By default, tokens must have an `exp` claim, and are rejected once it has passed. Tokens are
+/// also rejected before their `nbf` claim, if they have one. Up to a minute of clock skew between
+/// the issuer and the server is tolerated. Tokens which have an `aud` claim are rejected unless
+/// the audience is set using [`audience`].
+///
+/// This type requires the `jwt` feature, which is enabled by default.
+///
+/// [`with_jwt`]: fn.with_jwt.html
+/// [`audience`]: #method.audience
+pub struct Jwt<T> {
+    keys: JwtKeys,
+    realm: String,
+    audience: Vec<String>,
+    issuer: Vec<String>,
+    leeway: Duration,
+    claims: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for Jwt<T> {
+    fn clone(&self) -> Self {
+        Jwt {
+            keys: self.keys.clone(),
+            realm: self.realm.clone(),
+            audience: self.audience.clone(),
+            issuer: self.issuer.clone(),
+            leeway: self.leeway,
+            claims: PhantomData,
+        }
+    }
+}
+
+impl<T: DeserializeOwned> Jwt<T> {
+    /// Creates a `Jwt` which verifies tokens using the given keys.
+    pub fn new(keys: JwtKeys) -> Self {
+        Jwt {
+            keys,
+            realm: "api".to_owned(),
+            audience: Vec::new(),
+            issuer: Vec::new(),
+            leeway: Duration::from_secs(60),
+            claims: PhantomData,
+        }
+    }
+
+    /// Sets the realm included in the `WWW-Authenticate` challenge sent to unauthenticated clients.
+    pub fn realm(mut self, realm: &str) -> Self {
+        self.realm = realm.to_owned();
+        self
+    }
+
+    /// Requires tokens to have an `aud` claim containing the given audience.
+    ///
+    /// May be called more than once, to accept any of several audiences.
+    pub fn audience(mut self, audience: &str) -> Self {
+        self.audience.push(audience.to_owned());
+        self
+    }
+
+    /// Requires tokens to have an `iss` claim matching the given issuer.
+    ///
+    /// May be called more than once, to accept any of several issuers.
+    pub fn issuer(mut self, issuer: &str) -> Self {
+        self.issuer.push(issuer.to_owned());
+        self
+    }
+
+    /// Sets the clock skew tolerated when checking the `exp` and `nbf` claims.
+    pub fn leeway(mut self, leeway: Duration) -> Self {
+        self.leeway = leeway;
+        self
+    }
+
+    fn validation(&self, algorithm: Algorithm) -> Validation {
+        let mut validation = Validation::new(algorithm);
+        validation.leeway = self.leeway.as_secs();
+        validation.validate_nbf = true;
+        let mut required = vec!["exp"];
+        if !self.audience.is_empty() {
+            validation.set_audience(&self.audience);
+            required.push("aud");
+        }
+        if !self.issuer.is_empty() {
+            validation.set_issuer(&self.issuer);
+            required.push("iss");
+        }
+        validation.set_required_spec_claims(&required);
+        validation
+    }
+
+    /// Verifies a token, returning its claims, or `None` if the token is not valid.
+    pub fn verify(&self, token: &str) -> Option<T> {
+        let header = jsonwebtoken::decode_header(token).ok()?;
+        match header.alg {
+            Algorithm::HS256 | Algorithm::RS256 | Algorithm::ES256 => {}
+            _ => return None,
+        }
+
+        let validation = self.validation(header.alg);
+        let claims = self
+            .keys
+            .candidates(header.kid.as_deref(), header.alg)
+            .filter_map(|key| jsonwebtoken::decode::<T>(token, &key.key, &validation).ok())
+            .map(|data| data.claims)
+            .next();
+        claims
+    }
+}
+
+/// Middleware which authenticates requests using JSON Web Tokens (RFC 7519).
+///
+/// The token is read from the request's `Authorization: Bearer` header, and its signature is
+/// verified using the [`JwtKeys`] set on the provided [`Jwt`]. Its `exp`, `nbf`, `aud` and `iss`
+/// claims are then checked as configured. If the token is valid, its claims are decoded into a `T`,
+/// which is stored in the extensions of the request, and the wrapped handler is called. Otherwise,
+/// the request is rejected in the same way as by [`with_bearer_auth`].
+///
+/// This middleware requires the `jwt` feature, which is enabled by default.
+///
+/// [`JwtKeys`]: struct.JwtKeys.html
+/// [`Jwt`]: struct.Jwt.html
+/// [`with_bearer_auth`]: fn.with_bearer_auth.html
+///
+/// # Example
+///
+/// ```no_run
+/// extern crate aitch;
+/// extern crate http;
+/// #[macro_use]
+/// extern crate serde_derive;
+///
+/// use aitch::middlewares::{self, Jwt, JwtKeys};
+/// use aitch::servers::hyper::Server;
+/// use aitch::{Responder, ResponseBuilder, Result};
+/// use http::Request;
+///
+/// #[derive(Clone, Deserialize)]
+/// struct Claims {
+///     sub: String,
+/// }
+///
+/// fn handler(req: Request<()>, mut resp: ResponseBuilder) -> impl Responder {
+///     let claims = req.extensions().get::<Claims>().unwrap();
+///     resp.body(format!("Hello, {}!", claims.sub))
+/// }
+///
+/// fn main() -> Result<()> {
+///     let keys = JwtKeys::from_jwks_file("/etc/gateway/jwks.json")?;
+///     let jwt = Jwt::<Claims>::new(keys)
+///         .audience("orders-api")
+///         .issuer("https://gateway.example.com");
+///     let wrapped = middlewares::with_jwt(jwt, handler);
+///
+///     let addr = "127.0.0.1:3000".parse()?;
+///     Server::new(addr, wrapped)?.run()
+/// }
+/// ```
+pub fn with_jwt<B, T>(jwt: Jwt<T>, handler: impl Handler<B>) -> impl Handler<B>
+where
+    B: Body,
+    T: DeserializeOwned + Clone + Send + Sync + 'static,
+{
+    let realm = jwt.realm.clone();
+    let auth = BearerAuth::new(&realm, move |token: &str| Ok(jwt.verify(token)));
+    with_bearer_auth(auth, handler)
+}
+
+#[cfg(test)]
+mod test {
+    use jsonwebtoken::Algorithm;
+
+    use super::JwtKeys;
+
+    #[test]
+    fn key_algorithms() {
+        let jwks = br#"{"keys": [
+            {"kty": "oct", "kid": "hmac", "k": "c2VjcmV0"},
+            {"kty": "oct", "kid": "hmac-512", "alg": "HS512", "k": "c2VjcmV0"},
+            {"kty": "oct", "kid": "mismatched", "alg": "RS256", "k": "c2VjcmV0"},
+            {"kty": "EC", "kid": "p256", "crv": "P-256",
+             "x": "g7kHTwDEDIwoiJW9WkFrMyTAxgz6AlE98R7o86j1juA",
+             "y": "M6NskKJj2awZpgbSAMH2CePrcxZBOvCwUPI1FLdqkUY"},
+            {"kty": "RSA", "kid": "rsa-enc", "use": "enc", "n": "AQAB", "e": "AQAB"},
+            {"kty": "RSA", "kid": "rsa", "use": "sig", "alg": "RS256", "n": "AQAB", "e": "AQAB"}
+        ]}"#;
+        let keys = JwtKeys::from_jwks(jwks).unwrap();
+        let found: Vec<_> = keys
+            .keys
+            .iter()
+            .map(|key| (key.id.as_deref().unwrap(), key.algorithm))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("hmac", Algorithm::HS256),
+                ("p256", Algorithm::ES256),
+                ("rsa", Algorithm::RS256),
+            ]
+        );
+
+        let ids = |id, algorithm| {
+            keys.candidates(id, algorithm)
+                .map(|key| key.id.clone().unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(None, Algorithm::ES256), vec!["p256"]);
+        assert_eq!(ids(Some("rsa"), Algorithm::RS256), vec!["rsa"]);
+        assert!(ids(Some("rsa"), Algorithm::HS256).is_empty());
+
+        assert!(JwtKeys::from_jwks(b"{}").is_err());
+    }
+}
@@ -1,23 +1,80 @@
 //! A collection of useful HTTP middlewares.
 
+mod access_log;
+mod auth;
+mod buffer;
+mod cache;
+#[cfg(feature = "compression")]
+mod compression;
+mod concurrency;
+mod conditional;
+mod cookies;
+mod cors;
+mod csrf;
+#[cfg(feature = "compression")]
+mod decompression;
+mod guard;
+#[cfg(feature = "jwt")]
+mod jwt;
+mod panic_recovery;
+mod rate_limit;
+mod request_id;
 mod router;
+mod security_headers;
+#[cfg(feature = "sessions")]
+mod sessions;
+mod timeout;
 
 use futures::Future;
 use http;
 
 use {Body, Error, Handler, Responder, ResponseBuilder};
 
+pub use self::access_log::{with_access_log, AccessLog, AccessLogEntry, LogFormat};
+pub use self::auth::{with_basic_auth, with_bearer_auth, AuthFuture, BasicAuth, BearerAuth};
+pub use self::cache::{with_response_cache, ResponseCache};
+#[cfg(feature = "compression")]
+pub use self::compression::{with_compression, Compression, CompressionLevel, Encoding};
+pub use self::concurrency::{with_concurrency_limit, ConcurrencyLimit};
+pub use self::conditional::{with_conditional_requests, ConditionalRequests, Preconditions};
+pub use self::cookies::with_cookies;
+#[cfg(feature = "secure-cookies")]
+pub use self::cookies::with_cookie_keys;
+pub use self::cors::{with_cors, Cors};
+pub use self::csrf::{with_csrf, Csrf, CsrfToken};
+#[cfg(feature = "compression")]
+pub use self::decompression::{with_decompression, Decompression, DecompressionError};
+pub use self::guard::{with_guard, Guard, Principal};
+#[cfg(feature = "jwt")]
+pub use self::jwt::{with_jwt, Jwt, JwtKeys};
+pub use self::panic_recovery::{install_panic_hook, with_panic_recovery, PanicError};
+pub use self::rate_limit::{
+    with_rate_limit, Quota, RateLimit, RateLimitDecision, RateLimitFuture, RateLimitMemoryStore,
+    RateLimitStore,
+};
+#[cfg(any(feature = "server-hyper", feature = "server-tiny-http"))]
+pub(crate) use self::request_id::RequestIdSlot;
+pub use self::request_id::{with_request_id, RequestId, RequestIdConfig};
 pub use self::router::SimpleRouter;
+pub use self::security_headers::{with_security_headers, CspNonce, SecurityHeaders};
+#[cfg(feature = "sessions")]
+pub use self::sessions::{with_sessions, SessionConfig};
+pub use self::timeout::{with_body_timeout, with_timeout, BodyTimeoutError};
 
 /// Middleware which outputs details of HTTP requests/responses to stdout.
 ///
 /// This middleware wraps another HTTP handler, and logs details about each HTTP request (method and
-/// URI) and the associated response (status) to stdout. The logging only occurs when the
+/// URI) and the associated response (status) to stdout. If the request has a [`RequestId`] (see
+/// [`with_request_id`]), it is also included in the output. The logging only occurs when the
 /// [`Responder`] returned from the [`Handler`] resolved to a `http::Response`.
 ///
-/// This middleware is intended to help during development. It is expected that any production
-/// application creates its own version of this middleware, to integrate it into the application's
-/// logging infrastructure.
+/// This middleware is intended to help during development. Production applications should consider
+/// using [`with_access_log`] instead, or creating their own version of this middleware to integrate
+/// it into the application's logging infrastructure.
+///
+/// [`with_access_log`]: fn.with_access_log.html
+/// [`RequestId`]: struct.RequestId.html
+/// [`with_request_id`]: fn.with_request_id.html
 ///
 /// [`Responder`]: ../trait.Responder.html [`Handler`]: ../trait.Handler.html [`http::Response`]:
 /// https://docs.rs/http/0.1.7/http/response/struct.Response.html
@@ -57,9 +114,13 @@ pub fn with_stdout_logging<B: Body>(handler: impl Handler<B>) -> impl Handler<B>
     move |req: http::Request<B>, resp: ResponseBuilder| {
         let method = req.method().clone();
         let uri = req.uri().clone();
+        let id = req.extensions().get::<RequestId>().cloned();
 
         handler.handle(req, resp).into_response().map(move |resp| {
-            println!("{} {} {}", method, uri.path(), resp.status());
+            match id {
+                Some(id) => println!("{} {} {} {}", method, uri.path(), resp.status(), id),
+                None => println!("{} {} {}", method, uri.path(), resp.status()),
+            }
             resp
         })
     }
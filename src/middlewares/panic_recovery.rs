@@ -0,0 +1,170 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::error::Error as StdError;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Once;
+
+use futures::{future, Future};
+use http;
+
+use {Body, BoxedResponse, Error, Handler, Responder, ResponseBuilder};
+
+thread_local! {
+    // The location of the most recent panic on this thread, recorded by the panic hook.
+    static LAST_LOCATION: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+static INSTALL_HOOK: Once = Once::new();
+
+/// Installs a panic hook which records the location of each panic, so that [`with_panic_recovery`]
+/// can include it in the [`PanicError`].
+///
+/// This replaces the process-wide panic hook (see [`std::panic::set_hook`]) with one which records
+/// the location and then calls the previous hook, so panics are still printed as usual. Any hook
+/// which is set afterwards replaces this one, and locations are no longer recorded. Calling this
+/// function more than once has no further effect.
+///
+/// [`with_panic_recovery`]: fn.with_panic_recovery.html
+/// [`PanicError`]: struct.PanicError.html
+/// [`std::panic::set_hook`]: https://doc.rust-lang.org/std/panic/fn.set_hook.html
+pub fn install_panic_hook() {
+    INSTALL_HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let location = info
+                .location()
+                .map(|loc| format!("{}:{}:{}", loc.file(), loc.line(), loc.column()));
+            LAST_LOCATION.with(|last| *last.borrow_mut() = location);
+            previous(info);
+        }));
+    });
+}
+
+/// An error returned by [`with_panic_recovery`] when the wrapped handler panics.
+///
+/// [`with_panic_recovery`]: fn.with_panic_recovery.html
+#[derive(Debug)]
+pub struct PanicError {
+    message: String,
+    location: Option<String>,
+}
+
+impl PanicError {
+    fn new(payload: &(Any + Send)) -> PanicError {
+        let message = if let Some(message) = payload.downcast_ref::<&str>() {
+            (*message).to_owned()
+        } else if let Some(message) = payload.downcast_ref::<String>() {
+            message.clone()
+        } else {
+            "Box<Any>".to_owned()
+        };
+        let location = LAST_LOCATION.with(|last| last.borrow_mut().take());
+        PanicError { message, location }
+    }
+
+    /// Returns the message that the handler panicked with.
+    ///
+    /// If the panic payload was not a string, this is `Box<Any>`.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Returns the location of the panic in the source code, in the form `file:line:column`, if
+    /// it is known.
+    ///
+    /// The location is only known if [`install_panic_hook`] has been called.
+    ///
+    /// [`install_panic_hook`]: fn.install_panic_hook.html
+    pub fn location(&self) -> Option<&str> {
+        self.location.as_deref()
+    }
+}
+
+impl fmt::Display for PanicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "handler panicked at '{}'", self.message)?;
+        if let Some(ref location) = self.location {
+            write!(f, ", {}", location)?;
+        }
+        Ok(())
+    }
+}
+
+impl StdError for PanicError {
+    fn description(&self) -> &str {
+        "handler panicked"
+    }
+}
+
+/// Middleware which catches panics in the wrapped handler.
+///
+/// Without this middleware, a handler which panics causes the connection to be dropped (when
+/// using the hyper server), or the worker thread to die without responding (when using the
+/// tiny_http server).
+///
+/// Panics which occur when calling the handler, or when polling the [`Responder`] it returns, are
+/// converted to a [`PanicError`], which includes the panic's message. The server reports this
+/// error to its error hook, and responds with a `500 Internal Server Error`.
+///
+/// This middleware does not change the process-wide panic hook. To include the location of each
+/// panic in the [`PanicError`], call [`install_panic_hook`] when starting the application.
+///
+/// Only unwinding panics can be caught: if the application is compiled with `panic = "abort"`,
+/// this middleware has no effect.
+///
+/// [`Responder`]: ../trait.Responder.html
+/// [`PanicError`]: struct.PanicError.html
+/// [`install_panic_hook`]: fn.install_panic_hook.html
+///
+/// # Example
+///
+/// ```no_run
+/// extern crate aitch;
+/// extern crate http;
+///
+/// use aitch::middlewares::{self, PanicError};
+/// use aitch::servers::hyper::Server;
+/// use aitch::{Responder, ResponseBuilder, Result};
+/// use http::Request;
+///
+/// fn handler(req: Request<()>, mut resp: ResponseBuilder) -> impl Responder {
+///     let name = req.uri().query().expect("no query string");
+///     resp.body(format!("Hello, {}!", name))
+/// }
+///
+/// fn main() -> Result<()> {
+///     middlewares::install_panic_hook();
+///     let wrapped = middlewares::with_panic_recovery(handler);
+///
+///     let addr = "127.0.0.1:3000".parse()?;
+///     Server::new(addr, wrapped)?
+///         .with_error_hook(|err, _| match err.downcast_ref::<PanicError>() {
+///             Some(panic) => eprintln!("panic: {}", panic),
+///             None => eprintln!("error: {}", err),
+///         })
+///         .run()
+/// }
+/// ```
+pub fn with_panic_recovery<B: Body>(handler: impl Handler<B>) -> impl Handler<B> {
+    move |req: http::Request<B>, resp: ResponseBuilder| -> BoxedResponse {
+        let called = panic::catch_unwind(AssertUnwindSafe(|| {
+            handler.handle(req, resp).into_response()
+        }));
+        let fut = match called {
+            Ok(fut) => fut,
+            Err(payload) => {
+                let err = Error::from(PanicError::new(&*payload));
+                return Box::new(future::err(err));
+            }
+        };
+
+        let fut = AssertUnwindSafe(fut)
+            .catch_unwind()
+            .then(|result| match result {
+                Ok(result) => result,
+                Err(payload) => Err(Error::from(PanicError::new(&*payload))),
+            });
+        Box::new(fut)
+    }
+}
@@ -0,0 +1,468 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use futures::{future, Future};
+use http;
+use http::header::{self, HeaderMap, HeaderName, HeaderValue};
+
+use servers::ConnectionInfo;
+use {Body, BoxedResponse, Error, Handler, Responder, ResponseBuilder};
+
+/// The number of requests a client may make in a period of time.
+///
+/// Quotas are enforced using a token bucket: each client has a bucket holding up to `burst`
+/// tokens, which is refilled at a rate of `limit` tokens per `period`, and each request takes a
+/// token. By default, `burst` is equal to `limit`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quota {
+    limit: u32,
+    period: Duration,
+    burst: u32,
+}
+
+impl Quota {
+    /// Creates a quota which allows `limit` requests per `period`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `limit` is zero, or if `period` is zero.
+    pub fn new(limit: u32, period: Duration) -> Self {
+        assert!(limit > 0, "Quota: limit must be greater than zero");
+        assert!(
+            period > Duration::from_secs(0),
+            "Quota: period must be greater than zero"
+        );
+        Quota {
+            limit,
+            period,
+            burst: limit,
+        }
+    }
+
+    /// Creates a quota which allows `limit` requests per second.
+    pub fn per_second(limit: u32) -> Self {
+        Quota::new(limit, Duration::from_secs(1))
+    }
+
+    /// Creates a quota which allows `limit` requests per minute.
+    pub fn per_minute(limit: u32) -> Self {
+        Quota::new(limit, Duration::from_secs(60))
+    }
+
+    /// Sets the number of requests which may be made in a burst, after a client has been idle.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `burst` is zero.
+    pub fn burst(mut self, burst: u32) -> Self {
+        assert!(burst > 0, "Quota: burst must be greater than zero");
+        self.burst = burst;
+        self
+    }
+
+    /// Returns the number of requests allowed per period.
+    pub fn limit(&self) -> u32 {
+        self.limit
+    }
+
+    /// Returns the period over which `limit` requests are allowed.
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Returns the maximum number of requests which may be made in a burst.
+    pub fn max_burst(&self) -> u32 {
+        self.burst
+    }
+
+    // The number of tokens added to a bucket per second.
+    fn rate(&self) -> f64 {
+        f64::from(self.limit) / duration_secs(self.period)
+    }
+}
+
+fn duration_secs(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1e9
+}
+
+fn secs_duration(secs: f64) -> Duration {
+    let secs = secs.max(0.0);
+    Duration::new(secs.trunc() as u64, (secs.fract() * 1e9) as u32)
+}
+
+/// The result of checking a client's quota.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimitDecision {
+    /// Whether the request is allowed.
+    pub allowed: bool,
+    /// The maximum number of requests which may be made in a burst (the size of the bucket).
+    pub limit: u32,
+    /// The number of further requests which the client may make immediately.
+    pub remaining: u32,
+    /// The time until the client's quota is fully replenished.
+    pub reset: Duration,
+    /// If the request is not allowed, the time until the client may make another request.
+    pub retry_after: Option<Duration>,
+}
+
+/// A future returned by a [`RateLimitStore`].
+///
+/// [`RateLimitStore`]: trait.RateLimitStore.html
+pub type RateLimitFuture = Box<Future<Item = RateLimitDecision, Error = Error> + Send>;
+
+/// Stores the state of each client's quota, for the [`with_rate_limit`] middleware.
+///
+/// This crate provides [`RateLimitMemoryStore`], which keeps state in memory, and so is only
+/// suitable when a single server is running. Other implementations may share state between servers
+/// (e.g. using a database).
+///
+/// [`with_rate_limit`]: fn.with_rate_limit.html
+/// [`RateLimitMemoryStore`]: struct.RateLimitMemoryStore.html
+pub trait RateLimitStore: Send + Sync + 'static {
+    /// Takes a token from the bucket for `key`, if one is available, returning whether the request
+    /// is allowed.
+    fn acquire(&self, key: &str, quota: &Quota) -> RateLimitFuture;
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Debug)]
+struct MemoryState {
+    buckets: HashMap<String, Bucket>,
+    last_sweep: Instant,
+}
+
+/// A [`RateLimitStore`] which keeps a token bucket for each client in memory.
+///
+/// Buckets expire once they would have been refilled, so that memory use is proportional to the
+/// number of recently active clients. Expired buckets are removed periodically.
+///
+/// [`RateLimitStore`]: trait.RateLimitStore.html
+#[derive(Debug)]
+pub struct RateLimitMemoryStore {
+    state: Mutex<MemoryState>,
+    sweep_interval: Duration,
+}
+
+impl Default for RateLimitMemoryStore {
+    fn default() -> Self {
+        RateLimitMemoryStore::new()
+    }
+}
+
+impl RateLimitMemoryStore {
+    /// Creates an empty `RateLimitMemoryStore`.
+    pub fn new() -> Self {
+        RateLimitMemoryStore {
+            state: Mutex::new(MemoryState {
+                buckets: HashMap::new(),
+                last_sweep: Instant::now(),
+            }),
+            sweep_interval: Duration::from_secs(60),
+        }
+    }
+
+    /// Returns the number of buckets currently held in memory.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().buckets.len()
+    }
+
+    /// Returns `true` if no buckets are held in memory.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Takes a token from the bucket for `key`, as of the time `now`.
+    pub fn acquire_at(&self, key: &str, quota: &Quota, now: Instant) -> RateLimitDecision {
+        let mut state = self.state.lock().unwrap();
+        let capacity = f64::from(quota.burst);
+        let rate = quota.rate();
+
+        if now.duration_since(state.last_sweep) >= self.sweep_interval {
+            state.buckets.retain(|_, bucket| {
+                let refilled =
+                    bucket.tokens + duration_secs(now.duration_since(bucket.updated)) * rate;
+                refilled < capacity
+            });
+            state.last_sweep = now;
+        }
+
+        let bucket = state.buckets.entry(key.to_owned()).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        let elapsed = duration_secs(now.duration_since(bucket.updated));
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        bucket.updated = now;
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+        RateLimitDecision {
+            allowed,
+            limit: quota.burst,
+            remaining: bucket.tokens.floor() as u32,
+            reset: secs_duration((capacity - bucket.tokens) / rate),
+            retry_after: if allowed {
+                None
+            } else {
+                Some(secs_duration((1.0 - bucket.tokens) / rate))
+            },
+        }
+    }
+}
+
+impl RateLimitStore for RateLimitMemoryStore {
+    fn acquire(&self, key: &str, quota: &Quota) -> RateLimitFuture {
+        Box::new(future::ok(self.acquire_at(key, quota, Instant::now())))
+    }
+}
+
+type KeyFn = Fn(&http::request::Parts) -> Option<String> + Send + Sync;
+
+/// Configuration for the [`with_rate_limit`] middleware.
+///
+/// By default, clients are identified by their IP address (using the [`ConnectionInfo`] added by
+/// the provided servers), and quotas are stored in a [`RateLimitMemoryStore`].
+///
+/// [`with_rate_limit`]: fn.with_rate_limit.html
+/// [`ConnectionInfo`]: ../servers/struct.ConnectionInfo.html
+/// [`RateLimitMemoryStore`]: struct.RateLimitMemoryStore.html
+#[derive(Clone)]
+pub struct RateLimit {
+    quota: Quota,
+    key: Arc<KeyFn>,
+    store: Arc<RateLimitStore>,
+}
+
+impl RateLimit {
+    /// Creates a `RateLimit` which gives each client the provided quota.
+    pub fn new(quota: Quota) -> Self {
+        RateLimit {
+            quota,
+            key: Arc::new(remote_ip),
+            store: Arc::new(RateLimitMemoryStore::new()),
+        }
+    }
+
+    /// Sets the function used to identify the client making a request.
+    ///
+    /// Requests for which the function returns `None` are not rate limited.
+    pub fn key<F>(mut self, key: F) -> Self
+    where
+        F: Fn(&http::request::Parts) -> Option<String> + Send + Sync + 'static,
+    {
+        self.key = Arc::new(key);
+        self
+    }
+
+    /// Identifies clients using the value of a request header (e.g. an API key).
+    ///
+    /// Requests without the header are not rate limited.
+    pub fn key_header(self, name: HeaderName) -> Self {
+        self.key(move |parts| {
+            parts
+                .headers
+                .get(&name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_owned())
+        })
+    }
+
+    /// Sets the store used to hold the state of each client's quota.
+    pub fn store<S: RateLimitStore>(mut self, store: S) -> Self {
+        self.store = Arc::new(store);
+        self
+    }
+}
+
+fn remote_ip(parts: &http::request::Parts) -> Option<String> {
+    parts
+        .extensions
+        .get::<ConnectionInfo>()
+        .map(|info| info.remote_addr.ip().to_string())
+}
+
+fn ceil_secs(duration: Duration) -> u64 {
+    if duration.subsec_nanos() > 0 {
+        duration.as_secs() + 1
+    } else {
+        duration.as_secs()
+    }
+}
+
+fn add_headers(headers: &mut HeaderMap, decision: &RateLimitDecision) {
+    headers.insert(
+        HeaderName::from_static("ratelimit-limit"),
+        HeaderValue::from(decision.limit),
+    );
+    headers.insert(
+        HeaderName::from_static("ratelimit-remaining"),
+        HeaderValue::from(decision.remaining),
+    );
+    headers.insert(
+        HeaderName::from_static("ratelimit-reset"),
+        HeaderValue::from(ceil_secs(decision.reset)),
+    );
+    if let Some(retry_after) = decision.retry_after {
+        headers.insert(
+            header::RETRY_AFTER,
+            HeaderValue::from(ceil_secs(retry_after)),
+        );
+    }
+}
+
+/// Middleware which limits the rate at which each client may make requests.
+///
+/// Each client is identified by a key, which is derived from the request using the function set on
+/// the provided [`RateLimit`] (by default, the client's IP address). Requests are allowed while the
+/// client is within its [`Quota`], and receive a `429 Too Many Requests` response otherwise.
+///
+/// All responses include the `RateLimit-Limit`, `RateLimit-Remaining` and `RateLimit-Reset`
+/// headers (as described by the [IETF draft]), and rejected responses also include a `Retry-After`
+/// header.
+///
+/// [`RateLimit`]: struct.RateLimit.html
+/// [`Quota`]: struct.Quota.html
+/// [IETF draft]: https://datatracker.ietf.org/doc/draft-ietf-httpapi-ratelimit-headers/
+///
+/// # Example
+///
+/// ```no_run
+/// extern crate aitch;
+/// extern crate http;
+///
+/// use aitch::middlewares::{self, Quota, RateLimit};
+/// use aitch::servers::hyper::Server;
+/// use aitch::{Responder, ResponseBuilder, Result};
+/// use http::Request;
+///
+/// fn handler(_req: Request<()>, mut resp: ResponseBuilder) -> impl Responder {
+///     resp.body("Hello, world!".to_owned())
+/// }
+///
+/// fn main() -> Result<()> {
+///     // Allow each API key 100 requests per minute, in bursts of up to 10.
+///     let rate_limit = RateLimit::new(Quota::per_minute(100).burst(10))
+///         .key_header(http::header::HeaderName::from_static("x-api-key"));
+///     let wrapped = middlewares::with_rate_limit(rate_limit, handler);
+///
+///     let addr = "127.0.0.1:3000".parse()?;
+///     Server::new(addr, wrapped)?.run()
+/// }
+/// ```
+pub fn with_rate_limit<B: Body>(
+    rate_limit: RateLimit,
+    handler: impl Handler<B>,
+) -> impl Handler<B> {
+    let handler = Arc::new(handler);
+    move |req: http::Request<B>, resp: ResponseBuilder| -> BoxedResponse {
+        let (parts, body) = req.into_parts();
+        let key = (rate_limit.key)(&parts);
+        let req = http::Request::from_parts(parts, body);
+
+        let key = match key {
+            Some(key) => key,
+            None => return handler.handle(req, resp).into_response(),
+        };
+
+        let handler = handler.clone();
+        let fut = rate_limit.store.acquire(&key, &rate_limit.quota).and_then(
+            move |decision| -> BoxedResponse {
+                if !decision.allowed {
+                    let mut resp =
+                        http::Response::new(Bytes::from_static(b"too many requests").into_stream());
+                    *resp.status_mut() = http::StatusCode::TOO_MANY_REQUESTS;
+                    add_headers(resp.headers_mut(), &decision);
+                    return Box::new(future::ok(resp));
+                }
+
+                let fut = handler
+                    .handle(req, resp)
+                    .into_response()
+                    .map(move |mut resp| {
+                        add_headers(resp.headers_mut(), &decision);
+                        resp
+                    });
+                Box::new(fut)
+            },
+        );
+        Box::new(fut)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::{Quota, RateLimitMemoryStore};
+
+    #[test]
+    fn token_bucket() {
+        let store = RateLimitMemoryStore::new();
+        let quota = Quota::new(2, Duration::from_secs(10));
+        let start = Instant::now();
+
+        let first = store.acquire_at("a", &quota, start);
+        assert!(first.allowed);
+        assert_eq!(first.remaining, 1);
+        assert_eq!(first.reset, Duration::from_secs(5));
+
+        assert!(store.acquire_at("a", &quota, start).allowed);
+        let rejected = store.acquire_at("a", &quota, start);
+        assert!(!rejected.allowed);
+        assert_eq!(rejected.remaining, 0);
+        assert_eq!(rejected.retry_after, Some(Duration::from_secs(5)));
+
+        // Other clients have their own bucket.
+        assert!(store.acquire_at("b", &quota, start).allowed);
+
+        // A token is added every five seconds.
+        let later = start + Duration::from_secs(5);
+        assert!(store.acquire_at("a", &quota, later).allowed);
+        assert!(!store.acquire_at("a", &quota, later).allowed);
+    }
+
+    #[test]
+    fn burst() {
+        let store = RateLimitMemoryStore::new();
+        let quota = Quota::per_second(1).burst(3);
+        let now = Instant::now();
+
+        for remaining in (0..3).rev() {
+            let decision = store.acquire_at("a", &quota, now);
+            assert!(decision.allowed);
+            assert_eq!(decision.remaining, remaining);
+            assert_eq!(decision.limit, 3);
+        }
+        assert!(!store.acquire_at("a", &quota, now).allowed);
+    }
+
+    #[test]
+    fn expiry() {
+        let store = RateLimitMemoryStore::new();
+        let quota = Quota::per_second(10);
+        let start = Instant::now();
+
+        store.acquire_at("a", &quota, start);
+        store.acquire_at("b", &quota, start);
+        assert_eq!(store.len(), 2);
+
+        // Both buckets have refilled, so are removed by the next sweep.
+        store.acquire_at("c", &quota, start + Duration::from_secs(120));
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "burst must be greater than zero")]
+    fn zero_burst() {
+        Quota::per_second(10).burst(0);
+    }
+}
@@ -0,0 +1,176 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use futures::Future;
+use http;
+use http::header::{HeaderName, HeaderValue};
+
+use random;
+use {Body, Handler, Responder, ResponseBuilder};
+
+/// The ID of a HTTP request, which can be used to correlate logs across services.
+///
+/// The [`with_request_id`] middleware stores a `RequestId` in the extensions of each request.
+///
+/// [`with_request_id`]: fn.with_request_id.html
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RequestId(String);
+
+impl RequestId {
+    /// Returns the ID as a string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Configuration for the [`with_request_id`] middleware.
+///
+/// By default, the `X-Request-Id` header is used, and new IDs are 128-bit random values encoded as
+/// hex.
+///
+/// [`with_request_id`]: fn.with_request_id.html
+#[derive(Clone)]
+pub struct RequestIdConfig {
+    header: HeaderName,
+    generator: Arc<Fn() -> String + Send + Sync>,
+}
+
+impl Default for RequestIdConfig {
+    fn default() -> Self {
+        RequestIdConfig {
+            header: HeaderName::from_static("x-request-id"),
+            generator: Arc::new(|| random::hex_token(16)),
+        }
+    }
+}
+
+impl RequestIdConfig {
+    /// Creates a `RequestIdConfig` with the default settings.
+    pub fn new() -> Self {
+        RequestIdConfig::default()
+    }
+
+    /// Sets the header which is used to read incoming IDs, and to return the ID in the response.
+    pub fn header(mut self, header: HeaderName) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Sets the function used to generate new IDs, when the request does not already have one.
+    ///
+    /// The generated IDs must be valid header values.
+    pub fn generator<F>(mut self, generator: F) -> Self
+    where
+        F: Fn() -> String + Send + Sync + 'static,
+    {
+        self.generator = Arc::new(generator);
+        self
+    }
+
+    fn incoming_id<B>(&self, req: &http::Request<B>) -> Option<RequestId> {
+        let value = req.headers().get(&self.header)?.to_str().ok()?;
+        if value.is_empty() || value.len() > MAX_INCOMING_LEN {
+            return None;
+        }
+        Some(RequestId(value.to_owned()))
+    }
+}
+
+// Incoming IDs longer than this are replaced, to stop clients from bloating logs.
+const MAX_INCOMING_LEN: usize = 200;
+
+// A slot which the servers insert into the extensions of each request, so that the ID assigned by
+// `with_request_id` can be included in the `RequestMetadata` passed to their error hook.
+#[derive(Clone, Default)]
+pub(crate) struct RequestIdSlot(Arc<Mutex<Option<RequestId>>>);
+
+impl RequestIdSlot {
+    #[cfg(any(feature = "server-hyper", feature = "server-tiny-http"))]
+    pub fn get(&self) -> Option<RequestId> {
+        self.0.lock().unwrap().clone()
+    }
+
+    fn set(&self, id: RequestId) {
+        *self.0.lock().unwrap() = Some(id);
+    }
+}
+
+/// Middleware which assigns an ID to each HTTP request.
+///
+/// If the request already has an ID in the configured header (`X-Request-Id`, by default), that ID
+/// is used. Otherwise, a new ID is generated. The ID is:
+///
+///  - stored in the request's extensions as a [`RequestId`], so that it can be used by inner
+///    handlers and middlewares (such as [`with_access_log`] and [`with_stdout_logging`]).
+///  - set on the request's header, so that it can be forwarded to other services.
+///  - echoed in the response's header.
+///  - passed to the server's error hook in [`RequestMetadata::request_id`], if the inner handler
+///    fails. The error itself is passed through unchanged.
+///
+/// To include the ID in logs, this middleware should wrap the logging middleware.
+///
+/// [`RequestId`]: struct.RequestId.html
+/// [`RequestMetadata::request_id`]: ../servers/struct.RequestMetadata.html#structfield.request_id
+/// [`with_access_log`]: fn.with_access_log.html
+/// [`with_stdout_logging`]: fn.with_stdout_logging.html
+///
+/// # Example
+///
+/// ```no_run
+/// extern crate aitch;
+/// extern crate http;
+///
+/// use aitch::middlewares::{self, RequestId, RequestIdConfig};
+/// use aitch::servers::hyper::Server;
+/// use aitch::{Responder, ResponseBuilder, Result};
+/// use http::Request;
+///
+/// fn handler(req: Request<()>, mut resp: ResponseBuilder) -> impl Responder {
+///     let id = req.extensions().get::<RequestId>().unwrap();
+///     resp.body(format!("Your request ID is {}", id))
+/// }
+///
+/// fn main() -> Result<()> {
+///     let handler = middlewares::with_stdout_logging(handler);
+///     let wrapped = middlewares::with_request_id(RequestIdConfig::default(), handler);
+///
+///     let addr = "127.0.0.1:3000".parse()?;
+///     Server::new(addr, wrapped)?.run()
+/// }
+/// ```
+pub fn with_request_id<B: Body>(
+    config: RequestIdConfig,
+    handler: impl Handler<B>,
+) -> impl Handler<B> {
+    move |mut req: http::Request<B>, resp: ResponseBuilder| {
+        let id = config
+            .incoming_id(&req)
+            .unwrap_or_else(|| RequestId((config.generator)()));
+
+        let header = config.header.clone();
+        let value = HeaderValue::from_str(id.as_str()).ok();
+        if let Some(ref value) = value {
+            req.headers_mut().insert(header.clone(), value.clone());
+        }
+        if let Some(slot) = req.extensions().get::<RequestIdSlot>() {
+            slot.set(id.clone());
+        }
+        req.extensions_mut().insert(id);
+
+        handler
+            .handle(req, resp)
+            .into_response()
+            .map(move |mut resp| {
+                if let Some(value) = value {
+                    resp.headers_mut().insert(header, value);
+                }
+                resp
+            })
+    }
+}
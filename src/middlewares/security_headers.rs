@@ -0,0 +1,294 @@
+use std::fmt;
+use std::time::Duration;
+
+use futures::Future;
+use http;
+use http::header::{self, HeaderMap, HeaderName, HeaderValue};
+
+use random;
+use {Body, BoxedResponse, Handler, Responder, ResponseBuilder};
+
+// The placeholder which is replaced with the request's nonce in the Content-Security-Policy.
+const NONCE_PLACEHOLDER: &str = "{nonce}";
+
+/// A random nonce, which can be used to allow inline scripts and styles under a
+/// Content-Security-Policy.
+///
+/// When the policy configured on [`SecurityHeaders`] contains a `{nonce}` placeholder, the
+/// [`with_security_headers`] middleware generates a new nonce for each request and stores it in the
+/// request's extensions. Handlers should include it in the `nonce` attribute of `<script>` and
+/// `<style>` tags.
+///
+/// [`SecurityHeaders`]: struct.SecurityHeaders.html
+/// [`with_security_headers`]: fn.with_security_headers.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CspNonce(String);
+
+impl CspNonce {
+    /// Returns the nonce as a string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for CspNonce {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+// Inserted into the extensions of responses which have already had security headers applied, so
+// that an outer `with_security_headers` does not override the configuration of an inner one.
+struct SecurityHeadersApplied;
+
+/// Configuration for the [`with_security_headers`] middleware.
+///
+/// By default, the following headers are set:
+///
+/// - `Strict-Transport-Security: max-age=31536000; includeSubDomains`
+/// - `X-Content-Type-Options: nosniff`
+/// - `X-Frame-Options: DENY`
+/// - `Referrer-Policy: strict-origin-when-cross-origin`
+/// - `Content-Security-Policy: default-src 'self'; frame-ancestors 'none'`
+///
+/// Each header can be changed, or disabled by passing `None` to the corresponding method.
+///
+/// [`with_security_headers`]: fn.with_security_headers.html
+#[derive(Clone, Debug)]
+pub struct SecurityHeaders {
+    hsts_max_age: Option<Duration>,
+    hsts_include_subdomains: bool,
+    hsts_preload: bool,
+    content_type_options: bool,
+    frame_options: Option<HeaderValue>,
+    referrer_policy: Option<HeaderValue>,
+    content_security_policy: Option<String>,
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> Self {
+        SecurityHeaders {
+            hsts_max_age: Some(Duration::from_secs(365 * 24 * 60 * 60)),
+            hsts_include_subdomains: true,
+            hsts_preload: false,
+            content_type_options: true,
+            frame_options: Some(HeaderValue::from_static("DENY")),
+            referrer_policy: Some(HeaderValue::from_static("strict-origin-when-cross-origin")),
+            content_security_policy: Some("default-src 'self'; frame-ancestors 'none'".to_owned()),
+        }
+    }
+}
+
+fn header_value(name: &str, value: &str) -> HeaderValue {
+    match HeaderValue::from_str(value) {
+        Ok(value) => value,
+        Err(_) => panic!("SecurityHeaders: invalid {} value: {:?}", name, value),
+    }
+}
+
+impl SecurityHeaders {
+    /// Creates a `SecurityHeaders` with the default settings.
+    pub fn new() -> Self {
+        SecurityHeaders::default()
+    }
+
+    /// Sets the `max-age` of the `Strict-Transport-Security` header, or disables the header.
+    pub fn hsts(mut self, max_age: Option<Duration>) -> Self {
+        self.hsts_max_age = max_age;
+        self
+    }
+
+    /// Sets whether the `Strict-Transport-Security` header applies to subdomains.
+    pub fn hsts_include_subdomains(mut self, include_subdomains: bool) -> Self {
+        self.hsts_include_subdomains = include_subdomains;
+        self
+    }
+
+    /// Sets whether the `Strict-Transport-Security` header includes the `preload` directive.
+    pub fn hsts_preload(mut self, preload: bool) -> Self {
+        self.hsts_preload = preload;
+        self
+    }
+
+    /// Sets whether the `X-Content-Type-Options: nosniff` header is sent.
+    pub fn content_type_options(mut self, nosniff: bool) -> Self {
+        self.content_type_options = nosniff;
+        self
+    }
+
+    /// Sets the value of the `X-Frame-Options` header (e.g. `SAMEORIGIN`), or disables the header.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is not a valid header value.
+    pub fn frame_options(mut self, value: Option<&str>) -> Self {
+        self.frame_options = value.map(|value| header_value("X-Frame-Options", value));
+        self
+    }
+
+    /// Sets the value of the `Referrer-Policy` header (e.g. `no-referrer`), or disables the header.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is not a valid header value.
+    pub fn referrer_policy(mut self, value: Option<&str>) -> Self {
+        self.referrer_policy = value.map(|value| header_value("Referrer-Policy", value));
+        self
+    }
+
+    /// Sets the value of the `Content-Security-Policy` header, or disables the header.
+    ///
+    /// Any occurrences of `{nonce}` in the policy are replaced with a [`CspNonce`] which is
+    /// generated for each request, e.g. `script-src 'self' 'nonce-{nonce}'`.
+    ///
+    /// [`CspNonce`]: struct.CspNonce.html
+    ///
+    /// # Panics
+    ///
+    /// Panics if the policy is not a valid header value.
+    pub fn content_security_policy(mut self, policy: Option<&str>) -> Self {
+        self.content_security_policy = policy.map(|policy| {
+            header_value("Content-Security-Policy", policy);
+            policy.to_owned()
+        });
+        self
+    }
+
+    fn uses_nonce(&self) -> bool {
+        match self.content_security_policy {
+            Some(ref policy) => policy.contains(NONCE_PLACEHOLDER),
+            None => false,
+        }
+    }
+
+    fn hsts_value(&self, max_age: Duration) -> HeaderValue {
+        let mut value = format!("max-age={}", max_age.as_secs());
+        if self.hsts_include_subdomains {
+            value.push_str("; includeSubDomains");
+        }
+        if self.hsts_preload {
+            value.push_str("; preload");
+        }
+        header_value("Strict-Transport-Security", &value)
+    }
+
+    fn apply(&self, headers: &mut HeaderMap, nonce: Option<&CspNonce>) {
+        let mut set = |name: HeaderName, value: HeaderValue| {
+            if !headers.contains_key(&name) {
+                headers.insert(name, value);
+            }
+        };
+
+        if let Some(max_age) = self.hsts_max_age {
+            set(header::STRICT_TRANSPORT_SECURITY, self.hsts_value(max_age));
+        }
+        if self.content_type_options {
+            set(
+                header::X_CONTENT_TYPE_OPTIONS,
+                HeaderValue::from_static("nosniff"),
+            );
+        }
+        if let Some(ref value) = self.frame_options {
+            set(header::X_FRAME_OPTIONS, value.clone());
+        }
+        if let Some(ref value) = self.referrer_policy {
+            set(header::REFERRER_POLICY, value.clone());
+        }
+        if let Some(ref policy) = self.content_security_policy {
+            let policy = match nonce {
+                Some(nonce) => policy.replace(NONCE_PLACEHOLDER, nonce.as_str()),
+                None => policy.clone(),
+            };
+            set(
+                header::CONTENT_SECURITY_POLICY,
+                header_value("Content-Security-Policy", &policy),
+            );
+        }
+    }
+}
+
+/// Middleware which sets security-related headers on all responses.
+///
+/// The headers set are configured by the provided [`SecurityHeaders`]. A header is not set if the
+/// inner handler has already set it on its response.
+///
+/// To use a different configuration for some routes, wrap those routes' handlers in their own
+/// `with_security_headers`. The configuration of the innermost middleware applies to the whole
+/// response: outer middlewares leave responses which it has handled unchanged, even if it disabled
+/// some headers.
+///
+/// If the Content-Security-Policy contains a `{nonce}` placeholder, a [`CspNonce`] is generated for
+/// each request and stored in its extensions.
+///
+/// [`SecurityHeaders`]: struct.SecurityHeaders.html
+/// [`CspNonce`]: struct.CspNonce.html
+///
+/// # Example
+///
+/// ```no_run
+/// extern crate aitch;
+/// extern crate http;
+///
+/// use aitch::middlewares::{self, CspNonce, SecurityHeaders, SimpleRouter};
+/// use aitch::servers::hyper::Server;
+/// use aitch::{Responder, ResponseBuilder, Result};
+/// use http::Request;
+///
+/// fn page(req: Request<()>, mut resp: ResponseBuilder) -> impl Responder {
+///     let nonce = req.extensions().get::<CspNonce>().unwrap();
+///     let html = format!("<script nonce=\"{}\">alert('Hello!')</script>", nonce);
+///     resp.header(http::header::CONTENT_TYPE, "text/html").body(html)
+/// }
+///
+/// fn widget(_req: Request<()>, mut resp: ResponseBuilder) -> impl Responder {
+///     resp.body("This page may be embedded by other sites".to_owned())
+/// }
+///
+/// fn main() -> Result<()> {
+///     let mut router = SimpleRouter::new();
+///     router.register_handler("/", page);
+///
+///     // Allow the widget to be framed.
+///     let embeddable = SecurityHeaders::new()
+///         .frame_options(None)
+///         .content_security_policy(Some("default-src 'self'"));
+///     router.register_handler(
+///         "/widget",
+///         middlewares::with_security_headers(embeddable, widget),
+///     );
+///
+///     let headers = SecurityHeaders::new()
+///         .content_security_policy(Some("default-src 'self'; script-src 'nonce-{nonce}'"));
+///     let wrapped = middlewares::with_security_headers(headers, router);
+///
+///     let addr = "127.0.0.1:3000".parse()?;
+///     Server::new(addr, wrapped)?.run()
+/// }
+/// ```
+pub fn with_security_headers<B: Body>(
+    config: SecurityHeaders,
+    handler: impl Handler<B>,
+) -> impl Handler<B> {
+    move |mut req: http::Request<B>, resp: ResponseBuilder| -> BoxedResponse {
+        let nonce = if config.uses_nonce() {
+            let nonce = CspNonce(random::hex_token(16));
+            req.extensions_mut().insert(nonce.clone());
+            Some(nonce)
+        } else {
+            None
+        };
+
+        let config = config.clone();
+        let fut = handler
+            .handle(req, resp)
+            .into_response()
+            .map(move |mut resp| {
+                if resp.extensions().get::<SecurityHeadersApplied>().is_none() {
+                    config.apply(resp.headers_mut(), nonce.as_ref());
+                    resp.extensions_mut().insert(SecurityHeadersApplied);
+                }
+                resp
+            });
+        Box::new(fut)
+    }
+}
@@ -0,0 +1,247 @@
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use futures::{future, Future};
+use http;
+use http::header;
+use serde_json::{Map, Value};
+
+use cookies::{Cookie, CookieJar, InvalidCookie, SameSite};
+use random;
+use sessions::{Session, SessionRecord, SessionStore, StoreFuture};
+use {Body, BodyStream, BoxedResponse, Error, Handler, Responder, ResponseBuilder};
+
+/// Configuration for the [`with_sessions`] middleware.
+///
+/// By default, the session ID is stored in a cookie named `session`, with `Path=/`, `HttpOnly`
+/// and `SameSite=Lax`. Sessions expire after an hour without any requests, or a day after they
+/// were created, whichever is sooner.
+///
+/// [`with_sessions`]: fn.with_sessions.html
+#[derive(Clone)]
+pub struct SessionConfig {
+    store: Arc<SessionStore>,
+    cookie: Cookie,
+    idle_timeout: Duration,
+    absolute_timeout: Duration,
+}
+
+impl SessionConfig {
+    /// Creates a `SessionConfig` which keeps sessions in the given store.
+    pub fn new<S: SessionStore>(store: S) -> Self {
+        SessionConfig {
+            store: Arc::new(store),
+            cookie: Cookie::named("session")
+                .path("/")
+                .http_only(true)
+                .same_site(SameSite::Lax),
+            idle_timeout: Duration::from_secs(60 * 60),
+            absolute_timeout: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+
+    /// Sets the cookie used to store the session ID.
+    ///
+    /// The provided cookie is used as a template: its name and attributes are used for the session
+    /// cookie, and its value is ignored. Applications served over HTTPS should set `Secure`.
+    pub fn cookie(mut self, cookie: Cookie) -> Self {
+        self.cookie = cookie;
+        self
+    }
+
+    /// Sets the time after which a session expires, if the client makes no requests.
+    ///
+    /// A timeout which is too large to be added to the current time (e.g. `Duration::from_secs(
+    /// u64::MAX)`) means that sessions never expire due to inactivity.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// Sets the time after which a session expires, regardless of activity.
+    ///
+    /// As with [`idle_timeout`], a timeout which is too large to be added to the current time means
+    /// that sessions never reach it.
+    ///
+    /// [`idle_timeout`]: #method.idle_timeout
+    pub fn absolute_timeout(mut self, timeout: Duration) -> Self {
+        self.absolute_timeout = timeout;
+        self
+    }
+
+    fn is_valid(&self, record: &SessionRecord, now: SystemTime) -> bool {
+        let deadline = record.created.checked_add(self.absolute_timeout);
+        !record.is_expired(now) && deadline.is_none_or(|deadline| now < deadline)
+    }
+
+    fn record(
+        &self,
+        data: Map<String, Value>,
+        created: SystemTime,
+        now: SystemTime,
+    ) -> SessionRecord {
+        // Timeouts which overflow are treated as never expiring.
+        let idle = now.checked_add(self.idle_timeout);
+        let absolute = created.checked_add(self.absolute_timeout);
+        let expires = match (idle, absolute) {
+            (Some(idle), Some(absolute)) => Some(idle.min(absolute)),
+            (idle, absolute) => idle.or(absolute),
+        };
+        SessionRecord {
+            data,
+            created,
+            expires,
+        }
+    }
+
+    fn set_cookie(
+        &self,
+        resp: &mut http::Response<BodyStream>,
+        cookie: &Cookie,
+    ) -> Result<(), InvalidCookie> {
+        resp.headers_mut()
+            .append(header::SET_COOKIE, cookie.to_header_value()?);
+        Ok(())
+    }
+}
+
+// Loads the session identified by the request's cookie, returning it along with the ID of any
+// expired session that should be removed from the store.
+fn load_session(
+    config: &SessionConfig,
+    id: Option<String>,
+) -> StoreFuture<(Session, Option<String>)> {
+    let id = match id {
+        Some(id) => id,
+        None => return Box::new(future::ok((Session::new(SystemTime::now()), None))),
+    };
+
+    let config = config.clone();
+    let fut = config.store.load(&id).map(move |record| {
+        let now = SystemTime::now();
+        match record {
+            Some(ref record) if config.is_valid(record, now) => {
+                (Session::load(id, record.clone()), None)
+            }
+            Some(_) => (Session::new(now), Some(id)),
+            None => (Session::new(now), None),
+        }
+    });
+    Box::new(fut)
+}
+
+// Updates the store once the handler has responded, and sets or removes the session cookie.
+fn save_session(
+    config: &SessionConfig,
+    session: &Session,
+    expired: Option<String>,
+    resp: &mut http::Response<BodyStream>,
+) -> Result<Vec<StoreFuture<()>>, InvalidCookie> {
+    let changes = session.take_changes();
+    let now = SystemTime::now();
+
+    let mut ops = Vec::new();
+    if let Some(ref id) = expired {
+        ops.push(config.store.remove(id));
+    }
+
+    if changes.destroyed {
+        if let Some(ref id) = changes.id {
+            ops.push(config.store.remove(id));
+        }
+        if changes.id.is_some() || expired.is_some() {
+            config.set_cookie(resp, &config.cookie.clone().into_removal())?;
+        }
+        return Ok(ops);
+    }
+
+    let new_session = changes.id.is_none() && (changes.changed || !changes.data.is_empty());
+    if changes.regenerate || new_session {
+        if let Some(ref id) = changes.id {
+            ops.push(config.store.remove(id));
+        }
+        let id = random::hex_token(32);
+        let record = config.record(changes.data, changes.created, now);
+        ops.push(config.store.save(&id, record));
+        let mut cookie = config.cookie.clone();
+        cookie.set_value(id);
+        config.set_cookie(resp, &cookie)?;
+    } else if let Some(ref id) = changes.id {
+        // Existing sessions are saved after each request, to extend their idle timeout.
+        let record = config.record(changes.data, changes.created, now);
+        ops.push(config.store.save(id, record));
+    }
+    Ok(ops)
+}
+
+/// Middleware which provides a server-side [`Session`] to the wrapped handler.
+///
+/// The session is identified by a cookie containing a random session ID, and its data is loaded
+/// from the [`SessionStore`] set on the provided [`SessionConfig`] before the handler is called.
+/// Once the handler's [`Responder`] has resolved, the session is saved to the store (extending its
+/// idle timeout), and the session cookie is set if the session has a new ID.
+///
+/// New sessions are only saved (and sent to the client) once a value has been stored in them.
+/// Sessions which have expired are treated as new sessions. Errors from the store cause the
+/// request to fail.
+///
+/// This middleware requires the `sessions` feature, which is enabled by default.
+///
+/// [`Session`]: ../sessions/struct.Session.html
+/// [`SessionStore`]: ../sessions/trait.SessionStore.html
+/// [`SessionConfig`]: struct.SessionConfig.html
+/// [`Responder`]: ../trait.Responder.html
+///
+/// # Example
+///
+/// ```no_run
+/// extern crate aitch;
+/// extern crate http;
+///
+/// use aitch::middlewares::{self, SessionConfig};
+/// use aitch::servers::hyper::Server;
+/// use aitch::sessions::{MemoryStore, Session};
+/// use aitch::{Responder, ResponseBuilder, Result};
+/// use http::Request;
+///
+/// fn login(req: Request<()>, mut resp: ResponseBuilder) -> impl Responder {
+///     let session = req.extensions().get::<Session>().unwrap();
+///     // Prevent session fixation, by giving the session a new ID when logging in.
+///     session.regenerate();
+///     session.insert("user", "alice").unwrap();
+///     resp.body("Logged in".to_owned())
+/// }
+///
+/// fn main() -> Result<()> {
+///     let config = SessionConfig::new(MemoryStore::new());
+///     let wrapped = middlewares::with_sessions(config, login);
+///
+///     let addr = "127.0.0.1:3000".parse()?;
+///     Server::new(addr, wrapped)?.run()
+/// }
+/// ```
+pub fn with_sessions<B: Body>(config: SessionConfig, handler: impl Handler<B>) -> impl Handler<B> {
+    let handler = Arc::new(handler);
+    move |mut req: http::Request<B>, resp: ResponseBuilder| -> BoxedResponse {
+        let id = CookieJar::from_headers(req.headers())
+            .get(config.cookie.name())
+            .map(|cookie| cookie.value().to_owned());
+
+        let handler = handler.clone();
+        let config = config.clone();
+        let fut = load_session(&config, id).and_then(move |(session, expired)| {
+            req.extensions_mut().insert(session.clone());
+            handler
+                .handle(req, resp)
+                .into_response()
+                .and_then(move |mut resp| {
+                    let ops = save_session(&config, &session, expired, &mut resp);
+                    future::result(ops)
+                        .map_err(Error::from)
+                        .and_then(future::join_all)
+                        .map(move |_| resp)
+                })
+        });
+        Box::new(fut)
+    }
+}
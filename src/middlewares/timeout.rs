@@ -0,0 +1,207 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::future::{self, Either};
+use futures::{Async, Future, Poll, Stream};
+use futures_timer::Delay;
+use http;
+
+use {Body, BodyStream, BoxedResponse, Error, Handler, Responder, ResponseBuilder};
+
+/// An error returned when a request body is not received within the time allowed by
+/// [`with_body_timeout`].
+///
+/// [`with_body_timeout`] responds to requests which fail with this error, so it is only seen by
+/// handlers which read the body themselves (e.g. those which use a [`BodyStream`]).
+///
+/// [`with_body_timeout`]: fn.with_body_timeout.html
+/// [`BodyStream`]: ../type.BodyStream.html
+#[derive(Debug)]
+pub struct BodyTimeoutError {
+    timeout: Duration,
+}
+
+impl BodyTimeoutError {
+    /// Returns the time that was allowed to receive the request body.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+}
+
+impl fmt::Display for BodyTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "request body was not received within {}ms",
+            self.timeout.as_millis()
+        )
+    }
+}
+
+impl StdError for BodyTimeoutError {
+    fn description(&self) -> &str {
+        "timed out reading request body"
+    }
+}
+
+fn simple_response(status: http::StatusCode, body: &'static str) -> http::Response<BodyStream> {
+    let mut resp = http::Response::new(Bytes::from_static(body.as_bytes()).into_stream());
+    *resp.status_mut() = status;
+    resp
+}
+
+/// Middleware which limits the time taken by a handler to respond to a request.
+///
+/// If the inner handler's [`Responder`] does not resolve to a response within the given duration,
+/// it is dropped (cancelling any work it has yet to do) and a `503 Service Unavailable` response is
+/// returned instead.
+///
+/// The timeout applies to the time taken for the handler to return the response headers. It does
+/// not apply to the time taken to send a streaming response body.
+///
+/// [`Responder`]: ../trait.Responder.html
+///
+/// # Example
+///
+/// ```no_run
+/// extern crate aitch;
+/// extern crate http;
+///
+/// use std::time::Duration;
+///
+/// use aitch::servers::hyper::Server;
+/// use aitch::{middlewares, Responder, ResponseBuilder, Result};
+/// use http::Request;
+///
+/// fn handler(_req: Request<()>, mut resp: ResponseBuilder) -> impl Responder {
+///     resp.body("Hello, world!".to_owned())
+/// }
+///
+/// fn main() -> Result<()> {
+///     let wrapped = middlewares::with_timeout(Duration::from_secs(30), handler);
+///
+///     let addr = "127.0.0.1:3000".parse()?;
+///     Server::new(addr, wrapped)?.run()
+/// }
+/// ```
+pub fn with_timeout<B: Body>(duration: Duration, handler: impl Handler<B>) -> impl Handler<B> {
+    move |req: http::Request<B>, resp: ResponseBuilder| -> BoxedResponse {
+        let fut = handler
+            .handle(req, resp)
+            .into_response()
+            .select2(Delay::new(duration))
+            .then(|result| match result {
+                Ok(Either::A((resp, _))) => Ok(resp),
+                Err(Either::A((err, _))) => Err(err),
+                Ok(Either::B(_)) => Ok(simple_response(
+                    http::StatusCode::SERVICE_UNAVAILABLE,
+                    "request timed out",
+                )),
+                Err(Either::B((err, _))) => Err(Error::from(err)),
+            });
+        Box::new(fut)
+    }
+}
+
+// Wraps a request body, failing with a `BodyTimeoutError` if it has not been fully received
+// before the deadline.
+struct TimedBody {
+    inner: BodyStream,
+    delay: Option<Delay>,
+    timeout: Duration,
+}
+
+impl Stream for TimedBody {
+    type Item = Bytes;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Bytes>, Error> {
+        if let Some(ref mut delay) = self.delay {
+            if let Async::Ready(()) = delay.poll()? {
+                return Err(Box::new(BodyTimeoutError {
+                    timeout: self.timeout,
+                }));
+            }
+        }
+
+        let polled = self.inner.poll()?;
+        if let Async::Ready(None) = polled {
+            self.delay = None;
+        }
+        Ok(polled)
+    }
+}
+
+/// Middleware which limits the time taken for a client to send the request body.
+///
+/// The timeout starts when the request is received by this middleware (i.e. once its headers have
+/// been received). If the body has not been fully received within the given duration, a
+/// `408 Request Timeout` response is returned. This stops slow (or malicious) clients from tying
+/// up resources.
+///
+/// The inner handler's body type is created from the timed body stream, so the handler is only
+/// called once the body has been received (unless it uses a [`BodyStream`], in which case it will
+/// see a [`BodyTimeoutError`] while reading the body).
+///
+/// [`BodyStream`]: ../type.BodyStream.html
+/// [`BodyTimeoutError`]: struct.BodyTimeoutError.html
+///
+/// # Example
+///
+/// ```no_run
+/// extern crate aitch;
+/// extern crate http;
+///
+/// use std::time::Duration;
+///
+/// use aitch::servers::hyper::Server;
+/// use aitch::{middlewares, Responder, ResponseBuilder, Result};
+/// use http::Request;
+///
+/// fn handler(req: Request<Vec<u8>>, mut resp: ResponseBuilder) -> impl Responder {
+///     resp.body(format!("Received {} bytes", req.body().len()))
+/// }
+///
+/// fn main() -> Result<()> {
+///     let handler = middlewares::with_body_timeout(Duration::from_secs(10), handler);
+///     let wrapped = middlewares::with_timeout(Duration::from_secs(30), handler);
+///
+///     let addr = "127.0.0.1:3000".parse()?;
+///     Server::new(addr, wrapped)?.run()
+/// }
+/// ```
+pub fn with_body_timeout<B: Body>(
+    duration: Duration,
+    handler: impl Handler<B>,
+) -> impl Handler<BodyStream> {
+    let handler = Arc::new(handler);
+    move |req: http::Request<BodyStream>, resp: ResponseBuilder| -> BoxedResponse {
+        let (parts, body) = req.into_parts();
+        let body = TimedBody {
+            inner: body,
+            delay: Some(Delay::new(duration)),
+            timeout: duration,
+        };
+
+        let handler = handler.clone();
+        let fut = B::from_stream(Box::new(body))
+            .and_then(move |body| {
+                let req = http::Request::from_parts(parts, body);
+                handler.handle(req, resp).into_response()
+            })
+            .or_else(|err| -> BoxedResponse {
+                if err.is::<BodyTimeoutError>() {
+                    Box::new(future::ok(simple_response(
+                        http::StatusCode::REQUEST_TIMEOUT,
+                        "request body timed out",
+                    )))
+                } else {
+                    Box::new(future::err(err))
+                }
+            });
+        Box::new(fut)
+    }
+}
@@ -0,0 +1,13 @@
+use rand::{thread_rng, RngCore};
+
+/// Returns `len` cryptographically secure random bytes.
+pub fn bytes(len: usize) -> Vec<u8> {
+    let mut buf = vec![0; len];
+    thread_rng().fill_bytes(&mut buf);
+    buf
+}
+
+/// Returns a random token, containing `len` random bytes encoded as lowercase hex.
+pub fn hex_token(len: usize) -> String {
+    bytes(len).iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,455 @@
+//! A test suite, which checks that a [`Server`] back-end behaves consistently with the provided
+//! servers.
+//!
+//! The suite starts the server on a random local port, and sends it real HTTP requests using a
+//! small blocking HTTP client. It covers echoing request bodies, status codes, request/response
+//! headers, large bodies, handler errors and shutdown.
+//!
+//! All tests serve the same [`handler`], so that they only require the back-end to implement
+//! `Server<ConformanceHandler, Bytes>`.
+//!
+//! [`Server`]: ../trait.Server.html
+//! [`handler`]: fn.handler.html
+//!
+//! # Example
+//!
+//! The [`server_conformance_tests!`] macro generates a `#[test]` function for each test in the
+//! suite. It should be passed the type of the server, with its type parameters left to be inferred:
+//!
+//! ```ignore
+//! #[macro_use]
+//! extern crate aitch;
+//!
+//! server_conformance_tests!(my_crate::MyServer<_, _>);
+//! ```
+//!
+//! [`server_conformance_tests!`]: ../../macro.server_conformance_tests.html
+
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::str;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use bytes::Bytes;
+use http;
+
+use servers::{Server, ShutdownHandle};
+use {ResponseBuilder, Result};
+
+/// The size of the response body returned from `/large`, and of the request body sent by the
+/// [`large_bodies`] test.
+///
+/// [`large_bodies`]: fn.large_bodies.html
+pub const LARGE_BODY_SIZE: usize = 4 * 1024 * 1024;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The type of the [`handler`] served by all tests in the suite.
+///
+/// [`handler`]: fn.handler.html
+pub type ConformanceHandler =
+    fn(http::Request<Bytes>, ResponseBuilder) -> Result<http::Response<Bytes>>;
+
+/// The handler served by all tests in the suite.
+///
+/// It responds to the following paths:
+///
+///  - `/echo`: responds with the request's body.
+///  - `/status/<code>`: responds with the given status code.
+///  - `/headers`: copies the value of the `X-Conformance-Request` request header into the
+///    `X-Conformance-Response` response header, and sets two values for `X-Conformance-Multi`.
+///  - `/large`: responds with a body of [`LARGE_BODY_SIZE`] bytes.
+///  - `/error`: returns an error.
+///
+/// Any other path results in a `404 Not Found`.
+///
+/// [`LARGE_BODY_SIZE`]: constant.LARGE_BODY_SIZE.html
+pub fn handler(
+    req: http::Request<Bytes>,
+    mut resp: ResponseBuilder,
+) -> Result<http::Response<Bytes>> {
+    let path = req.uri().path().to_owned();
+    let resp = match path.as_str() {
+        "/echo" => resp.body(req.into_body())?,
+        "/headers" => {
+            if let Some(value) = req.headers().get("X-Conformance-Request") {
+                resp.header("X-Conformance-Response", value.clone());
+            }
+            resp.header("X-Conformance-Multi", "a")
+                .header("X-Conformance-Multi", "b")
+                .body(Bytes::new())?
+        }
+        "/large" => resp.body(large_body())?,
+        "/error" => return Err("conformance handler error".into()),
+        path if path.starts_with("/status/") => {
+            let status = http::StatusCode::from_bytes(&path.as_bytes()["/status/".len()..])?;
+            resp.status(status).body(Bytes::new())?
+        }
+        _ => resp
+            .status(http::StatusCode::NOT_FOUND)
+            .body(Bytes::new())?,
+    };
+    Ok(resp)
+}
+
+fn large_body() -> Bytes {
+    (0..LARGE_BODY_SIZE)
+        .map(|i| (i % 251) as u8)
+        .collect::<Vec<u8>>()
+        .into()
+}
+
+/// A response received by the [`request`] client.
+///
+/// [`request`]: fn.request.html
+#[derive(Debug)]
+pub struct Response {
+    /// The status code of the response.
+    pub status: u16,
+    /// The headers of the response, in the order they were received.
+    pub headers: Vec<(String, String)>,
+    /// The (de-chunked) body of the response.
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// Returns all values of the header with the given name.
+    pub fn header_values(&self, name: &str) -> Vec<&str> {
+        self.headers
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+
+    /// Returns the first value of the header with the given name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.header_values(name).into_iter().next()
+    }
+}
+
+/// Sends a HTTP/1.1 request to a server, and waits for the full response.
+///
+/// This is a deliberately simple client, which sends `Connection: close` and reads the response
+/// until the server closes the connection.
+pub fn request(
+    addr: SocketAddr,
+    method: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> io::Result<Response> {
+    let mut stream = TcpStream::connect(addr)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    let mut head = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n",
+        method, path, addr
+    );
+    for &(name, value) in headers {
+        head += &format!("{}: {}\r\n", name, value);
+    }
+    head += &format!("Content-Length: {}\r\n\r\n", body.len());
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body)?;
+    stream.flush()?;
+
+    let mut raw = Vec::new();
+    stream.read_to_end(&mut raw)?;
+    parse_response(&raw)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_owned())
+}
+
+fn parse_response(raw: &[u8]) -> io::Result<Response> {
+    let split = raw
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| invalid("incomplete response head"))?;
+    let head = str::from_utf8(&raw[..split]).map_err(|_| invalid("response head is not UTF-8"))?;
+    let body = &raw[split + 4..];
+
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| invalid("invalid status line"))?;
+
+    let mut headers = Vec::new();
+    for line in lines {
+        let colon = line.find(':').ok_or_else(|| invalid("invalid header"))?;
+        let (name, value) = line.split_at(colon);
+        headers.push((name.trim().to_owned(), value[1..].trim().to_owned()));
+    }
+
+    let mut resp = Response {
+        status,
+        headers,
+        body: Vec::new(),
+    };
+    let chunked = resp
+        .header_values("Transfer-Encoding")
+        .iter()
+        .any(|value| value.eq_ignore_ascii_case("chunked"));
+    resp.body = if chunked {
+        dechunk(body)?
+    } else {
+        body.to_vec()
+    };
+    Ok(resp)
+}
+
+fn dechunk(mut raw: &[u8]) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = raw
+            .windows(2)
+            .position(|window| window == b"\r\n")
+            .ok_or_else(|| invalid("incomplete chunk size"))?;
+        let size = str::from_utf8(&raw[..line_end])
+            .ok()
+            .and_then(|line| line.split(';').next())
+            .and_then(|size| usize::from_str_radix(size.trim(), 16).ok())
+            .ok_or_else(|| invalid("invalid chunk size"))?;
+        raw = &raw[line_end + 2..];
+        if size == 0 {
+            return Ok(body);
+        }
+        if raw.len() < size + 2 {
+            return Err(invalid("incomplete chunk"));
+        }
+        body.extend_from_slice(&raw[..size]);
+        raw = &raw[size + 2..];
+    }
+}
+
+/// A server started by the test suite, running on a background thread.
+///
+/// The server is shut down when this is dropped.
+pub struct RunningServer {
+    addr: SocketAddr,
+    shutdown: ShutdownHandle,
+    finished: mpsc::Receiver<Result<()>>,
+}
+
+impl RunningServer {
+    /// Starts a server of type `S` on a random local port, serving the conformance [`handler`].
+    ///
+    /// [`handler`]: fn.handler.html
+    pub fn start<S>() -> RunningServer
+    where
+        S: Server<ConformanceHandler, Bytes> + Send + 'static,
+    {
+        let addr = "127.0.0.1:0".parse().unwrap();
+        let server = S::new(addr, handler as ConformanceHandler).expect("failed to create server");
+        let addr = server.addr();
+        let shutdown = server.shutdown_handle();
+
+        let (tx, finished) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = tx.send(server.run());
+        });
+
+        RunningServer {
+            addr,
+            shutdown,
+            finished,
+        }
+    }
+
+    /// Returns the address that the server is listening on.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Sends a request to the server.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the request fails.
+    pub fn request(
+        &self,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> Response {
+        match request(self.addr, method, path, headers, body) {
+            Ok(resp) => resp,
+            Err(err) => panic!("{} {} failed: {}", method, path, err),
+        }
+    }
+
+    /// Shuts down the server, and waits for its `run()` method to return.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the server doesn't stop within a few seconds, or if `run()` returns an error.
+    pub fn stop(self) {
+        self.shutdown.shutdown();
+        match self.finished.recv_timeout(TIMEOUT) {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => panic!("server returned an error: {}", err),
+            Err(_) => panic!("server did not shut down"),
+        }
+    }
+}
+
+impl Drop for RunningServer {
+    fn drop(&mut self) {
+        self.shutdown.shutdown();
+    }
+}
+
+/// Checks that request bodies are passed to the handler, and response bodies returned to the
+/// client.
+pub fn echo<S>()
+where
+    S: Server<ConformanceHandler, Bytes> + Send + 'static,
+{
+    let server = RunningServer::start::<S>();
+
+    let resp = server.request("POST", "/echo", &[], b"some body");
+    assert_eq!(resp.status, 200);
+    assert_eq!(resp.body, b"some body");
+
+    let resp = server.request("GET", "/echo", &[], b"");
+    assert_eq!(resp.status, 200);
+    assert_eq!(resp.body, b"");
+}
+
+/// Checks that status codes set by the handler are returned to the client.
+pub fn status_codes<S>()
+where
+    S: Server<ConformanceHandler, Bytes> + Send + 'static,
+{
+    let server = RunningServer::start::<S>();
+
+    for &status in &[200, 201, 202, 301, 400, 403, 404, 418, 500, 503] {
+        let resp = server.request("GET", &format!("/status/{}", status), &[], b"");
+        assert_eq!(resp.status, status);
+    }
+
+    let resp = server.request("GET", "/not-found", &[], b"");
+    assert_eq!(resp.status, 404);
+}
+
+/// Checks that request headers are passed to the handler, and that response headers (including
+/// repeated headers) are returned to the client.
+pub fn headers<S>()
+where
+    S: Server<ConformanceHandler, Bytes> + Send + 'static,
+{
+    let server = RunningServer::start::<S>();
+
+    let headers = [("X-Conformance-Request", "some value")];
+    let resp = server.request("GET", "/headers", &headers, b"");
+    assert_eq!(resp.status, 200);
+    assert_eq!(resp.header("X-Conformance-Response"), Some("some value"));
+
+    let mut multi = resp.header_values("X-Conformance-Multi");
+    multi.sort();
+    assert_eq!(multi, vec!["a", "b"]);
+}
+
+/// Checks that large request and response bodies are transferred intact.
+pub fn large_bodies<S>()
+where
+    S: Server<ConformanceHandler, Bytes> + Send + 'static,
+{
+    let server = RunningServer::start::<S>();
+    let expected = large_body();
+
+    let resp = server.request("GET", "/large", &[], b"");
+    assert_eq!(resp.status, 200);
+    assert!(resp.body == expected, "large response body was corrupted");
+
+    let resp = server.request("POST", "/echo", &[], &expected);
+    assert_eq!(resp.status, 200);
+    assert!(resp.body == expected, "large request body was corrupted");
+}
+
+/// Checks that errors returned by the handler result in a `500 Internal Server Error`.
+pub fn errors<S>()
+where
+    S: Server<ConformanceHandler, Bytes> + Send + 'static,
+{
+    let server = RunningServer::start::<S>();
+
+    let resp = server.request("GET", "/error", &[], b"");
+    assert_eq!(resp.status, 500);
+
+    // The server should continue to serve requests after an error.
+    let resp = server.request("POST", "/echo", &[], b"still alive");
+    assert_eq!(resp.status, 200);
+    assert_eq!(resp.body, b"still alive");
+}
+
+/// Checks that the server stops running when shutdown is requested.
+pub fn shutdown<S>()
+where
+    S: Server<ConformanceHandler, Bytes> + Send + 'static,
+{
+    let server = RunningServer::start::<S>();
+
+    let resp = server.request("POST", "/echo", &[], b"before shutdown");
+    assert_eq!(resp.status, 200);
+
+    server.stop();
+}
+
+/// Generates a `#[test]` function for each test in the [conformance test suite].
+///
+/// The macro should be passed the type of the server, with its type parameters left to be
+/// inferred.
+///
+/// [conformance test suite]: servers/conformance/index.html
+///
+/// # Example
+///
+/// ```ignore
+/// #[macro_use]
+/// extern crate aitch;
+///
+/// server_conformance_tests!(aitch::servers::hyper::Server<_, _>);
+/// ```
+#[macro_export]
+macro_rules! server_conformance_tests {
+    ($server:ty) => {
+        #[test]
+        fn conformance_echo() {
+            $crate::servers::conformance::echo::<$server>();
+        }
+
+        #[test]
+        fn conformance_status_codes() {
+            $crate::servers::conformance::status_codes::<$server>();
+        }
+
+        #[test]
+        fn conformance_headers() {
+            $crate::servers::conformance::headers::<$server>();
+        }
+
+        #[test]
+        fn conformance_large_bodies() {
+            $crate::servers::conformance::large_bodies::<$server>();
+        }
+
+        #[test]
+        fn conformance_errors() {
+            $crate::servers::conformance::errors::<$server>();
+        }
+
+        #[test]
+        fn conformance_shutdown() {
+            $crate::servers::conformance::shutdown::<$server>();
+        }
+    };
+}
@@ -13,27 +13,15 @@ use std::marker::PhantomData;
 use std::net::SocketAddr;
 use std::sync::Arc;
 
+use bytes::Bytes;
 use futures::{Future, Stream};
 use http;
 use hyper;
+use hyper::server::conn::{AddrIncoming, AddrStream};
 use hyper::server::Server as HyperServer;
 
-use {Body, BodyStream, Error, Handler, Responder, Result};
-
-// Works around lack of Box<FnOnce>/FnBox.
-pub trait ServeFunc {
-    fn call_box(self: Box<Self>) -> Result<()>;
-}
-
-impl<F> ServeFunc for F
-where
-    F: FnOnce() -> Result<()>,
-{
-    #[cfg_attr(feature = "cargo-clippy", allow(boxed_local))]
-    fn call_box(self: Box<Self>) -> Result<()> {
-        (*self)()
-    }
-}
+use super::{serve, ConnectionInfo, ErrorHandling, RequestMetadata, ShutdownHandle};
+use {servers, Body, BodyStream, Error, Handler, Result};
 
 /// A [`hyper`] server, which can serve a handler.
 ///
@@ -72,9 +60,11 @@ where
     H: Handler<ReqBody>,
     ReqBody: Body,
 {
-    addr: SocketAddr,
-    serve: Box<ServeFunc + Send>,
-    marker: PhantomData<(H, ReqBody)>,
+    incoming: AddrIncoming,
+    handler: Arc<H>,
+    errors: ErrorHandling,
+    shutdown: ShutdownHandle,
+    marker: PhantomData<ReqBody>,
 }
 
 impl<H, ReqBody> Server<H, ReqBody>
@@ -88,74 +78,112 @@ where
     /// [`SocketAddr`]: https://doc.rust-lang.org/std/net/enum.SocketAddr.html
     /// [`Handler`]: ../../trait.Handler.html
     pub fn new(addr: SocketAddr, handler: H) -> Result<Server<H, ReqBody>> {
-        let (addr, serve) = Server::construct_server(addr, handler);
+        let incoming = AddrIncoming::bind(&addr)?;
+        let handler = Arc::new(handler);
+        let errors = ErrorHandling::default();
+        let shutdown = ShutdownHandle::new();
         let marker = PhantomData;
         Ok(Server {
-            addr,
-            serve,
+            incoming,
+            handler,
+            errors,
+            shutdown,
             marker,
         })
     }
 
-    fn construct_server(addr: SocketAddr, handler: H) -> (SocketAddr, Box<ServeFunc + Send>) {
-        let handler = Arc::new(handler);
-        let new_service = move || {
-            let handler = handler.clone();
-            hyper::service::service_fn(move |req| {
-                let handler = handler.clone();
-                let builder = http::Response::builder();
-
-                map_request_body(req)
-                    .and_then(move |req| handler.handle(req, builder).into_response())
-                    .map(map_response_body)
-                    .or_else(|err| internal_server_error(&err))
-            })
-        };
-
-        let server = HyperServer::bind(&addr).serve(new_service);
-        let addr = server.local_addr();
-
-        let closure = move || {
-            hyper::rt::run(server.map_err(|e| {
-                eprintln!("server error: {}", e);
-            }));
-
-            Ok(())
-        };
+    /// Sets the callback which is used to report errors.
+    ///
+    /// The callback is called whenever the [`Handler`] returns an error, and whenever the
+    /// underlying [`hyper`] server fails. By default, errors are written to stderr.
+    ///
+    /// [`Handler`]: ../../trait.Handler.html
+    /// [`hyper`]: https://hyper.rs
+    pub fn with_error_hook<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&Error, Option<&RequestMetadata>) + Send + Sync + 'static,
+    {
+        self.errors.set_hook(Arc::new(hook));
+        self
+    }
 
-        (addr, Box::new(closure))
+    /// Sets the body of the `500 Internal Server Error` response, which is sent when the
+    /// [`Handler`] returns an error. By default, the body is empty.
+    ///
+    /// [`Handler`]: ../../trait.Handler.html
+    pub fn with_error_body<B: Into<Bytes>>(mut self, body: B) -> Self {
+        self.errors.set_body(body.into());
+        self
     }
 
     /// Returns the address that the server is listening on.
     pub fn addr(&self) -> SocketAddr {
-        self.addr
+        self.incoming.local_addr()
     }
 
-    /// Starts and runs the server.
+    /// Returns a [`ShutdownHandle`], which can be used to stop the server once it is running.
+    ///
+    /// [`ShutdownHandle`]: ../struct.ShutdownHandle.html
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Starts and runs the server, until it is shut down.
     pub fn run(self) -> Result<()> {
-        self.serve.call_box()
+        let handler = self.handler;
+        let errors = self.errors;
+        let local_addr = self.incoming.local_addr();
+
+        let service_errors = errors.clone();
+        let new_service = hyper::service::make_service_fn(move |conn: &AddrStream| {
+            let handler = handler.clone();
+            let errors = service_errors.clone();
+            let info = ConnectionInfo {
+                remote_addr: conn.remote_addr(),
+                local_addr,
+                secure: false,
+            };
+
+            let service = hyper::service::service_fn(move |req: http::Request<hyper::Body>| {
+                let (mut parts, body) = req.into_parts();
+                parts.extensions.insert(info);
+                let body_stream = body.map(hyper::Chunk::into_bytes).map_err(Box::from);
+                let req = http::Request::from_parts(parts, Box::new(body_stream) as BodyStream);
+
+                serve(&handler, &errors, req).map(map_response_body)
+            });
+            Ok::<_, Error>(service)
+        });
+
+        let server = HyperServer::builder(self.incoming)
+            .serve(new_service)
+            .with_graceful_shutdown(self.shutdown.signal());
+        hyper::rt::run(server.map_err(move |err| errors.report(&Error::from(err), None)));
+
+        Ok(())
     }
 }
 
-fn map_request_body<ReqBody>(
-    req: http::Request<hyper::Body>,
-) -> impl Future<Item = http::Request<ReqBody>, Error = Error>
+impl<H, ReqBody> servers::Server<H, ReqBody> for Server<H, ReqBody>
 where
+    H: Handler<ReqBody>,
     ReqBody: Body,
 {
-    let (parts, body) = req.into_parts();
-    let body_stream = body.map(hyper::Chunk::into_bytes).map_err(Box::from);
-    ReqBody::from_stream(Box::new(body_stream))
-        .map(move |body| http::Request::from_parts(parts, body))
-}
+    fn new(addr: SocketAddr, handler: H) -> Result<Self> {
+        Server::new(addr, handler)
+    }
 
-fn internal_server_error(err: &Error) -> Result<http::Response<hyper::Body>> {
-    eprintln!("server error: {}", err);
+    fn addr(&self) -> SocketAddr {
+        Server::addr(self)
+    }
+
+    fn shutdown_handle(&self) -> ShutdownHandle {
+        Server::shutdown_handle(self)
+    }
 
-    let resp = http::Response::builder()
-        .status(http::StatusCode::INTERNAL_SERVER_ERROR)
-        .body(hyper::Body::empty());
-    Ok(resp?)
+    fn run(self) -> Result<()> {
+        Server::run(self)
+    }
 }
 
 fn map_response_body(resp: http::Response<BodyStream>) -> http::Response<hyper::Body> {
@@ -1,7 +1,397 @@
 //! Server back-ends which can be used to serve a handler.
+//!
+//! Each back-end implements the [`Server`] trait, which allows applications (and tests) to be
+//! written independently of the server technology in use. Third-party crates can provide their own
+//! back-ends by implementing the same trait, and can check that their implementation behaves
+//! consistently with the provided servers by running the [`conformance`] test suite.
+//!
+//! [`Server`]: trait.Server.html
+//! [`conformance`]: conformance/index.html
+//!
+//! # Error Handling
+//!
+//! Both of the provided servers report errors (such as a [`Handler`] returning an error, or a
+//! failure to accept a connection) to an error hook, which can be configured using
+//! `with_error_hook()`. By default, errors are written to stderr. When a [`Handler`] fails, the
+//! server responds with a `500 Internal Server Error`, whose body can be configured using
+//! `with_error_body()`.
+//!
+//! [`Handler`]: ../trait.Handler.html
+//!
+//! ```no_run
+//! # extern crate aitch;
+//! # extern crate http;
+//! #
+//! # use aitch::{Responder, ResponseBuilder, Result};
+//! # use http::Request;
+//! #
+//! # fn handler(_req: Request<()>, mut resp: ResponseBuilder) -> impl Responder {
+//! #    resp.body("Hello, world!".to_owned())
+//! # }
+//! #
+//! # fn main() -> Result<()> {
+//! let addr = "127.0.0.1:3000".parse()?;
+//! aitch::servers::hyper::Server::new(addr, handler)?
+//!     .with_error_hook(|err, meta| match meta {
+//!         Some(meta) => eprintln!("error handling {}: {}", meta.uri, err),
+//!         None => eprintln!("server error: {}", err),
+//!     })
+//!     .with_error_body("Something went wrong!")
+//!     .run()
+//! # }
+//! ```
 
+pub mod conformance;
 #[cfg(feature = "server-hyper")]
 pub mod hyper;
 
 #[cfg(feature = "server-tiny-http")]
 pub mod tiny_http;
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+#[cfg(any(feature = "server-hyper", feature = "server-tiny-http"))]
+use bytes::Bytes;
+use futures::task::AtomicTask;
+use futures::{Async, Future, Poll};
+use http;
+
+use middlewares::RequestId;
+#[cfg(any(feature = "server-hyper", feature = "server-tiny-http"))]
+use middlewares::RequestIdSlot;
+use {Body, Error, Handler, Result};
+#[cfg(any(feature = "server-hyper", feature = "server-tiny-http"))]
+use {BodyStream, Responder};
+
+/// A trait implemented by server back-ends, which serve HTTP requests using a [`Handler`].
+///
+/// # Example
+///
+/// A function which serves a handler using any server back-end:
+///
+/// ```no_run
+/// # extern crate aitch;
+/// # extern crate http;
+/// #
+/// # use aitch::servers::Server;
+/// # use aitch::{Body, Handler, Responder, ResponseBuilder, Result};
+/// # use http::Request;
+/// #
+/// fn serve<S, H, B>(handler: H) -> Result<()>
+/// where
+///     S: Server<H, B>,
+///     H: Handler<B>,
+///     B: Body,
+/// {
+///     let server = S::new("127.0.0.1:3000".parse()?, handler)?;
+///     println!("Listening on http://{}", server.addr());
+///     server.run()
+/// }
+///
+/// fn handler(_req: Request<()>, mut resp: ResponseBuilder) -> impl Responder {
+///     resp.body("Hello, world!".to_owned())
+/// }
+///
+/// fn main() -> Result<()> {
+///     serve::<aitch::servers::hyper::Server<_, _>, _, _>(handler)
+/// }
+/// ```
+///
+/// [`Handler`]: ../trait.Handler.html
+pub trait Server<H, ReqBody>: Sized
+where
+    H: Handler<ReqBody>,
+    ReqBody: Body,
+{
+    /// Creates a server which will listen on the provided [`SocketAddr`] and handle requests using
+    /// the provided [`Handler`].
+    ///
+    /// [`SocketAddr`]: https://doc.rust-lang.org/std/net/enum.SocketAddr.html
+    /// [`Handler`]: ../trait.Handler.html
+    fn new(addr: SocketAddr, handler: H) -> Result<Self>;
+
+    /// Returns the address that the server is listening on.
+    fn addr(&self) -> SocketAddr;
+
+    /// Returns a [`ShutdownHandle`], which can be used to stop the server once it is running.
+    ///
+    /// [`ShutdownHandle`]: struct.ShutdownHandle.html
+    fn shutdown_handle(&self) -> ShutdownHandle;
+
+    /// Starts and runs the server, until it is shut down.
+    fn run(self) -> Result<()>;
+}
+
+/// A handle which can be used to stop a running [`Server`].
+///
+/// Calling [`shutdown()`] causes the server to stop accepting new requests. The server's `run()`
+/// method returns once any in-flight requests have been completed.
+///
+/// [`Server`]: trait.Server.html
+/// [`shutdown()`]: #method.shutdown
+#[derive(Clone, Default)]
+pub struct ShutdownHandle {
+    state: Arc<Mutex<ShutdownState>>,
+}
+
+#[derive(Default)]
+struct ShutdownState {
+    requested: bool,
+    waiting: Vec<Arc<AtomicTask>>,
+}
+
+impl ShutdownHandle {
+    /// Creates a new `ShutdownHandle`.
+    pub fn new() -> Self {
+        ShutdownHandle::default()
+    }
+
+    /// Requests that the server shuts down.
+    pub fn shutdown(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.requested = true;
+        for task in state.waiting.drain(..) {
+            task.notify();
+        }
+    }
+
+    /// Returns whether shutdown has been requested.
+    pub fn is_shutdown(&self) -> bool {
+        self.state.lock().unwrap().requested
+    }
+
+    /// Returns a future which resolves once shutdown has been requested.
+    pub fn signal(&self) -> ShutdownSignal {
+        ShutdownSignal {
+            handle: self.clone(),
+            task: None,
+        }
+    }
+}
+
+/// A future which resolves once shutdown has been requested through a [`ShutdownHandle`].
+///
+/// [`ShutdownHandle`]: struct.ShutdownHandle.html
+pub struct ShutdownSignal {
+    handle: ShutdownHandle,
+    // Registered with the handle on the first poll, and updated with the current task on each
+    // subsequent poll.
+    task: Option<Arc<AtomicTask>>,
+}
+
+impl Future for ShutdownSignal {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        let mut state = self.handle.state.lock().unwrap();
+        if state.requested {
+            return Ok(Async::Ready(()));
+        }
+        match self.task {
+            Some(ref task) => task.register(),
+            None => {
+                let task = Arc::new(AtomicTask::new());
+                task.register();
+                state.waiting.push(task.clone());
+                self.task = Some(task);
+            }
+        }
+        Ok(Async::NotReady)
+    }
+}
+
+/// Calls the handler with a request, and returns its response.
+///
+/// This contains the logic shared by the provided servers, which only need to convert requests and
+/// responses to/from the types used by their underlying HTTP library. Any error returned by the
+/// handler is reported to the error hook, and results in a `500 Internal Server Error` response.
+/// The returned future never fails.
+#[cfg(any(feature = "server-hyper", feature = "server-tiny-http"))]
+pub(crate) fn serve<H, ReqBody>(
+    handler: &Arc<H>,
+    errors: &ErrorHandling,
+    req: http::Request<BodyStream>,
+) -> impl Future<Item = http::Response<BodyStream>, Error = Error>
+where
+    H: Handler<ReqBody>,
+    ReqBody: Body,
+{
+    let handler = handler.clone();
+    let errors = errors.clone();
+    let mut meta = RequestMetadata::new(&req);
+    let slot = RequestIdSlot::default();
+
+    let (mut parts, body) = req.into_parts();
+    parts.extensions.insert(slot.clone());
+    body.into_body::<ReqBody>()
+        .map(move |body| http::Request::from_parts(parts, body))
+        .and_then(move |req| {
+            handler
+                .handle(req, http::Response::builder())
+                .into_response()
+        })
+        .or_else(move |err| {
+            if meta.request_id.is_none() {
+                meta.request_id = slot.get();
+            }
+            errors.report(&err, Some(&meta));
+            Ok(errors.internal_server_error().map(Body::into_stream))
+        })
+}
+
+/// Details of the connection over which a HTTP request was received.
+///
+/// Both of the provided servers insert a `ConnectionInfo` into the extensions of each request,
+/// before passing it to the [`Handler`]. This allows handlers and middlewares to make decisions
+/// based on the client's address, such as rate-limiting or audit-logging.
+///
+/// [`Handler`]: ../trait.Handler.html
+///
+/// # Example
+///
+/// ```
+/// # extern crate aitch;
+/// # extern crate http;
+/// #
+/// # use aitch::servers::ConnectionInfo;
+/// # use aitch::{Responder, ResponseBuilder};
+/// # use http::Request;
+/// #
+/// fn handler(req: Request<()>, mut resp: ResponseBuilder) -> impl Responder {
+///     let message = match req.extensions().get::<ConnectionInfo>() {
+///         Some(info) => format!("Hello, {}!", info.remote_addr.ip()),
+///         None => "Hello, stranger!".to_owned(),
+///     };
+///     resp.body(message)
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    /// The address of the client.
+    pub remote_addr: SocketAddr,
+    /// The address that the server is listening on.
+    pub local_addr: SocketAddr,
+    /// Whether the connection was made using TLS.
+    pub secure: bool,
+}
+
+/// Metadata describing the HTTP request that was being processed when an error occurred.
+///
+/// This is passed to a server's error hook, as the request itself will usually have been consumed
+/// by the [`Handler`] by the time an error is reported.
+///
+/// [`Handler`]: ../trait.Handler.html
+#[derive(Clone, Debug)]
+pub struct RequestMetadata {
+    /// The HTTP method of the request.
+    pub method: http::Method,
+    /// The URI of the request.
+    pub uri: http::Uri,
+    /// The HTTP version of the request.
+    pub version: http::Version,
+    /// The ID assigned to the request by the [`with_request_id`] middleware, if it was used.
+    ///
+    /// [`with_request_id`]: ../middlewares/fn.with_request_id.html
+    pub request_id: Option<RequestId>,
+}
+
+impl RequestMetadata {
+    /// Captures the metadata of a `http::Request`.
+    pub fn new<B>(req: &http::Request<B>) -> RequestMetadata {
+        RequestMetadata {
+            method: req.method().clone(),
+            uri: req.uri().clone(),
+            version: req.version(),
+            request_id: req.extensions().get::<RequestId>().cloned(),
+        }
+    }
+}
+
+/// A callback which is used by servers to report errors.
+///
+/// The callback is passed the error, and the metadata of the request which caused it (if the error
+/// was associated with a particular request).
+pub type ErrorHook = Arc<Fn(&Error, Option<&RequestMetadata>) + Send + Sync>;
+
+/// The error reporting configuration shared by the provided servers.
+#[derive(Clone)]
+#[cfg(any(feature = "server-hyper", feature = "server-tiny-http"))]
+pub(crate) struct ErrorHandling {
+    hook: ErrorHook,
+    body: Bytes,
+}
+
+#[cfg(any(feature = "server-hyper", feature = "server-tiny-http"))]
+impl Default for ErrorHandling {
+    fn default() -> Self {
+        ErrorHandling {
+            hook: Arc::new(log_to_stderr),
+            body: Bytes::new(),
+        }
+    }
+}
+
+#[cfg(any(feature = "server-hyper", feature = "server-tiny-http"))]
+impl ErrorHandling {
+    pub fn set_hook(&mut self, hook: ErrorHook) {
+        self.hook = hook;
+    }
+
+    pub fn set_body(&mut self, body: Bytes) {
+        self.body = body;
+    }
+
+    /// Passes the error to the configured error hook.
+    pub fn report(&self, err: &Error, meta: Option<&RequestMetadata>) {
+        (self.hook)(err, meta)
+    }
+
+    /// Returns the response that should be sent when a handler fails.
+    pub fn internal_server_error(&self) -> http::Response<Bytes> {
+        let mut resp = http::Response::new(self.body.clone());
+        *resp.status_mut() = http::StatusCode::INTERNAL_SERVER_ERROR;
+        resp
+    }
+}
+
+#[cfg(any(feature = "server-hyper", feature = "server-tiny-http"))]
+fn log_to_stderr(err: &Error, meta: Option<&RequestMetadata>) {
+    match meta {
+        Some(&RequestMetadata {
+            ref method,
+            ref uri,
+            request_id: Some(ref id),
+            ..
+        }) => eprintln!("server error: [request {}] {} {}: {}", id, method, uri, err),
+        Some(meta) => eprintln!("server error: {} {}: {}", meta.method, meta.uri, err),
+        None => eprintln!("server error: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::{future, Async, Future};
+
+    use super::ShutdownHandle;
+
+    #[test]
+    fn shutdown_signal() {
+        let handle = ShutdownHandle::new();
+        let mut signal = handle.signal();
+
+        future::lazy(|| {
+            for _ in 0..3 {
+                assert_eq!(signal.poll(), Ok(Async::NotReady));
+            }
+            assert_eq!(handle.state.lock().unwrap().waiting.len(), 1);
+
+            handle.shutdown();
+            assert_eq!(signal.poll(), Ok(Async::Ready(())));
+            Ok::<_, ()>(())
+        })
+        .wait()
+        .unwrap();
+    }
+}
@@ -12,18 +12,28 @@
 mod request;
 mod response;
 
+use std::io::Cursor;
 use std::marker::PhantomData;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
+use bytes::Bytes;
 use futures::{future, Future};
 use http;
 use tiny_http;
 use tokio_threadpool::ThreadPool;
 
+pub use self::request::RequestError;
+pub use self::response::ResponseError;
+
 use self::request::as_http_request;
-use self::response::as_tiny_http_response;
-use {Body, Handler, Responder, Result};
+use self::response::{as_tiny_http_response, simple_response};
+use super::{serve, ConnectionInfo, ErrorHandling, RequestMetadata, ShutdownHandle};
+use {servers, Body, Error, Handler, Result};
+
+// How often the server checks whether it has been shut down, while waiting for requests.
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
 /// A [`tiny_http`] server, which can serve a handler.
 ///
@@ -73,6 +83,8 @@ where
 {
     server: tiny_http::Server,
     handler: Arc<H>,
+    errors: ErrorHandling,
+    shutdown: ShutdownHandle,
     marker: PhantomData<ReqBody>,
 }
 
@@ -89,61 +101,145 @@ where
     pub fn new(addr: SocketAddr, handler: H) -> Result<Server<H, ReqBody>> {
         let server = tiny_http::Server::http(addr)?;
         let handler = Arc::new(handler);
+        let errors = ErrorHandling::default();
+        let shutdown = ShutdownHandle::new();
         let marker = PhantomData;
         Ok(Server {
             server,
             handler,
+            errors,
+            shutdown,
             marker,
         })
     }
 
+    /// Sets the callback which is used to report errors.
+    ///
+    /// The callback is called whenever the [`Handler`] returns an error, and whenever a request
+    /// cannot be received or responded to. By default, errors are written to stderr.
+    ///
+    /// [`Handler`]: ../../trait.Handler.html
+    pub fn with_error_hook<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&Error, Option<&RequestMetadata>) + Send + Sync + 'static,
+    {
+        self.errors.set_hook(Arc::new(hook));
+        self
+    }
+
+    /// Sets the body of the `500 Internal Server Error` response, which is sent when the
+    /// [`Handler`] returns an error. By default, the body is empty.
+    ///
+    /// [`Handler`]: ../../trait.Handler.html
+    pub fn with_error_body<B: Into<Bytes>>(mut self, body: B) -> Self {
+        self.errors.set_body(body.into());
+        self
+    }
+
     /// Returns the address that the server is listening on.
     pub fn addr(&self) -> SocketAddr {
         self.server.server_addr()
     }
 
-    /// Starts and runs the server.
+    /// Returns a [`ShutdownHandle`], which can be used to stop the server once it is running.
+    ///
+    /// [`ShutdownHandle`]: ../struct.ShutdownHandle.html
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Starts and runs the server, until it is shut down.
     pub fn run(self) -> Result<()> {
         let pool = ThreadPool::new();
 
-        loop {
-            let req = match self.server.recv() {
-                Ok(req) => req,
-                Err(e) => {
-                    eprintln!("Server error: {}", e);
+        while !self.shutdown.is_shutdown() {
+            let req = match self.server.recv_timeout(SHUTDOWN_POLL_INTERVAL) {
+                Ok(Some(req)) => req,
+                Ok(None) => continue,
+                Err(err) => {
+                    self.errors.report(&Error::from(err), None);
                     continue;
                 }
             };
 
+            let info = ConnectionInfo {
+                remote_addr: *req.remote_addr(),
+                local_addr: self.server.server_addr(),
+                secure: req.secure(),
+            };
             let handler = self.handler.clone();
-            pool.spawn(future::lazy(move || Server::process_request(handler, req)));
+            let errors = self.errors.clone();
+            pool.spawn(future::lazy(move || {
+                Server::process_request(handler, errors, info, req)
+            }));
         }
+
+        // Wait for in-flight requests to complete.
+        let _ = pool.shutdown_on_idle().wait();
+        Ok(())
     }
 
     fn process_request(
         handler: Arc<H>,
+        errors: ErrorHandling,
+        info: ConnectionInfo,
         mut req: tiny_http::Request,
     ) -> impl Future<Item = (), Error = ()> {
-        let http_request = as_http_request(&mut req);
-
-        future::lazy(move || Ok(http_request?.into_parts()))
-            .and_then(|(parts, body)| {
-                body.into_body::<ReqBody>()
-                    .map(move |body| http::Request::from_parts(parts, body))
-            })
-            .and_then(move |http_request| {
-                handler
-                    .handle(http_request, http::Response::builder())
-                    .into_response()
-            })
+        let mut http_request = match as_http_request(&mut req) {
+            Ok(http_request) => http_request,
+            Err(err) => {
+                errors.report(&Error::from(err), None);
+                let resp = simple_response(http::StatusCode::BAD_REQUEST, Bytes::new());
+                respond(&errors, req, resp);
+                return future::Either::A(future::ok(()));
+            }
+        };
+        let meta = RequestMetadata::new(&http_request);
+        http_request.extensions_mut().insert(info);
+
+        let fut = serve(&handler, &errors, http_request)
             .and_then(as_tiny_http_response)
-            .and_then(move |resp| {
-                req.respond(resp)?;
+            .then(move |result| {
+                let resp = result.unwrap_or_else(|err| {
+                    errors.report(&err, Some(&meta));
+                    let (parts, body) = errors.internal_server_error().into_parts();
+                    simple_response(parts.status, body)
+                });
+                respond(&errors, req, resp);
                 Ok(())
-            })
-            .or_else(|err| {
-                eprintln!("Server error processing request: {}", err);
-                Ok(())
-            })
+            });
+        future::Either::B(fut)
+    }
+}
+
+impl<H, ReqBody> servers::Server<H, ReqBody> for Server<H, ReqBody>
+where
+    H: Handler<ReqBody>,
+    ReqBody: Body,
+{
+    fn new(addr: SocketAddr, handler: H) -> Result<Self> {
+        Server::new(addr, handler)
+    }
+
+    fn addr(&self) -> SocketAddr {
+        Server::addr(self)
+    }
+
+    fn shutdown_handle(&self) -> ShutdownHandle {
+        Server::shutdown_handle(self)
+    }
+
+    fn run(self) -> Result<()> {
+        Server::run(self)
+    }
+}
+
+fn respond(
+    errors: &ErrorHandling,
+    req: tiny_http::Request,
+    resp: tiny_http::Response<Cursor<Bytes>>,
+) {
+    if let Err(err) = req.respond(resp) {
+        errors.report(&Error::from(err), None);
     }
 }
@@ -1,3 +1,6 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
 use std::str::FromStr;
 
 use bytes::Bytes;
@@ -5,12 +8,56 @@ use futures::stream;
 use http;
 use tiny_http;
 
-use {BodyStream, Result};
+use BodyStream;
+
+/// An error which occurred while converting a `tiny_http::Request` into a `http::Request`.
+///
+/// These errors are caused by requests which `tiny_http` accepted, but which cannot be represented
+/// by the types in the `http` crate. They should result in a `400 Bad Request` response.
+#[derive(Debug)]
+pub enum RequestError {
+    /// The request method was not a valid HTTP method.
+    Method(String),
+    /// The request URI could not be parsed.
+    Uri(String),
+    /// The request used a HTTP version which is not known to the `http` crate.
+    Version(u8, u8),
+    /// A request header had an invalid name or value.
+    Header(String),
+    /// The request body could not be read.
+    Body(io::Error),
+    /// The `http::Request` could not be constructed.
+    Http(http::Error),
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RequestError::Method(method) => write!(f, "invalid HTTP method: {:?}", method),
+            RequestError::Uri(uri) => write!(f, "invalid request URI: {:?}", uri),
+            RequestError::Version(major, minor) => {
+                write!(f, "unknown HTTP version: ({}, {})", major, minor)
+            }
+            RequestError::Header(name) => write!(f, "invalid request header: {:?}", name),
+            RequestError::Body(err) => write!(f, "error reading request body: {}", err),
+            RequestError::Http(err) => write!(f, "error constructing request: {}", err),
+        }
+    }
+}
+
+impl StdError for RequestError {
+    fn description(&self) -> &str {
+        "error converting tiny_http request"
+    }
+}
 
 /// Creates a `http::Request<BodyStream>` representing a `tiny_http::Request`.
-pub fn as_http_request(req: &mut tiny_http::Request) -> Result<http::Request<BodyStream>> {
+pub fn as_http_request(
+    req: &mut tiny_http::Request,
+) -> Result<http::Request<BodyStream>, RequestError> {
     let method = map_method(req.method())?;
-    let uri: http::Uri = http::HttpTryFrom::try_from(req.url())?;
+    let uri: http::Uri = http::HttpTryFrom::try_from(req.url())
+        .map_err(|_| RequestError::Uri(req.url().to_owned()))?;
     let version = map_version(req.http_version())?;
 
     let mut builder = http::request::Builder::new();
@@ -23,10 +70,10 @@ pub fn as_http_request(req: &mut tiny_http::Request) -> Result<http::Request<Bod
 
     let body = read_body(req)?;
 
-    Ok(builder.body(body)?)
+    builder.body(body).map_err(RequestError::Http)
 }
 
-fn map_method(method: &tiny_http::Method) -> Result<http::Method> {
+fn map_method(method: &tiny_http::Method) -> Result<http::Method, RequestError> {
     let mapped = match method {
         tiny_http::Method::Get => http::Method::GET,
         tiny_http::Method::Head => http::Method::HEAD,
@@ -38,40 +85,112 @@ fn map_method(method: &tiny_http::Method) -> Result<http::Method> {
         tiny_http::Method::Trace => http::Method::TRACE,
         tiny_http::Method::Patch => http::Method::PATCH,
         tiny_http::Method::NonStandard(ascii_string) => {
-            http::Method::from_str(ascii_string.as_str())?
+            http::Method::from_str(ascii_string.as_str())
+                .map_err(|_| RequestError::Method(ascii_string.to_string()))?
         }
     };
     Ok(mapped)
 }
 
-fn map_version(version: &tiny_http::HTTPVersion) -> Result<http::Version> {
+fn map_version(version: &tiny_http::HTTPVersion) -> Result<http::Version, RequestError> {
     let (major, minor) = (version.0, version.1);
     let version = match (major, minor) {
         (0, 9) => http::Version::HTTP_09,
         (1, 0) => http::Version::HTTP_10,
         (1, 1) => http::Version::HTTP_11,
         (2, 0) => http::Version::HTTP_2,
-        // TODO: Return some kind of error.
-        _ => panic!("Unknown HTTP Version: ({}, {})", major, minor),
+        _ => return Err(RequestError::Version(major, minor)),
     };
     Ok(version)
 }
 
 fn map_header(
     header: &tiny_http::Header,
-) -> Result<(http::header::HeaderName, http::header::HeaderValue)> {
-    let name = http::header::HeaderName::from_str(header.field.as_str().as_str())?;
-    let value = http::header::HeaderValue::from_str(header.value.as_str())?;
+) -> Result<(http::header::HeaderName, http::header::HeaderValue), RequestError> {
+    let field = header.field.as_str().as_str();
+    let name = http::header::HeaderName::from_str(field)
+        .map_err(|_| RequestError::Header(field.to_owned()))?;
+    let value = http::header::HeaderValue::from_str(header.value.as_str())
+        .map_err(|_| RequestError::Header(field.to_owned()))?;
     Ok((name, value))
 }
 
-fn read_body(req: &mut tiny_http::Request) -> Result<BodyStream> {
+fn read_body(req: &mut tiny_http::Request) -> Result<BodyStream, RequestError> {
     let content_length = req.body_length().unwrap_or(0);
 
     let mut buf = Vec::with_capacity(content_length);
-    req.as_reader().read_to_end(&mut buf)?;
+    req.as_reader()
+        .read_to_end(&mut buf)
+        .map_err(RequestError::Body)?;
 
     let bytes = Bytes::from(buf);
     let stream = stream::once(Ok(bytes));
     Ok(Box::new(stream))
 }
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use http;
+    use quickcheck::{quickcheck, TestResult};
+    use tiny_http;
+
+    use super::{map_header, map_method, map_version};
+
+    #[test]
+    fn map_version_never_panics() {
+        fn prop(major: u8, minor: u8) -> bool {
+            let known = [(0, 9), (1, 0), (1, 1), (2, 0)];
+            let version = tiny_http::HTTPVersion(major, minor);
+            map_version(&version).is_ok() == known.contains(&(major, minor))
+        }
+        quickcheck(prop as fn(u8, u8) -> bool);
+    }
+
+    #[test]
+    fn map_method_never_panics() {
+        fn prop(method: String) -> TestResult {
+            let method = match tiny_http::Method::from_str(&method) {
+                Ok(method) => method,
+                Err(()) => return TestResult::discard(),
+            };
+            match map_method(&method) {
+                Ok(mapped) => TestResult::from_bool(
+                    mapped.as_str().eq_ignore_ascii_case(method.as_str()),
+                ),
+                Err(_) => TestResult::passed(),
+            }
+        }
+        quickcheck(prop as fn(String) -> TestResult);
+    }
+
+    #[test]
+    fn map_header_never_panics() {
+        fn prop(name: Vec<u8>, value: Vec<u8>) -> TestResult {
+            let header = match tiny_http::Header::from_bytes(name, value) {
+                Ok(header) => header,
+                Err(()) => return TestResult::discard(),
+            };
+            match map_header(&header) {
+                Ok((name, value)) => TestResult::from_bool(
+                    name.as_str().eq_ignore_ascii_case(header.field.as_str().as_str())
+                        && value.as_bytes() == header.value.as_bytes(),
+                ),
+                Err(_) => TestResult::passed(),
+            }
+        }
+        quickcheck(prop as fn(Vec<u8>, Vec<u8>) -> TestResult);
+    }
+
+    #[test]
+    fn map_header_rejects_invalid_name() {
+        let header = tiny_http::Header::from_bytes("Bad Name", "value").unwrap();
+        assert!(map_header(&header).is_err());
+
+        let header = tiny_http::Header::from_bytes("X-Good-Name", "value").unwrap();
+        let (name, value) = map_header(&header).unwrap();
+        assert_eq!(name, http::header::HeaderName::from_static("x-good-name"));
+        assert_eq!(value, "value");
+    }
+}
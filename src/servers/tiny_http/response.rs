@@ -1,12 +1,39 @@
+use std::error::Error as StdError;
+use std::fmt;
 use std::io::Cursor;
 
 use bytes::{Bytes, IntoBuf};
-use futures::{Future, Stream};
+use futures::{future, Future, Stream};
 use http;
 use tiny_http;
 
 use {BodyStream, Error};
 
+/// An error which occurred while converting a `http::Response` into a `tiny_http::Response`.
+///
+/// `tiny_http` only supports ASCII header values, whereas the `http` crate also allows opaque
+/// bytes. Responses containing such headers cannot be sent, and should instead result in a
+/// `500 Internal Server Error` response.
+#[derive(Debug)]
+pub enum ResponseError {
+    /// A response header could not be represented by `tiny_http`.
+    Header(String),
+}
+
+impl fmt::Display for ResponseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ResponseError::Header(name) => write!(f, "invalid response header: {:?}", name),
+        }
+    }
+}
+
+impl StdError for ResponseError {
+    fn description(&self) -> &str {
+        "error converting http response"
+    }
+}
+
 /// Returns a `Future<Item = tiny_http::Response<Cursor<Bytes>>` representing an `http::Response<BodyStream>`.
 pub fn as_tiny_http_response(
     resp: http::Response<BodyStream>,
@@ -15,14 +42,14 @@ pub fn as_tiny_http_response(
 
     let headers = resp.headers()
         .iter()
-        .map(|(name, value)| {
-            let name: &[u8] = name.as_ref();
-            let value: &[u8] = value.as_ref();
-            tiny_http::Header::from_bytes(name, value).unwrap()
-        })
-        .collect();
-
-    resp.into_body().concat2().and_then(move |body| {
+        .map(|(name, value)| map_header(name, value))
+        .collect::<Result<Vec<_>, _>>();
+    let headers = match headers {
+        Ok(headers) => headers,
+        Err(err) => return future::Either::A(future::err(Error::from(err))),
+    };
+
+    let fut = resp.into_body().concat2().and_then(move |body| {
         Ok(tiny_http::Response::new(
             status_code,
             headers,
@@ -30,5 +57,60 @@ pub fn as_tiny_http_response(
             None,
             None,
         ))
-    })
+    });
+    future::Either::B(fut)
+}
+
+/// Returns a `tiny_http::Response` with the given status code and body, and no headers.
+pub fn simple_response(
+    status: http::StatusCode,
+    body: Bytes,
+) -> tiny_http::Response<Cursor<Bytes>> {
+    let status_code = tiny_http::StatusCode(status.as_u16());
+    let len = body.len();
+    tiny_http::Response::new(status_code, vec![], body.into_buf(), Some(len), None)
+}
+
+fn map_header(
+    name: &http::header::HeaderName,
+    value: &http::header::HeaderValue,
+) -> Result<tiny_http::Header, ResponseError> {
+    let name_bytes: &[u8] = name.as_ref();
+    let value_bytes: &[u8] = value.as_ref();
+    tiny_http::Header::from_bytes(name_bytes, value_bytes)
+        .map_err(|()| ResponseError::Header(name.as_str().to_owned()))
+}
+
+#[cfg(test)]
+mod test {
+    use http;
+    use quickcheck::{quickcheck, TestResult};
+
+    use super::map_header;
+
+    #[test]
+    fn map_header_never_panics() {
+        fn prop(value: Vec<u8>) -> TestResult {
+            let name = http::header::CONTENT_TYPE;
+            let value = match http::header::HeaderValue::from_bytes(&value) {
+                Ok(value) => value,
+                Err(_) => return TestResult::discard(),
+            };
+            let is_ascii = value.as_bytes().is_ascii();
+            match map_header(&name, &value) {
+                Ok(header) => TestResult::from_bool(
+                    is_ascii && header.value.as_bytes() == value.as_bytes(),
+                ),
+                Err(_) => TestResult::from_bool(!is_ascii),
+            }
+        }
+        quickcheck(prop as fn(Vec<u8>) -> TestResult);
+    }
+
+    #[test]
+    fn map_header_rejects_non_ascii_value() {
+        let name = http::header::CONTENT_TYPE;
+        let value = http::header::HeaderValue::from_bytes(b"caf\xe9").unwrap();
+        assert!(map_header(&name, &value).is_err());
+    }
 }
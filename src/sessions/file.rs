@@ -0,0 +1,211 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::future;
+use serde_json::{self, Map, Value};
+
+use super::{SessionRecord, SessionStore, StoreFuture};
+use {random, Result};
+
+/// A [`SessionStore`] which keeps each session in a JSON file, in a directory.
+///
+/// Sessions are read and written using blocking file I/O. Expired sessions are not removed
+/// automatically: applications should call [`remove_expired`] periodically.
+///
+/// [`SessionStore`]: trait.SessionStore.html
+/// [`remove_expired`]: #method.remove_expired
+#[derive(Clone, Debug)]
+pub struct FileStore {
+    dir: PathBuf,
+}
+
+impl FileStore {
+    /// Creates a `FileStore` which keeps sessions in the given directory, creating it if necessary.
+    pub fn new<P: AsRef<Path>>(dir: P) -> Result<FileStore> {
+        let dir = dir.as_ref().to_owned();
+        fs::create_dir_all(&dir)?;
+        Ok(FileStore { dir })
+    }
+
+    /// Removes all expired sessions from the directory.
+    pub fn remove_expired(&self) -> Result<()> {
+        let now = SystemTime::now();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            if let Some(record) = read_record(&path)? {
+                if record.is_expired(now) {
+                    remove_file(&path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Returns the path of the session's file, or `None` if the ID cannot be a valid session ID.
+    // IDs come from clients, so must be checked to avoid reading arbitrary files.
+    fn path(&self, id: &str) -> Option<PathBuf> {
+        let valid = !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric());
+        if valid {
+            Some(self.dir.join(format!("{}.json", id)))
+        } else {
+            None
+        }
+    }
+
+    fn save_sync(&self, id: &str, record: SessionRecord) -> Result<()> {
+        let path = match self.path(id) {
+            Some(path) => path,
+            None => return Err(From::from(format!("invalid session ID: {:?}", id))),
+        };
+
+        let mut json = Map::new();
+        json.insert("created".to_owned(), Value::from(to_secs(record.created)));
+        let expires = record
+            .expires
+            .map_or(Value::Null, |expires| Value::from(to_secs(expires)));
+        json.insert("expires".to_owned(), expires);
+        json.insert("data".to_owned(), Value::Object(record.data));
+
+        // Write to a temporary file and then rename it, so that concurrent requests never see a
+        // partially written session.
+        let tmp = self
+            .dir
+            .join(format!(".{}.{}.tmp", id, random::hex_token(8)));
+        let mut file = File::create(&tmp)?;
+        file.write_all(&serde_json::to_vec(&json)?)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+}
+
+fn to_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+fn from_secs(value: Option<&Value>) -> Option<SystemTime> {
+    let secs = value?.as_u64()?;
+    UNIX_EPOCH.checked_add(Duration::from_secs(secs))
+}
+
+fn read_record(path: &Path) -> Result<Option<SessionRecord>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    // Files which can't be parsed are treated as missing, so that they are replaced.
+    let mut json: Map<String, Value> = match serde_json::from_reader(file) {
+        Ok(json) => json,
+        Err(_) => return Ok(None),
+    };
+    let data = match json.remove("data") {
+        Some(Value::Object(data)) => data,
+        _ => return Ok(None),
+    };
+    let record = from_secs(json.get("created")).and_then(|created| {
+        let expires = match json.get("expires") {
+            Some(&Value::Null) => None,
+            expires => Some(from_secs(expires)?),
+        };
+        Some(SessionRecord {
+            data,
+            created,
+            expires,
+        })
+    });
+    Ok(record)
+}
+
+fn remove_file(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+impl SessionStore for FileStore {
+    fn load(&self, id: &str) -> StoreFuture<Option<SessionRecord>> {
+        let result = match self.path(id) {
+            Some(path) => read_record(&path),
+            None => Ok(None),
+        };
+        Box::new(future::result(result))
+    }
+
+    fn save(&self, id: &str, record: SessionRecord) -> StoreFuture<()> {
+        Box::new(future::result(self.save_sync(id, record)))
+    }
+
+    fn remove(&self, id: &str) -> StoreFuture<()> {
+        let result = match self.path(id) {
+            Some(path) => remove_file(&path),
+            None => Ok(()),
+        };
+        Box::new(future::result(result))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use futures::Future;
+    use serde_json::{Map, Value};
+
+    use super::FileStore;
+    use random;
+    use sessions::{SessionRecord, SessionStore};
+
+    fn record(expires: Option<SystemTime>) -> SessionRecord {
+        let mut data = Map::new();
+        data.insert("user".to_owned(), Value::from("alice"));
+        SessionRecord {
+            data,
+            created: UNIX_EPOCH + Duration::from_secs(1_000),
+            expires,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = env::temp_dir().join(format!("aitch-sessions-{}", random::hex_token(8)));
+        let store = FileStore::new(&dir).unwrap();
+        let future = Some(UNIX_EPOCH + Duration::from_secs(u64::from(u32::MAX)));
+
+        assert_eq!(store.load("abc").wait().unwrap(), None);
+        store.save("abc", record(future)).wait().unwrap();
+        assert_eq!(store.load("abc").wait().unwrap(), Some(record(future)));
+
+        // IDs which could escape the directory are rejected.
+        assert_eq!(store.load("../abc").wait().unwrap(), None);
+        assert!(store.save("../abc", record(future)).wait().is_err());
+
+        // Sessions may never expire.
+        store.save("forever", record(None)).wait().unwrap();
+        assert_eq!(store.load("forever").wait().unwrap(), Some(record(None)));
+
+        store.save("old", record(Some(UNIX_EPOCH))).wait().unwrap();
+        store.remove_expired().unwrap();
+        assert_eq!(store.load("old").wait().unwrap(), None);
+        assert!(store.load("abc").wait().unwrap().is_some());
+        assert!(store.load("forever").wait().unwrap().is_some());
+
+        store.remove("abc").wait().unwrap();
+        assert_eq!(store.load("abc").wait().unwrap(), None);
+        store.remove("abc").wait().unwrap();
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use futures::future;
+
+use super::{SessionRecord, SessionStore, StoreFuture};
+
+#[derive(Debug)]
+struct State {
+    sessions: HashMap<String, SessionRecord>,
+    last_sweep: SystemTime,
+}
+
+/// A [`SessionStore`] which keeps sessions in memory.
+///
+/// Sessions are lost when the process exits, and are not shared between processes. Expired
+/// sessions are removed periodically.
+///
+/// [`SessionStore`]: trait.SessionStore.html
+#[derive(Debug)]
+pub struct MemoryStore {
+    state: Mutex<State>,
+    sweep_interval: Duration,
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        MemoryStore::new()
+    }
+}
+
+impl MemoryStore {
+    /// Creates an empty `MemoryStore`.
+    pub fn new() -> Self {
+        MemoryStore {
+            state: Mutex::new(State {
+                sessions: HashMap::new(),
+                last_sweep: SystemTime::now(),
+            }),
+            sweep_interval: Duration::from_secs(60),
+        }
+    }
+
+    /// Returns the number of sessions currently held in memory.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().sessions.len()
+    }
+
+    /// Returns `true` if no sessions are held in memory.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn sweep(&self, state: &mut State) {
+        let now = SystemTime::now();
+        let due = match now.duration_since(state.last_sweep) {
+            Ok(elapsed) => elapsed >= self.sweep_interval,
+            Err(_) => false,
+        };
+        if due {
+            state.sessions.retain(|_, record| !record.is_expired(now));
+            state.last_sweep = now;
+        }
+    }
+}
+
+impl SessionStore for MemoryStore {
+    fn load(&self, id: &str) -> StoreFuture<Option<SessionRecord>> {
+        let state = self.state.lock().unwrap();
+        Box::new(future::ok(state.sessions.get(id).cloned()))
+    }
+
+    fn save(&self, id: &str, record: SessionRecord) -> StoreFuture<()> {
+        let mut state = self.state.lock().unwrap();
+        self.sweep(&mut state);
+        state.sessions.insert(id.to_owned(), record);
+        Box::new(future::ok(()))
+    }
+
+    fn remove(&self, id: &str) -> StoreFuture<()> {
+        self.state.lock().unwrap().sessions.remove(id);
+        Box::new(future::ok(()))
+    }
+}
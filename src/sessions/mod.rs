@@ -0,0 +1,95 @@
+//! Server-side sessions, which store data between requests from the same client.
+//!
+//! The [`with_sessions`] middleware identifies each client's session using a cookie, which holds a
+//! random session ID. The session's data is loaded from a [`SessionStore`] before the handler is
+//! called, and is made available to the handler as a [`Session`] in the request's extensions. Once
+//! the handler has responded, any changes to the session are saved to the store.
+//!
+//! Two stores are provided: [`MemoryStore`], which keeps sessions in memory, and [`FileStore`],
+//! which keeps each session in a file. Other stores (e.g. using a database) can be used by
+//! implementing the [`SessionStore`] trait.
+//!
+//! This module requires the `sessions` feature, which is enabled by default.
+//!
+//! [`with_sessions`]: ../middlewares/fn.with_sessions.html
+//! [`SessionStore`]: trait.SessionStore.html
+//! [`Session`]: struct.Session.html
+//! [`MemoryStore`]: struct.MemoryStore.html
+//! [`FileStore`]: struct.FileStore.html
+
+mod file;
+mod memory;
+mod session;
+
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use futures::Future;
+use serde_json::{Map, Value};
+
+use Error;
+
+pub use self::file::FileStore;
+pub use self::memory::MemoryStore;
+pub use self::session::Session;
+
+/// The data of a session, as saved in a [`SessionStore`].
+///
+/// [`SessionStore`]: trait.SessionStore.html
+#[derive(Clone, Debug, PartialEq)]
+pub struct SessionRecord {
+    /// The values stored in the session.
+    pub data: Map<String, Value>,
+    /// The time at which the session was created.
+    pub created: SystemTime,
+    /// The time after which the session is no longer valid, and can be removed from the store, or
+    /// `None` if it never expires.
+    pub expires: Option<SystemTime>,
+}
+
+impl SessionRecord {
+    /// Returns whether the session has expired, as of the time `now`.
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires.is_some_and(|expires| now >= expires)
+    }
+}
+
+/// A future returned by a [`SessionStore`].
+///
+/// [`SessionStore`]: trait.SessionStore.html
+pub type StoreFuture<T> = Box<Future<Item = T, Error = Error> + Send>;
+
+/// Stores the data of sessions, for the [`with_sessions`] middleware.
+///
+/// Session IDs are random strings, which are generated by the middleware. Stores do not need to
+/// check whether a session has expired when loading it, as the middleware does this, but should
+/// eventually remove expired sessions, so that they do not accumulate.
+///
+/// This trait is implemented for `Arc<S>`, so that an application can keep a reference to a store
+/// after passing it to the middleware.
+///
+/// [`with_sessions`]: ../middlewares/fn.with_sessions.html
+pub trait SessionStore: Send + Sync + 'static {
+    /// Loads the session with the given ID, returning `None` if there is no such session.
+    fn load(&self, id: &str) -> StoreFuture<Option<SessionRecord>>;
+
+    /// Saves the session with the given ID, replacing any existing session with the same ID.
+    fn save(&self, id: &str, record: SessionRecord) -> StoreFuture<()>;
+
+    /// Removes the session with the given ID, if it exists.
+    fn remove(&self, id: &str) -> StoreFuture<()>;
+}
+
+impl<S: SessionStore> SessionStore for Arc<S> {
+    fn load(&self, id: &str) -> StoreFuture<Option<SessionRecord>> {
+        (**self).load(id)
+    }
+
+    fn save(&self, id: &str, record: SessionRecord) -> StoreFuture<()> {
+        (**self).save(id, record)
+    }
+
+    fn remove(&self, id: &str) -> StoreFuture<()> {
+        (**self).remove(id)
+    }
+}
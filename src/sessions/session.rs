@@ -0,0 +1,184 @@
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{self, Map, Value};
+
+use super::SessionRecord;
+use Result;
+
+#[derive(Debug)]
+struct State {
+    // The ID of the session, if it has been saved before.
+    id: Option<String>,
+    data: Map<String, Value>,
+    created: SystemTime,
+    changed: bool,
+    regenerate: bool,
+    destroyed: bool,
+}
+
+/// The session of the client which made a request.
+///
+/// The [`with_sessions`] middleware stores a `Session` in the extensions of each request. Values
+/// of any type which implements `serde::Serialize` and `serde::de::DeserializeOwned` can be stored
+/// in the session, and are saved once the handler has responded.
+///
+/// A `Session` can be cloned, and all clones share the same data, which allows the session to be
+/// modified after the request has been consumed (e.g. by an asynchronous handler).
+///
+/// [`with_sessions`]: ../middlewares/fn.with_sessions.html
+///
+/// # Example
+///
+/// ```
+/// # extern crate aitch;
+/// # extern crate http;
+/// #
+/// use aitch::sessions::Session;
+/// use aitch::{Responder, ResponseBuilder};
+/// use http::Request;
+///
+/// fn handler(req: Request<()>, mut resp: ResponseBuilder) -> impl Responder {
+///     let session = req.extensions().get::<Session>().unwrap();
+///     let visits = session.get::<u32>("visits").unwrap_or(0) + 1;
+///     session.insert("visits", visits).unwrap();
+///     resp.body(format!("You have visited {} times", visits))
+/// }
+/// # fn main() {}
+/// ```
+#[derive(Clone, Debug)]
+pub struct Session {
+    state: Arc<Mutex<State>>,
+}
+
+impl Session {
+    pub(crate) fn new(now: SystemTime) -> Session {
+        Session::from_state(State {
+            id: None,
+            data: Map::new(),
+            created: now,
+            changed: false,
+            regenerate: false,
+            destroyed: false,
+        })
+    }
+
+    pub(crate) fn load(id: String, record: SessionRecord) -> Session {
+        Session::from_state(State {
+            id: Some(id),
+            data: record.data,
+            created: record.created,
+            changed: false,
+            regenerate: false,
+            destroyed: false,
+        })
+    }
+
+    fn from_state(state: State) -> Session {
+        Session {
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    /// Returns the ID of the session, or `None` if the session is new.
+    ///
+    /// New sessions are given an ID when they are first saved.
+    pub fn id(&self) -> Option<String> {
+        self.state.lock().unwrap().id.clone()
+    }
+
+    /// Returns whether the session is new (i.e. the client did not send a valid session ID).
+    pub fn is_new(&self) -> bool {
+        self.state.lock().unwrap().id.is_none()
+    }
+
+    /// Returns the time at which the session was created.
+    pub fn created(&self) -> SystemTime {
+        self.state.lock().unwrap().created
+    }
+
+    /// Returns the value stored under the given key.
+    ///
+    /// Returns `None` if there is no such value, or if it cannot be deserialized as a `T`.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let state = self.state.lock().unwrap();
+        let value = state.data.get(key)?.clone();
+        serde_json::from_value(value).ok()
+    }
+
+    /// Stores a value under the given key, replacing any existing value.
+    ///
+    /// Returns an error if the value cannot be serialized.
+    pub fn insert<T: Serialize>(&self, key: &str, value: T) -> Result<()> {
+        let value = serde_json::to_value(value)?;
+        let mut state = self.state.lock().unwrap();
+        if state.data.get(key) != Some(&value) {
+            state.data.insert(key.to_owned(), value);
+            state.changed = true;
+        }
+        Ok(())
+    }
+
+    /// Removes the value stored under the given key.
+    pub fn remove(&self, key: &str) {
+        let mut state = self.state.lock().unwrap();
+        if state.data.remove(key).is_some() {
+            state.changed = true;
+        }
+    }
+
+    /// Removes all values from the session.
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        if !state.data.is_empty() {
+            state.data.clear();
+            state.changed = true;
+        }
+    }
+
+    /// Gives the session a new ID, keeping its data.
+    ///
+    /// This should be called when the privileges of the session change (e.g. when a user logs in),
+    /// to prevent session fixation attacks. The session is saved under its new ID, and the old ID
+    /// is removed from the store.
+    pub fn regenerate(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.regenerate = true;
+        state.destroyed = false;
+    }
+
+    /// Destroys the session, removing it from the store and from the client.
+    ///
+    /// Any values stored in the session after it has been destroyed are discarded.
+    pub fn destroy(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.destroyed = true;
+        state.regenerate = false;
+    }
+
+    pub(crate) fn take_changes(&self) -> SessionChanges {
+        let mut state = self.state.lock().unwrap();
+        SessionChanges {
+            id: state.id.clone(),
+            data: mem::take(&mut state.data),
+            created: state.created,
+            changed: state.changed,
+            regenerate: state.regenerate,
+            destroyed: state.destroyed,
+        }
+    }
+}
+
+// The state of a session once the handler has responded, which determines how the middleware
+// updates the store.
+pub(crate) struct SessionChanges {
+    pub id: Option<String>,
+    pub data: Map<String, Value>,
+    pub created: SystemTime,
+    pub changed: bool,
+    pub regenerate: bool,
+    pub destroyed: bool,
+}
@@ -0,0 +1,391 @@
+//! An in-process test harness, which calls a [`Handler`] without starting a server.
+//!
+//! Starting a real server (and talking to it over a socket) is slow, and can be flaky when many
+//! tests are run in parallel. This module provides a [`TestClient`], which drives any [`Handler`]
+//! directly: requests are built as `http::Request`s, their body is passed through
+//! [`Body::from_stream`] (exactly as a server would), and the handler's response is collected into
+//! a [`TestResponse`].
+//!
+//! [`TestResponse`] provides fluent assertions for the status, headers and body of the response.
+//!
+//! [`Handler`]: ../trait.Handler.html
+//! [`Body::from_stream`]: ../trait.Body.html#tymethod.from_stream
+//! [`TestClient`]: struct.TestClient.html
+//! [`TestResponse`]: struct.TestResponse.html
+//!
+//! # Example
+//!
+//! ```
+//! # extern crate aitch;
+//! # extern crate http;
+//! #
+//! # use aitch::testing::TestClient;
+//! # use aitch::{Responder, ResponseBuilder};
+//! # use http::Request;
+//! #
+//! fn handler(req: Request<String>, mut resp: ResponseBuilder) -> impl Responder {
+//!     resp.header("X-Echo", "true").body(req.into_body())
+//! }
+//!
+//! # fn main() {
+//! let client = TestClient::new(handler);
+//! client
+//!     .post("/echo")
+//!     .body("Hello!")
+//!     .send()
+//!     .assert_status(http::StatusCode::OK)
+//!     .assert_header("X-Echo", "true")
+//!     .assert_body("Hello!");
+//! # }
+//! ```
+
+use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::net::SocketAddr;
+use std::str;
+
+use bytes::Bytes;
+use futures::{Future, Stream};
+use http;
+use http::header::{HeaderName, HeaderValue};
+use http::HttpTryFrom;
+
+#[cfg(feature = "json")]
+use serde::de::DeserializeOwned;
+#[cfg(feature = "json")]
+use serde::Serialize;
+#[cfg(feature = "json")]
+use serde_json;
+
+use servers::ConnectionInfo;
+use {Body, Handler, Responder, Result};
+
+/// Calls a [`Handler`] with a request, and waits for the full response.
+///
+/// The request body is converted to the handler's body type using [`Body::from_stream`], and the
+/// response body is collected into a single [`bytes::Bytes`].
+///
+/// [`Handler`]: ../trait.Handler.html
+/// [`Body::from_stream`]: ../trait.Body.html#tymethod.from_stream
+/// [`bytes::Bytes`]: http://carllerche.github.io/bytes/bytes/struct.Bytes.html
+pub fn call<H, ReqBody, B>(handler: &H, req: http::Request<B>) -> Result<http::Response<Bytes>>
+where
+    H: Handler<ReqBody>,
+    ReqBody: Body,
+    B: Body,
+{
+    let (parts, body) = req.into_parts();
+    let body = body.into_body::<ReqBody>().wait()?;
+    let req = http::Request::from_parts(parts, body);
+
+    let resp = handler
+        .handle(req, http::Response::builder())
+        .into_response()
+        .wait()?;
+
+    let (parts, body) = resp.into_parts();
+    let body = body.concat2().wait()?;
+    Ok(http::Response::from_parts(parts, body))
+}
+
+/// A client which sends requests directly to a [`Handler`].
+///
+/// See the [module level documentation] for an example.
+///
+/// [`Handler`]: ../trait.Handler.html
+/// [module level documentation]: ./index.html
+pub struct TestClient<H, ReqBody>
+where
+    H: Handler<ReqBody>,
+    ReqBody: Body,
+{
+    handler: H,
+    marker: PhantomData<ReqBody>,
+}
+
+impl<H, ReqBody> TestClient<H, ReqBody>
+where
+    H: Handler<ReqBody>,
+    ReqBody: Body,
+{
+    /// Creates a client which sends requests to the provided [`Handler`].
+    ///
+    /// [`Handler`]: ../trait.Handler.html
+    pub fn new(handler: H) -> Self {
+        TestClient {
+            handler,
+            marker: PhantomData,
+        }
+    }
+
+    /// Starts building a request with the given method and URI.
+    pub fn request<'a, U>(&'a self, method: http::Method, uri: U) -> TestRequest<'a, H, ReqBody>
+    where
+        http::Uri: HttpTryFrom<U>,
+    {
+        let mut builder = http::Request::builder();
+        builder.method(method).uri(uri);
+
+        let connection = ConnectionInfo {
+            remote_addr: ([127, 0, 0, 1], 50000).into(),
+            local_addr: ([127, 0, 0, 1], 80).into(),
+            secure: false,
+        };
+
+        TestRequest {
+            client: self,
+            builder,
+            connection,
+            body: Bytes::new(),
+        }
+    }
+
+    /// Starts building a `GET` request for the given URI.
+    pub fn get<'a, U>(&'a self, uri: U) -> TestRequest<'a, H, ReqBody>
+    where
+        http::Uri: HttpTryFrom<U>,
+    {
+        self.request(http::Method::GET, uri)
+    }
+
+    /// Starts building a `POST` request for the given URI.
+    pub fn post<'a, U>(&'a self, uri: U) -> TestRequest<'a, H, ReqBody>
+    where
+        http::Uri: HttpTryFrom<U>,
+    {
+        self.request(http::Method::POST, uri)
+    }
+
+    /// Starts building a `PUT` request for the given URI.
+    pub fn put<'a, U>(&'a self, uri: U) -> TestRequest<'a, H, ReqBody>
+    where
+        http::Uri: HttpTryFrom<U>,
+    {
+        self.request(http::Method::PUT, uri)
+    }
+
+    /// Starts building a `DELETE` request for the given URI.
+    pub fn delete<'a, U>(&'a self, uri: U) -> TestRequest<'a, H, ReqBody>
+    where
+        http::Uri: HttpTryFrom<U>,
+    {
+        self.request(http::Method::DELETE, uri)
+    }
+
+    /// Sends a request to the handler, returning its response.
+    ///
+    /// Returns an error if the handler (or the conversion of the request body) fails.
+    pub fn send<B: Body>(&self, req: http::Request<B>) -> Result<TestResponse> {
+        call(&self.handler, req).map(TestResponse::from)
+    }
+}
+
+/// A request which is being built by a [`TestClient`].
+///
+/// [`TestClient`]: struct.TestClient.html
+pub struct TestRequest<'a, H, ReqBody>
+where
+    H: Handler<ReqBody> + 'a,
+    ReqBody: Body,
+{
+    client: &'a TestClient<H, ReqBody>,
+    builder: http::request::Builder,
+    connection: ConnectionInfo,
+    body: Bytes,
+}
+
+impl<'a, H, ReqBody> TestRequest<'a, H, ReqBody>
+where
+    H: Handler<ReqBody>,
+    ReqBody: Body,
+{
+    /// Appends a header to the request.
+    pub fn header<K, V>(mut self, key: K, value: V) -> Self
+    where
+        HeaderName: HttpTryFrom<K>,
+        HeaderValue: HttpTryFrom<V>,
+    {
+        self.builder.header(key, value);
+        self
+    }
+
+    /// Inserts a value into the request's extensions.
+    pub fn extension<T>(mut self, extension: T) -> Self
+    where
+        T: Send + Sync + 'static,
+    {
+        self.builder.extension(extension);
+        self
+    }
+
+    /// Sets the address of the (pretend) client sending the request.
+    ///
+    /// Requests sent by a `TestClient` have a [`ConnectionInfo`] extension, as they would if they
+    /// had been received by one of the provided servers. By default, requests appear to come from
+    /// `127.0.0.1`.
+    ///
+    /// [`ConnectionInfo`]: ../servers/struct.ConnectionInfo.html
+    pub fn remote_addr(mut self, addr: SocketAddr) -> Self {
+        self.connection.remote_addr = addr;
+        self
+    }
+
+    /// Sets the body of the request.
+    pub fn body<B: Into<Bytes>>(mut self, body: B) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Sets the body of the request to the JSON serialization of `value`, and sets the
+    /// `Content-Type` header to `application/json`.
+    #[cfg(feature = "json")]
+    pub fn json<T: Serialize>(self, value: &T) -> Self {
+        let body = serde_json::to_vec(value).expect("failed to serialize JSON");
+        self.header(http::header::CONTENT_TYPE, "application/json")
+            .body(body)
+    }
+
+    /// Sends the request, returning an error if the handler fails.
+    pub fn try_send(mut self) -> Result<TestResponse> {
+        let req = self.builder.extension(self.connection).body(self.body)?;
+        self.client.send(req)
+    }
+
+    /// Sends the request.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the request is invalid, or if the handler returns an error.
+    pub fn send(self) -> TestResponse {
+        match self.try_send() {
+            Ok(resp) => resp,
+            Err(err) => panic!("TestRequest: handler returned an error: {}", err),
+        }
+    }
+}
+
+/// A response returned by a [`Handler`] to a [`TestClient`].
+///
+/// The `assert_*` methods panic with a descriptive message if the response doesn't match, and
+/// return `&Self` so that they can be chained.
+///
+/// [`Handler`]: ../trait.Handler.html
+/// [`TestClient`]: struct.TestClient.html
+#[derive(Debug)]
+pub struct TestResponse {
+    inner: http::Response<Bytes>,
+}
+
+impl From<http::Response<Bytes>> for TestResponse {
+    fn from(inner: http::Response<Bytes>) -> Self {
+        TestResponse { inner }
+    }
+}
+
+impl TestResponse {
+    /// Returns the status code of the response.
+    pub fn status(&self) -> http::StatusCode {
+        self.inner.status()
+    }
+
+    /// Returns the headers of the response.
+    pub fn headers(&self) -> &http::HeaderMap {
+        self.inner.headers()
+    }
+
+    /// Returns the value of a response header, if it is present and valid UTF-8.
+    pub fn header<K: http::header::AsHeaderName>(&self, key: K) -> Option<&str> {
+        self.inner.headers().get(key).and_then(|v| v.to_str().ok())
+    }
+
+    /// Returns the body of the response.
+    pub fn body(&self) -> &Bytes {
+        self.inner.body()
+    }
+
+    /// Returns the body of the response as a string.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the body isn't valid UTF-8.
+    pub fn text(&self) -> &str {
+        str::from_utf8(self.body()).expect("response body is not valid UTF-8")
+    }
+
+    /// Deserializes the body of the response as JSON.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the body can't be deserialized.
+    #[cfg(feature = "json")]
+    pub fn json<T: DeserializeOwned>(&self) -> T {
+        match serde_json::from_slice(self.body()) {
+            Ok(value) => value,
+            Err(err) => panic!(
+                "response body is not valid JSON: {}: {:?}",
+                err,
+                self.body()
+            ),
+        }
+    }
+
+    /// Consumes the `TestResponse`, returning the underlying `http::Response`.
+    pub fn into_inner(self) -> http::Response<Bytes> {
+        self.inner
+    }
+
+    /// Asserts that the response has the given status code.
+    pub fn assert_status(&self, status: http::StatusCode) -> &Self {
+        assert_eq!(self.status(), status, "unexpected response status");
+        self
+    }
+
+    /// Asserts that the response has a header with the given value.
+    pub fn assert_header<K>(&self, key: K, value: &str) -> &Self
+    where
+        K: http::header::AsHeaderName + Debug + Clone,
+    {
+        match self.header(key.clone()) {
+            Some(actual) => assert_eq!(actual, value, "unexpected value for header {:?}", key),
+            None => panic!("response is missing header {:?}", key),
+        }
+        self
+    }
+
+    /// Asserts that the response does not have a header with the given name.
+    pub fn assert_no_header<K>(&self, key: K) -> &Self
+    where
+        K: http::header::AsHeaderName + Debug + Clone,
+    {
+        if let Some(value) = self.headers().get(key.clone()) {
+            panic!("response has unexpected header {:?}: {:?}", key, value);
+        }
+        self
+    }
+
+    /// Asserts that the body of the response is equal to `expected`.
+    pub fn assert_body<B: AsRef<[u8]>>(&self, expected: B) -> &Self {
+        let expected = expected.as_ref();
+        if self.body().as_ref() != expected {
+            panic!(
+                "unexpected response body:\n  actual: {:?}\nexpected: {:?}",
+                self.body(),
+                Bytes::from(expected)
+            );
+        }
+        self
+    }
+
+    /// Asserts that the body of the response is JSON, which is equal to the JSON serialization of
+    /// `expected`.
+    ///
+    /// The comparison is made between parsed JSON values, so differences in whitespace or the
+    /// order of object keys are ignored.
+    #[cfg(feature = "json")]
+    pub fn assert_json<T: Serialize>(&self, expected: &T) -> &Self {
+        let actual: serde_json::Value = self.json();
+        let expected = serde_json::to_value(expected).expect("failed to serialize JSON");
+        assert_eq!(actual, expected, "unexpected JSON response body");
+        self
+    }
+}
@@ -0,0 +1,141 @@
+extern crate aitch;
+extern crate http;
+extern crate serde_json;
+
+use std::io::{self, Write};
+use std::str;
+use std::sync::{Arc, Mutex};
+
+use aitch::middlewares::{self, AccessLog, LogFormat};
+use aitch::testing::TestClient;
+use aitch::ResponseBuilder;
+use http::Request;
+
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    fn lines(&self) -> Vec<String> {
+        let buf = self.0.lock().unwrap();
+        str::from_utf8(&buf)
+            .unwrap()
+            .lines()
+            .map(|line| line.to_owned())
+            .collect()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn handler(_: Request<()>, mut resp: ResponseBuilder) -> http::Result<http::Response<String>> {
+    resp.status(http::StatusCode::CREATED)
+        .body("Hello, world!".to_owned())
+}
+
+#[test]
+fn combined_format() {
+    let buffer = SharedBuffer::default();
+    let log = AccessLog::new(buffer.clone());
+    let client = TestClient::new(middlewares::with_access_log(log, handler));
+
+    client
+        .get("/path?query=1")
+        .header("User-Agent", "test-agent")
+        .header("Referer", "http://example.com/")
+        .send()
+        .assert_body("Hello, world!");
+
+    let lines = buffer.lines();
+    assert_eq!(lines.len(), 1);
+    let line = &lines[0];
+    assert!(line.starts_with("127.0.0.1 - - ["), "{}", line);
+    assert!(
+        line.ends_with(
+            "\"GET /path?query=1 HTTP/1.1\" 201 13 \"http://example.com/\" \"test-agent\""
+        ),
+        "{}",
+        line
+    );
+}
+
+#[test]
+fn common_format() {
+    let buffer = SharedBuffer::default();
+    let log = AccessLog::new(buffer.clone()).format(LogFormat::Common);
+    let client = TestClient::new(middlewares::with_access_log(log, handler));
+
+    client.get("/").send();
+
+    let lines = buffer.lines();
+    assert_eq!(lines.len(), 1);
+    assert!(
+        lines[0].ends_with("\"GET / HTTP/1.1\" 201 13"),
+        "{}",
+        lines[0]
+    );
+}
+
+#[test]
+fn json_format() {
+    let buffer = SharedBuffer::default();
+    let log = AccessLog::new(buffer.clone()).format(LogFormat::Json);
+    let client = TestClient::new(middlewares::with_access_log(log, handler));
+
+    client.post("/json").send();
+
+    let lines = buffer.lines();
+    assert_eq!(lines.len(), 1);
+    let entry: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+    assert_eq!(entry["method"], "POST");
+    assert_eq!(entry["uri"], "/json");
+    assert_eq!(entry["status"], 201);
+    assert_eq!(entry["bytes_sent"], 13);
+    assert_eq!(entry["remote_addr"], "127.0.0.1:50000");
+    assert!(
+        entry["total_time_ms"].as_f64().unwrap() >= entry["time_to_headers_ms"].as_f64().unwrap()
+    );
+}
+
+#[test]
+fn handler_error() {
+    let buffer = SharedBuffer::default();
+    let log = AccessLog::new(buffer.clone()).format(LogFormat::Common);
+    let handler = |_: Request<()>, _: ResponseBuilder| -> aitch::Result<http::Response<()>> {
+        Err("handler failed".into())
+    };
+    let client = TestClient::new(middlewares::with_access_log(log, handler));
+
+    assert!(client.get("/").try_send().is_err());
+
+    let lines = buffer.lines();
+    assert_eq!(lines.len(), 1);
+    assert!(
+        lines[0].ends_with("\"GET / HTTP/1.1\" 500 -"),
+        "{}",
+        lines[0]
+    );
+}
+
+#[test]
+fn request_id() {
+    let buffer = SharedBuffer::default();
+    let log = AccessLog::new(buffer.clone()).format(LogFormat::Json);
+    let handler = middlewares::with_access_log(log, handler);
+    let config = middlewares::RequestIdConfig::default();
+    let client = TestClient::new(middlewares::with_request_id(config, handler));
+
+    client.get("/").header("X-Request-Id", "abc").send();
+
+    let lines = buffer.lines();
+    assert_eq!(lines.len(), 1);
+    let entry: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+    assert_eq!(entry["request_id"], "abc");
+}
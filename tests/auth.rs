@@ -0,0 +1,132 @@
+extern crate aitch;
+extern crate futures;
+extern crate futures_timer;
+extern crate http;
+
+use std::time::Duration;
+
+use aitch::middlewares::{self, BasicAuth, BearerAuth};
+use aitch::testing::TestClient;
+use aitch::ResponseBuilder;
+use futures::Future;
+use futures_timer::Delay;
+use http::header;
+use http::{Request, StatusCode};
+
+#[derive(Clone, Debug)]
+struct User(String);
+
+fn handler(req: Request<()>, mut resp: ResponseBuilder) -> http::Result<http::Response<String>> {
+    let user = req.extensions().get::<User>().unwrap();
+    resp.body(format!("Hello, {}!", user.0))
+}
+
+#[test]
+fn basic() {
+    let auth = BasicAuth::new("internal \"admin\"", |username: &str, password: &str| {
+        let valid = username == "alice" && password == "secret";
+        Ok(if valid {
+            Some(User(username.to_owned()))
+        } else {
+            None
+        })
+    });
+    let client = TestClient::new(middlewares::with_basic_auth(auth, handler));
+    let challenge = "Basic realm=\"internal \\\"admin\\\"\", charset=\"UTF-8\"";
+
+    client
+        .get("/")
+        .header(header::AUTHORIZATION, "basic YWxpY2U6c2VjcmV0")
+        .send()
+        .assert_status(StatusCode::OK)
+        .assert_body("Hello, alice!");
+
+    // alice:wrong
+    client
+        .get("/")
+        .header(header::AUTHORIZATION, "Basic YWxpY2U6d3Jvbmc=")
+        .send()
+        .assert_status(StatusCode::UNAUTHORIZED)
+        .assert_header(header::WWW_AUTHENTICATE, challenge);
+
+    client
+        .get("/")
+        .send()
+        .assert_status(StatusCode::UNAUTHORIZED)
+        .assert_header(header::WWW_AUTHENTICATE, challenge);
+
+    client
+        .get("/")
+        .header(header::AUTHORIZATION, "Bearer YWxpY2U6c2VjcmV0")
+        .send()
+        .assert_status(StatusCode::UNAUTHORIZED);
+
+    // Malformed credentials are rejected, even if they're close to valid ones.
+    for credentials in &[
+        "YWxpY2U6c2VjcmV0=",
+        "YWxpY2U6c2VjcmV0==",
+        "YWxpY2U6c2VjcmV0Y",
+    ] {
+        client
+            .get("/")
+            .header(
+                header::AUTHORIZATION,
+                format!("Basic {}", credentials).as_str(),
+            )
+            .send()
+            .assert_status(StatusCode::UNAUTHORIZED);
+    }
+}
+
+#[test]
+fn bearer() {
+    let auth = BearerAuth::new("api", |token: &str| {
+        let user = match token {
+            "token-1" => Some(User("alice".to_owned())),
+            _ => None,
+        };
+        // Verifiers may be asynchronous.
+        Delay::new(Duration::from_millis(10))
+            .map_err(From::from)
+            .map(move |()| user)
+    });
+    let client = TestClient::new(middlewares::with_bearer_auth(auth, handler));
+
+    client
+        .get("/")
+        .header(header::AUTHORIZATION, "Bearer token-1")
+        .send()
+        .assert_status(StatusCode::OK)
+        .assert_body("Hello, alice!");
+
+    client
+        .get("/")
+        .header(header::AUTHORIZATION, "Bearer token-2")
+        .send()
+        .assert_status(StatusCode::UNAUTHORIZED)
+        .assert_header(
+            header::WWW_AUTHENTICATE,
+            "Bearer realm=\"api\", error=\"invalid_token\"",
+        );
+
+    client
+        .get("/")
+        .header(header::AUTHORIZATION, "Bearer ")
+        .send()
+        .assert_status(StatusCode::UNAUTHORIZED)
+        .assert_header(header::WWW_AUTHENTICATE, "Bearer realm=\"api\"");
+}
+
+#[test]
+fn verifier_errors() {
+    let auth = BearerAuth::new("api", |_: &str| -> aitch::Result<Option<User>> {
+        Err(From::from("database unavailable"))
+    });
+    let client = TestClient::new(middlewares::with_bearer_auth(auth, handler));
+
+    let result = client
+        .get("/")
+        .header(header::AUTHORIZATION, "Bearer token-1")
+        .try_send();
+    assert!(result.is_err());
+}
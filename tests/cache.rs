@@ -0,0 +1,246 @@
+extern crate aitch;
+extern crate futures;
+extern crate http;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use futures::future::{ExecuteError, Executor};
+use futures::Future;
+
+use aitch::middlewares::{self, ResponseCache};
+use aitch::testing::TestClient;
+use aitch::{Handler, ResponseBuilder};
+use http::header;
+use http::{Request, StatusCode};
+
+// Runs each future to completion on a new thread.
+struct ThreadExecutor;
+
+impl<F> Executor<F> for ThreadExecutor
+where
+    F: Future<Item = (), Error = ()> + Send + 'static,
+{
+    fn execute(&self, future: F) -> Result<(), ExecuteError<F>> {
+        thread::spawn(move || future.wait());
+        Ok(())
+    }
+}
+
+// Returns a handler which responds with the number of times it has been called, and the
+// `Cache-Control` header given by the `cache-control` query parameter.
+fn counting_handler() -> (Arc<AtomicUsize>, impl Handler<()>) {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    let handler = move |req: Request<()>, mut resp: ResponseBuilder| {
+        let count = counter.fetch_add(1, Ordering::SeqCst) + 1;
+        if let Some(query) = req.uri().query() {
+            for param in query.split('&') {
+                let mut iter = param.splitn(2, '=');
+                let name = iter.next().unwrap();
+                let value = iter.next().unwrap_or("").replace("%20", " ");
+                match name {
+                    "cache-control" => resp.header(header::CACHE_CONTROL, value.as_str()),
+                    "vary" => resp.header(header::VARY, value.as_str()),
+                    "cookie" => resp.header(header::SET_COOKIE, value.as_str()),
+                    _ => &mut resp,
+                };
+            }
+        }
+        let lang = req
+            .headers()
+            .get(header::ACCEPT_LANGUAGE)
+            .map(|value| value.to_str().unwrap().to_owned())
+            .unwrap_or_default();
+        resp.body(format!("{} {}", count, lang).trim().to_owned())
+    };
+    (calls, handler)
+}
+
+#[test]
+fn caches_responses() {
+    let (calls, handler) = counting_handler();
+    let cache = ResponseCache::new();
+    let client = TestClient::new(middlewares::with_response_cache(cache.clone(), handler));
+
+    let uri = "/?cache-control=max-age=60";
+    client
+        .get(uri)
+        .send()
+        .assert_status(StatusCode::OK)
+        .assert_body("1")
+        .assert_no_header(header::AGE);
+    client
+        .get(uri)
+        .send()
+        .assert_status(StatusCode::OK)
+        .assert_body("1")
+        .assert_header(header::AGE, "0")
+        .assert_header(header::CACHE_CONTROL, "max-age=60");
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    assert_eq!(cache.len(), 1);
+
+    // Responses are cached separately for each URI, host and method.
+    client
+        .get("/other?cache-control=max-age=60")
+        .send()
+        .assert_body("2");
+    client
+        .get(uri)
+        .header(header::HOST, "other.example.com")
+        .send()
+        .assert_body("3");
+    client
+        .get(uri)
+        .header(header::HOST, "Other.Example.com")
+        .send()
+        .assert_body("3");
+    client.get(uri).send().assert_body("1");
+
+    // Successful requests with unsafe methods remove the cached responses for the URI.
+    client.post(uri).send().assert_body("4");
+    client.get(uri).send().assert_body("5");
+    client.get(uri).send().assert_body("5");
+    client
+        .get(uri)
+        .header(header::HOST, "other.example.com")
+        .send()
+        .assert_body("3");
+
+    cache.clear();
+    client.get(uri).send().assert_body("6");
+}
+
+#[test]
+fn honours_cache_control() {
+    let (calls, handler) = counting_handler();
+    let client = TestClient::new(middlewares::with_response_cache(
+        ResponseCache::new(),
+        handler,
+    ));
+
+    for uri in &[
+        "/",
+        "/?cache-control=no-store",
+        "/?cache-control=max-age=60,%20private",
+        "/?cache-control=no-cache,%20max-age=60",
+        "/?cache-control=max-age=0",
+        "/?cache-control=max-age=60&cookie=a=b",
+        "/?cache-control=max-age=60&vary=accept-language",
+    ] {
+        let before = calls.load(Ordering::SeqCst);
+        client.get(*uri).send();
+        client.get(*uri).send().assert_no_header(header::AGE);
+        assert_eq!(calls.load(Ordering::SeqCst), before + 2, "{}", uri);
+    }
+
+    // Responses to authorized requests are only cached when they are public.
+    for &(uri, cached) in &[
+        ("/private?cache-control=max-age=60", false),
+        ("/public?cache-control=public,%20max-age=60", true),
+        ("/shared?cache-control=s-maxage=60", true),
+    ] {
+        client
+            .get(uri)
+            .header(header::AUTHORIZATION, "Basic YTpi")
+            .send();
+        let resp = client
+            .get(uri)
+            .header(header::AUTHORIZATION, "Basic YTpi")
+            .send();
+        assert_eq!(resp.header(header::AGE).is_some(), cached, "{}", uri);
+    }
+}
+
+#[test]
+fn vary_and_size() {
+    let (_, handler) = counting_handler();
+    let cache = ResponseCache::new()
+        .vary(header::ACCEPT_LANGUAGE)
+        .max_size(4);
+    let client = TestClient::new(middlewares::with_response_cache(cache, handler));
+
+    let uri = "/?cache-control=max-age=60&vary=Accept-Language";
+    client.get(uri).send().assert_body("1");
+    client.get(uri).send().assert_body("1");
+    client
+        .get(uri)
+        .header(header::ACCEPT_LANGUAGE, "en")
+        .send()
+        .assert_body("2 en");
+    client
+        .get(uri)
+        .header(header::ACCEPT_LANGUAGE, "en")
+        .send()
+        .assert_body("2 en");
+
+    // Bodies larger than the maximum size are not cached.
+    let large = |n| {
+        client
+            .get(uri)
+            .header(header::ACCEPT_LANGUAGE, "fr-CA")
+            .send()
+            .assert_body(format!("{} fr-CA", n));
+    };
+    large(3);
+    large(4);
+}
+
+#[test]
+fn stale_while_revalidate() {
+    let (calls, handler) = counting_handler();
+    let client = TestClient::new(middlewares::with_response_cache(
+        ResponseCache::new().executor(ThreadExecutor),
+        handler,
+    ));
+
+    let uri = "/?cache-control=max-age=1,%20stale-while-revalidate=60";
+    client.get(uri).send().assert_body("1");
+    thread::sleep(Duration::from_millis(1100));
+
+    // The stale response is served, while it is revalidated in the background.
+    client
+        .get(uri)
+        .send()
+        .assert_body("1")
+        .assert_header(header::AGE, "1");
+    for _ in 0..100 {
+        if calls.load(Ordering::SeqCst) == 2 {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    thread::sleep(Duration::from_millis(50));
+    client
+        .get(uri)
+        .send()
+        .assert_body("2")
+        .assert_header(header::AGE, "0");
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+    // Without `stale-while-revalidate`, stale responses are not served.
+    let uri = "/?cache-control=max-age=1";
+    client.get(uri).send().assert_body("3");
+    thread::sleep(Duration::from_millis(1100));
+    client.get(uri).send().assert_body("4");
+}
+
+#[test]
+fn stale_without_executor() {
+    // Requests sent by a `TestClient` are not handled on an executor, so stale responses are
+    // served without being revalidated.
+    let (calls, handler) = counting_handler();
+    let client = TestClient::new(middlewares::with_response_cache(
+        ResponseCache::new(),
+        handler,
+    ));
+
+    let uri = "/?cache-control=max-age=1,%20stale-while-revalidate=60";
+    client.get(uri).send().assert_body("1");
+    thread::sleep(Duration::from_millis(1100));
+    client.get(uri).send().assert_body("1");
+    client.get(uri).send().assert_body("1");
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
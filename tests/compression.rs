@@ -0,0 +1,195 @@
+#![cfg(feature = "compression")]
+
+extern crate aitch;
+extern crate brotli;
+extern crate flate2;
+extern crate http;
+
+use std::io::Read;
+
+use aitch::middlewares::{self, Compression, Encoding};
+use aitch::testing::{TestClient, TestResponse};
+use aitch::ResponseBuilder;
+use http::header;
+use http::Request;
+
+fn body() -> String {
+    "{\"message\": \"Hello, world!\"}\n".repeat(100)
+}
+
+fn handler(req: Request<()>, mut resp: ResponseBuilder) -> http::Result<http::Response<String>> {
+    let content_type = req
+        .headers()
+        .get("X-Content-Type")
+        .map_or("application/json", |value| value.to_str().unwrap());
+    resp.header(header::CONTENT_TYPE, content_type)
+        .header(header::ETAG, "\"abc\"")
+        .body(body())
+}
+
+fn client() -> TestClient<impl aitch::Handler<()>, ()> {
+    TestClient::new(middlewares::with_compression(
+        Compression::default(),
+        handler,
+    ))
+}
+
+fn decode(resp: &TestResponse) -> String {
+    let mut decoded = String::new();
+    let body = &resp.body()[..];
+    match resp.header(header::CONTENT_ENCODING) {
+        Some("gzip") => flate2::read::GzDecoder::new(body)
+            .read_to_string(&mut decoded)
+            .unwrap(),
+        Some("deflate") => flate2::read::ZlibDecoder::new(body)
+            .read_to_string(&mut decoded)
+            .unwrap(),
+        Some("br") => brotli::Decompressor::new(body, 4096)
+            .read_to_string(&mut decoded)
+            .unwrap(),
+        other => panic!("unexpected Content-Encoding: {:?}", other),
+    };
+    decoded
+}
+
+#[test]
+fn encodings() {
+    let client = client();
+
+    for encoding in &["gzip", "deflate", "br"] {
+        let resp = client.get("/").header("Accept-Encoding", *encoding).send();
+        resp.assert_header(header::CONTENT_ENCODING, encoding)
+            .assert_header(header::VARY, "accept-encoding")
+            .assert_header(header::ETAG, "W/\"abc\"")
+            .assert_no_header(header::CONTENT_LENGTH);
+        assert!(resp.body().len() < body().len());
+        assert_eq!(decode(&resp), body());
+    }
+}
+
+#[test]
+fn negotiation() {
+    let client = client();
+
+    let cases = vec![
+        ("gzip, deflate, br", Some("br")),
+        ("gzip;q=1.0, br;q=0.5", Some("gzip")),
+        ("deflate, *;q=0.1", Some("deflate")),
+        ("*", Some("br")),
+        ("br;q=0, *", Some("gzip")),
+        ("identity", None),
+        ("gzip;q=0", None),
+    ];
+    for (accept, expected) in cases {
+        let resp = client.get("/").header("Accept-Encoding", accept).send();
+        assert_eq!(
+            resp.header(header::CONTENT_ENCODING),
+            expected,
+            "{}",
+            accept
+        );
+        resp.assert_header(header::VARY, "accept-encoding");
+    }
+}
+
+#[test]
+fn encoding_preference() {
+    let compression = Compression::new().encodings(vec![Encoding::Gzip, Encoding::Brotli]);
+    let client = TestClient::new(middlewares::with_compression(compression, handler));
+
+    client
+        .get("/")
+        .header("Accept-Encoding", "br, gzip, deflate")
+        .send()
+        .assert_header(header::CONTENT_ENCODING, "gzip");
+    client
+        .get("/")
+        .header("Accept-Encoding", "deflate")
+        .send()
+        .assert_no_header(header::CONTENT_ENCODING);
+}
+
+#[test]
+fn no_accept_encoding() {
+    client()
+        .get("/")
+        .send()
+        .assert_no_header(header::CONTENT_ENCODING)
+        .assert_header(header::VARY, "accept-encoding")
+        .assert_header(header::ETAG, "\"abc\"")
+        .assert_body(body());
+}
+
+#[test]
+fn small_responses() {
+    let handler = |_: Request<()>, mut resp: ResponseBuilder| {
+        resp.header(header::CONTENT_TYPE, "text/plain")
+            .body("Hello, world!".to_owned())
+    };
+    let client = TestClient::new(middlewares::with_compression(
+        Compression::default(),
+        handler,
+    ));
+
+    client
+        .get("/")
+        .header("Accept-Encoding", "gzip")
+        .send()
+        .assert_no_header(header::CONTENT_ENCODING)
+        .assert_body("Hello, world!");
+
+    let compression = Compression::new().min_size(0);
+    let client = TestClient::new(middlewares::with_compression(compression, handler));
+    let resp = client.get("/").header("Accept-Encoding", "gzip").send();
+    resp.assert_header(header::CONTENT_ENCODING, "gzip");
+    assert_eq!(decode(&resp), "Hello, world!");
+}
+
+#[test]
+fn compressed_content_types() {
+    let client = client();
+
+    for content_type in &["image/png", "video/mp4", "application/zip", "font/woff2"] {
+        client
+            .get("/")
+            .header("Accept-Encoding", "gzip")
+            .header("X-Content-Type", *content_type)
+            .send()
+            .assert_no_header(header::CONTENT_ENCODING)
+            .assert_no_header(header::VARY)
+            .assert_body(body());
+    }
+    client
+        .get("/")
+        .header("Accept-Encoding", "gzip")
+        .header("X-Content-Type", "image/svg+xml")
+        .send()
+        .assert_header(header::CONTENT_ENCODING, "gzip");
+}
+
+#[test]
+fn already_encoded() {
+    let handler = |_: Request<()>, mut resp: ResponseBuilder| {
+        resp.header(header::CONTENT_ENCODING, "gzip").body(body())
+    };
+    let client = TestClient::new(middlewares::with_compression(
+        Compression::default(),
+        handler,
+    ));
+
+    client
+        .get("/")
+        .header("Accept-Encoding", "br")
+        .send()
+        .assert_header(header::CONTENT_ENCODING, "gzip")
+        .assert_body(body());
+}
+
+#[test]
+fn head_requests() {
+    client()
+        .request(http::Method::HEAD, "/")
+        .header("Accept-Encoding", "gzip")
+        .send()
+        .assert_no_header(header::CONTENT_ENCODING);
+}
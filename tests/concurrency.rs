@@ -0,0 +1,155 @@
+extern crate aitch;
+extern crate http;
+
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use aitch::middlewares::{self, ConcurrencyLimit};
+use aitch::testing::{TestClient, TestResponse};
+use aitch::{Handler, ResponseBuilder};
+use http::header;
+use http::{Request, StatusCode};
+
+// Returns a handler which blocks until it receives a message on the returned channel.
+fn blocking_handler() -> (impl Handler<()>, Sender<()>) {
+    let (tx, rx) = mpsc::channel();
+    let rx = Arc::new(Mutex::new(rx));
+    let handler = move |_: Request<()>, mut resp: ResponseBuilder| {
+        rx.lock().unwrap().recv().unwrap();
+        resp.body("done".to_owned())
+    };
+    (handler, tx)
+}
+
+fn spawn_request<H>(client: &Arc<TestClient<H, ()>>) -> JoinHandle<TestResponse>
+where
+    H: Handler<()>,
+{
+    let client = client.clone();
+    thread::spawn(move || client.get("/").send())
+}
+
+fn wait_until<F: Fn() -> bool>(condition: F) {
+    for _ in 0..500 {
+        if condition() {
+            return;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    panic!("condition was not met");
+}
+
+#[test]
+fn rejects_when_full() {
+    let limit = ConcurrencyLimit::new(1).retry_after(Duration::from_secs(5));
+    let (handler, tx) = blocking_handler();
+    let client = Arc::new(TestClient::new(middlewares::with_concurrency_limit(
+        limit.clone(),
+        handler,
+    )));
+    assert_eq!(limit.max_concurrency(), 1);
+
+    let first = spawn_request(&client);
+    wait_until(|| limit.in_flight() == 1);
+
+    client
+        .get("/")
+        .send()
+        .assert_status(StatusCode::SERVICE_UNAVAILABLE)
+        .assert_header(header::RETRY_AFTER, "5");
+
+    tx.send(()).unwrap();
+    first.join().unwrap().assert_status(StatusCode::OK);
+    assert_eq!(limit.in_flight(), 0);
+}
+
+#[test]
+fn rounds_retry_after_up() {
+    let cases = [(1500, "2"), (200, "1"), (0, "1")];
+    for &(millis, expected) in &cases {
+        let limit = ConcurrencyLimit::new(1).retry_after(Duration::from_millis(millis));
+        let (handler, tx) = blocking_handler();
+        let client = Arc::new(TestClient::new(middlewares::with_concurrency_limit(
+            limit.clone(),
+            handler,
+        )));
+
+        let first = spawn_request(&client);
+        wait_until(|| limit.in_flight() == 1);
+        client
+            .get("/")
+            .send()
+            .assert_header(header::RETRY_AFTER, expected);
+
+        tx.send(()).unwrap();
+        first.join().unwrap();
+    }
+}
+
+#[test]
+fn queues_when_full() {
+    let limit = ConcurrencyLimit::new(1).queue(1, Duration::from_secs(10));
+    let (handler, tx) = blocking_handler();
+    let client = Arc::new(TestClient::new(middlewares::with_concurrency_limit(
+        limit.clone(),
+        handler,
+    )));
+
+    let first = spawn_request(&client);
+    wait_until(|| limit.in_flight() == 1);
+    let second = spawn_request(&client);
+    wait_until(|| limit.queued() == 1);
+
+    // The queue is full, so this request is rejected.
+    client
+        .get("/")
+        .send()
+        .assert_status(StatusCode::SERVICE_UNAVAILABLE);
+
+    tx.send(()).unwrap();
+    first.join().unwrap().assert_status(StatusCode::OK);
+    wait_until(|| limit.queued() == 0);
+    assert_eq!(limit.in_flight(), 1);
+
+    tx.send(()).unwrap();
+    second.join().unwrap().assert_status(StatusCode::OK);
+    assert_eq!(limit.in_flight(), 0);
+}
+
+#[test]
+fn queue_timeout() {
+    let limit = ConcurrencyLimit::new(1).queue(10, Duration::from_millis(20));
+    let (handler, tx) = blocking_handler();
+    let client = Arc::new(TestClient::new(middlewares::with_concurrency_limit(
+        limit.clone(),
+        handler,
+    )));
+
+    let first = spawn_request(&client);
+    wait_until(|| limit.in_flight() == 1);
+
+    client
+        .get("/")
+        .send()
+        .assert_status(StatusCode::SERVICE_UNAVAILABLE)
+        .assert_header(header::RETRY_AFTER, "1");
+    assert_eq!(limit.queued(), 0);
+
+    tx.send(()).unwrap();
+    first.join().unwrap().assert_status(StatusCode::OK);
+}
+
+#[test]
+fn handler_errors_release_slot() {
+    let limit = ConcurrencyLimit::new(1);
+    let handler = |_: Request<()>, _: ResponseBuilder| -> aitch::Result<http::Response<()>> {
+        Err("handler failed".into())
+    };
+    let client = TestClient::new(middlewares::with_concurrency_limit(limit.clone(), handler));
+
+    assert!(client.get("/").try_send().is_err());
+    assert!(client.get("/").try_send().is_err());
+    assert_eq!(limit.in_flight(), 0);
+}
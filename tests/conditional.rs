@@ -0,0 +1,195 @@
+extern crate aitch;
+extern crate http;
+
+use aitch::middlewares::{self, ConditionalRequests, Preconditions};
+use aitch::testing::TestClient;
+use aitch::ResponseBuilder;
+use http::header;
+use http::{Request, StatusCode};
+
+const LAST_MODIFIED: &str = "Sun, 06 Nov 1994 08:49:37 GMT";
+
+fn handler(req: Request<()>, mut resp: ResponseBuilder) -> http::Result<http::Response<String>> {
+    match req.uri().path() {
+        "/tagged" => resp
+            .header(header::ETAG, "\"v1\"")
+            .header(header::CACHE_CONTROL, "max-age=60")
+            .body("tagged".to_owned()),
+        "/dated" => resp
+            .header(header::LAST_MODIFIED, LAST_MODIFIED)
+            .body("dated".to_owned()),
+        "/large" => resp.body("x".repeat(100)),
+        "/missing" => resp
+            .status(StatusCode::NOT_FOUND)
+            .body("missing".to_owned()),
+        _ => resp.body("Hello, world!".to_owned()),
+    }
+}
+
+#[test]
+fn computes_etags() {
+    let conditional = ConditionalRequests::new().max_size(64);
+    let client = TestClient::new(middlewares::with_conditional_requests(conditional, handler));
+
+    let resp = client.get("/").send();
+    resp.assert_status(StatusCode::OK)
+        .assert_body("Hello, world!");
+    let etag = resp.header(header::ETAG).unwrap().to_owned();
+    assert!(etag.starts_with('"') && etag.ends_with('"'));
+    assert_eq!(etag.len(), 34);
+
+    // The ETag is the same for the same body.
+    client.get("/").send().assert_header(header::ETAG, &etag);
+
+    // ETags set by the handler are kept.
+    client
+        .get("/tagged")
+        .send()
+        .assert_header(header::ETAG, "\"v1\"");
+
+    // Larger bodies are sent without an ETag.
+    client
+        .get("/large")
+        .send()
+        .assert_status(StatusCode::OK)
+        .assert_body("x".repeat(100))
+        .assert_no_header(header::ETAG);
+
+    client
+        .get("/missing")
+        .send()
+        .assert_status(StatusCode::NOT_FOUND)
+        .assert_no_header(header::ETAG);
+}
+
+#[test]
+fn if_none_match() {
+    let client = TestClient::new(middlewares::with_conditional_requests(
+        ConditionalRequests::new(),
+        handler,
+    ));
+
+    let etag = client
+        .get("/")
+        .send()
+        .header(header::ETAG)
+        .unwrap()
+        .to_owned();
+    client
+        .get("/")
+        .header(
+            header::IF_NONE_MATCH,
+            format!("\"other\", W/{}", etag).as_str(),
+        )
+        .send()
+        .assert_status(StatusCode::NOT_MODIFIED)
+        .assert_header(header::ETAG, &etag)
+        .assert_body("");
+
+    client
+        .get("/tagged")
+        .header(header::IF_NONE_MATCH, "\"v1\"")
+        .send()
+        .assert_status(StatusCode::NOT_MODIFIED)
+        .assert_header(header::CACHE_CONTROL, "max-age=60")
+        .assert_body("");
+
+    client
+        .get("/tagged")
+        .header(header::IF_NONE_MATCH, "\"v0\"")
+        .send()
+        .assert_status(StatusCode::OK)
+        .assert_body("tagged");
+
+    // Preconditions are ignored for responses other than 200 OK.
+    client
+        .get("/missing")
+        .header(header::IF_NONE_MATCH, "*")
+        .send()
+        .assert_status(StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn dates() {
+    let client = TestClient::new(middlewares::with_conditional_requests(
+        ConditionalRequests::new(),
+        handler,
+    ));
+
+    client
+        .get("/dated")
+        .header(header::IF_MODIFIED_SINCE, LAST_MODIFIED)
+        .send()
+        .assert_status(StatusCode::NOT_MODIFIED)
+        .assert_header(header::LAST_MODIFIED, LAST_MODIFIED);
+
+    client
+        .get("/dated")
+        .header(header::IF_MODIFIED_SINCE, "Sun, 06 Nov 1994 08:49:36 GMT")
+        .send()
+        .assert_status(StatusCode::OK);
+
+    client
+        .get("/dated")
+        .header(header::IF_UNMODIFIED_SINCE, "Sun, 06 Nov 1994 08:49:36 GMT")
+        .send()
+        .assert_status(StatusCode::PRECONDITION_FAILED);
+}
+
+#[test]
+fn if_match() {
+    let client = TestClient::new(middlewares::with_conditional_requests(
+        ConditionalRequests::new(),
+        handler,
+    ));
+
+    client
+        .get("/tagged")
+        .header(header::IF_MATCH, "\"v1\"")
+        .send()
+        .assert_status(StatusCode::OK);
+    client
+        .get("/tagged")
+        .header(header::IF_MATCH, "\"v0\"")
+        .send()
+        .assert_status(StatusCode::PRECONDITION_FAILED)
+        .assert_body("");
+}
+
+#[test]
+fn unsafe_methods() {
+    let handler = |req: Request<()>, mut resp: ResponseBuilder| {
+        // The current ETag of the resource being updated.
+        let rejected = req
+            .extensions()
+            .get::<Preconditions>()
+            .and_then(|preconditions| preconditions.evaluate(Some("\"v1\""), None));
+        match rejected {
+            Some(status) => resp.status(status).body(String::new()),
+            None => resp.body("Updated".to_owned()),
+        }
+    };
+    let client = TestClient::new(middlewares::with_conditional_requests(
+        ConditionalRequests::new(),
+        handler,
+    ));
+
+    client
+        .put("/")
+        .header(header::IF_MATCH, "\"v1\"")
+        .send()
+        .assert_status(StatusCode::OK)
+        .assert_body("Updated")
+        .assert_no_header(header::ETAG);
+    client
+        .put("/")
+        .header(header::IF_MATCH, "\"v0\"")
+        .send()
+        .assert_status(StatusCode::PRECONDITION_FAILED);
+    client
+        .put("/")
+        .header(header::IF_NONE_MATCH, "*")
+        .send()
+        .assert_status(StatusCode::PRECONDITION_FAILED);
+    client.put("/").send().assert_status(StatusCode::OK);
+}
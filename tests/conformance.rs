@@ -0,0 +1,12 @@
+#[macro_use]
+extern crate aitch;
+
+#[cfg(feature = "server-hyper")]
+mod hyper {
+    server_conformance_tests!(::aitch::servers::hyper::Server<_, _>);
+}
+
+#[cfg(feature = "server-tiny-http")]
+mod tiny_http {
+    server_conformance_tests!(::aitch::servers::tiny_http::Server<_, _>);
+}
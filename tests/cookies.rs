@@ -0,0 +1,103 @@
+extern crate aitch;
+extern crate http;
+
+use aitch::cookies::{Cookie, CookieJar, ResponseBuilderExt, SameSite};
+use aitch::middlewares;
+use aitch::testing::TestClient;
+use aitch::ResponseBuilder;
+use http::header;
+use http::{Request, StatusCode};
+
+fn set_cookie_headers(resp: &aitch::testing::TestResponse) -> Vec<String> {
+    resp.headers()
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .map(|value| value.to_str().unwrap().to_owned())
+        .collect()
+}
+
+#[test]
+fn reads_cookies() {
+    let handler = |req: Request<()>, mut resp: ResponseBuilder| {
+        let jar = req.extensions().get::<CookieJar>().unwrap();
+        let names: Vec<_> = jar
+            .cookies()
+            .iter()
+            .map(|cookie| format!("{}={}", cookie.name(), cookie.value()))
+            .collect();
+        resp.body(names.join(","))
+    };
+    let client = TestClient::new(middlewares::with_cookies(handler));
+
+    let resp = client
+        .get("/")
+        .header(header::COOKIE, "a=1; b=2")
+        .header(header::COOKIE, "c=3")
+        .send();
+    resp.assert_status(StatusCode::OK)
+        .assert_body("a=1,b=2,c=3")
+        .assert_no_header(header::SET_COOKIE);
+
+    client.get("/").send().assert_body("");
+}
+
+#[test]
+fn sends_changes() {
+    let handler = |req: Request<()>, mut resp: ResponseBuilder| {
+        let jar = req.extensions().get::<CookieJar>().unwrap();
+        jar.add(Cookie::new("unchanged", "1"));
+        jar.add(
+            Cookie::new("session", "abc")
+                .path("/")
+                .http_only(true)
+                .same_site(SameSite::Lax),
+        );
+        jar.remove(Cookie::named("old").path("/"));
+        resp.body(())
+    };
+    let client = TestClient::new(middlewares::with_cookies(handler));
+
+    let resp = client
+        .get("/")
+        .header(header::COOKIE, "unchanged=1; old=x")
+        .send();
+    assert_eq!(
+        set_cookie_headers(&resp),
+        vec![
+            "old=; Path=/; Expires=Thu, 01 Jan 1970 00:00:00 GMT; Max-Age=0",
+            "session=abc; Path=/; HttpOnly; SameSite=Lax",
+        ]
+    );
+}
+
+#[test]
+fn response_builder() {
+    let handler = |req: Request<()>, mut resp: ResponseBuilder| {
+        let jar = req.extensions().get::<CookieJar>().unwrap();
+        jar.add(Cookie::new("from_jar", "1"));
+        resp.cookie(&Cookie::new("from_builder", "2").secure(true))
+            .body(())
+    };
+    let client = TestClient::new(middlewares::with_cookies(handler));
+
+    let resp = client.get("/").send();
+    assert_eq!(
+        set_cookie_headers(&resp),
+        vec!["from_builder=2; Secure", "from_jar=1"]
+    );
+}
+
+#[test]
+fn rejects_invalid_cookies() {
+    let handler = |req: Request<()>, mut resp: ResponseBuilder| {
+        let jar = req.extensions().get::<CookieJar>().unwrap();
+        let value = req.uri().query().unwrap_or("").replace("%20", " ");
+        jar.add(Cookie::new("name", value));
+        resp.body(())
+    };
+    let client = TestClient::new(middlewares::with_cookies(handler));
+
+    client.get("/?alice").send().assert_status(StatusCode::OK);
+    let err = client.get("/?x;%20Domain=evil.com").try_send().unwrap_err();
+    assert_eq!(err.to_string(), "cookie \"name\" has an invalid value");
+}
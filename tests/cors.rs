@@ -0,0 +1,166 @@
+extern crate aitch;
+extern crate http;
+
+use std::time::Duration;
+
+use aitch::middlewares::{self, Cors, SimpleRouter};
+use aitch::testing::TestClient;
+use aitch::ResponseBuilder;
+use http::header::{self, HeaderName};
+use http::{Method, Request, StatusCode};
+
+fn handler(_: Request<()>, mut resp: ResponseBuilder) -> http::Result<http::Response<String>> {
+    resp.header("X-Custom", "value")
+        .body("Hello, world!".to_owned())
+}
+
+fn router() -> SimpleRouter {
+    let mut router = SimpleRouter::new();
+    router.register_handler("/api", handler);
+    router
+}
+
+fn restricted() -> Cors {
+    Cors::new()
+        .allow_origins(vec!["https://a.example", "https://b.example"])
+        .allow_methods(vec![Method::GET, Method::PUT])
+        .allow_headers(vec![header::CONTENT_TYPE])
+        .expose_headers(vec![HeaderName::from_static("x-custom")])
+        .allow_credentials(true)
+        .max_age(Duration::from_secs(600))
+}
+
+#[test]
+fn no_origin() {
+    let client = TestClient::new(middlewares::with_cors(restricted(), router()));
+
+    client
+        .get("/api")
+        .send()
+        .assert_status(StatusCode::OK)
+        .assert_no_header(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        .assert_body("Hello, world!");
+}
+
+#[test]
+fn any_origin() {
+    let client = TestClient::new(middlewares::with_cors(Cors::default(), router()));
+
+    client
+        .get("/api")
+        .header("Origin", "https://anywhere.example")
+        .send()
+        .assert_header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .assert_no_header(header::ACCESS_CONTROL_ALLOW_CREDENTIALS)
+        .assert_no_header(header::VARY);
+}
+
+#[test]
+#[should_panic(expected = "credentials cannot be allowed from any origin")]
+fn any_origin_with_credentials() {
+    let cors = Cors::new().allow_credentials(true);
+    middlewares::with_cors(cors, router());
+}
+
+#[test]
+fn allowed_origin() {
+    let client = TestClient::new(middlewares::with_cors(restricted(), router()));
+
+    client
+        .get("/api")
+        .header("Origin", "https://b.example")
+        .send()
+        .assert_status(StatusCode::OK)
+        .assert_header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "https://b.example")
+        .assert_header(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, "true")
+        .assert_header(header::ACCESS_CONTROL_EXPOSE_HEADERS, "x-custom")
+        .assert_header(header::VARY, "origin")
+        .assert_body("Hello, world!");
+}
+
+#[test]
+fn disallowed_origin() {
+    let client = TestClient::new(middlewares::with_cors(restricted(), router()));
+
+    client
+        .get("/api")
+        .header("Origin", "https://evil.example")
+        .send()
+        .assert_status(StatusCode::OK)
+        .assert_no_header(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        .assert_header(header::VARY, "origin");
+}
+
+#[test]
+fn origin_predicate() {
+    let cors = Cors::new().allow_origin_fn(|origin| origin.ends_with(".example.com"));
+    let client = TestClient::new(middlewares::with_cors(cors, router()));
+
+    client
+        .get("/api")
+        .header("Origin", "https://app.example.com")
+        .send()
+        .assert_header(
+            header::ACCESS_CONTROL_ALLOW_ORIGIN,
+            "https://app.example.com",
+        );
+    client
+        .get("/api")
+        .header("Origin", "https://example.org")
+        .send()
+        .assert_no_header(header::ACCESS_CONTROL_ALLOW_ORIGIN);
+}
+
+#[test]
+fn preflight() {
+    let client = TestClient::new(middlewares::with_cors(restricted(), router()));
+
+    // The router has no handler for "/other", so this also checks the inner handler isn't called.
+    client
+        .request(Method::OPTIONS, "/other")
+        .header("Origin", "https://a.example")
+        .header("Access-Control-Request-Method", "PUT")
+        .header("Access-Control-Request-Headers", "Content-Type")
+        .send()
+        .assert_status(StatusCode::NO_CONTENT)
+        .assert_header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "https://a.example")
+        .assert_header(header::ACCESS_CONTROL_ALLOW_METHODS, "GET, PUT")
+        .assert_header(header::ACCESS_CONTROL_ALLOW_HEADERS, "content-type")
+        .assert_header(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, "true")
+        .assert_header(header::ACCESS_CONTROL_MAX_AGE, "600")
+        .assert_no_header("X-Custom")
+        .assert_body("");
+}
+
+#[test]
+fn preflight_rejected() {
+    let client = TestClient::new(middlewares::with_cors(restricted(), router()));
+
+    let requests = vec![
+        ("https://evil.example", "GET", "content-type"),
+        ("https://a.example", "DELETE", "content-type"),
+        ("https://a.example", "GET", "x-not-allowed"),
+    ];
+    for (origin, method, headers) in requests {
+        client
+            .request(Method::OPTIONS, "/api")
+            .header("Origin", origin)
+            .header("Access-Control-Request-Method", method)
+            .header("Access-Control-Request-Headers", headers)
+            .send()
+            .assert_status(StatusCode::FORBIDDEN)
+            .assert_no_header(header::ACCESS_CONTROL_ALLOW_ORIGIN);
+    }
+}
+
+#[test]
+fn options_without_preflight_headers() {
+    let client = TestClient::new(middlewares::with_cors(restricted(), router()));
+
+    client
+        .request(Method::OPTIONS, "/api")
+        .header("Origin", "https://a.example")
+        .send()
+        .assert_header("X-Custom", "value")
+        .assert_header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "https://a.example");
+}
@@ -0,0 +1,181 @@
+extern crate aitch;
+extern crate http;
+
+use aitch::middlewares::{self, Csrf, CsrfToken};
+use aitch::testing::TestClient;
+use aitch::ResponseBuilder;
+use http::header;
+use http::{Request, StatusCode};
+
+fn handler(
+    req: Request<String>,
+    mut resp: ResponseBuilder,
+) -> http::Result<http::Response<String>> {
+    let token = req.extensions().get::<CsrfToken>().unwrap().clone();
+    resp.body(format!("{} {}", token, req.body()))
+}
+
+#[test]
+fn issues_token() {
+    let client = TestClient::new(middlewares::with_csrf(Csrf::new(), handler));
+
+    let resp = client.get("/").send();
+    resp.assert_status(StatusCode::OK);
+    let token = resp.text().trim().to_owned();
+    assert_eq!(token.len(), 64);
+    assert_eq!(
+        resp.header(header::SET_COOKIE),
+        Some(format!("csrf={}; Path=/; SameSite=Strict", token).as_str())
+    );
+
+    // The client's existing token is reused.
+    client
+        .get("/")
+        .header(header::COOKIE, "csrf=abc")
+        .send()
+        .assert_status(StatusCode::OK)
+        .assert_body("abc ")
+        .assert_no_header(header::SET_COOKIE);
+}
+
+#[test]
+fn validates_header() {
+    let client = TestClient::new(middlewares::with_csrf(Csrf::new(), handler));
+
+    client
+        .post("/")
+        .header(header::COOKIE, "csrf=abc")
+        .header("x-csrf-token", "abc")
+        .body("data")
+        .send()
+        .assert_status(StatusCode::OK)
+        .assert_body("abc data");
+
+    client
+        .post("/")
+        .header(header::COOKIE, "csrf=abc")
+        .header("x-csrf-token", "abd")
+        .send()
+        .assert_status(StatusCode::FORBIDDEN);
+
+    client
+        .delete("/")
+        .header(header::COOKIE, "csrf=abc")
+        .send()
+        .assert_status(StatusCode::FORBIDDEN);
+
+    // Clients without a token are rejected, but given one for their next attempt.
+    let resp = client.post("/").header("x-csrf-token", "").send();
+    resp.assert_status(StatusCode::FORBIDDEN)
+        .assert_body("invalid CSRF token");
+    assert!(resp.header(header::SET_COOKIE).is_some());
+}
+
+#[test]
+fn validates_form() {
+    let csrf = Csrf::new().form_field("token");
+    let client = TestClient::new(middlewares::with_csrf(csrf, handler));
+
+    // The body is passed on to the handler unchanged.
+    client
+        .post("/")
+        .header(header::COOKIE, "csrf=abc")
+        .header(
+            header::CONTENT_TYPE,
+            "application/x-www-form-urlencoded; charset=utf-8",
+        )
+        .body("name=alice&token=abc")
+        .send()
+        .assert_status(StatusCode::OK)
+        .assert_body("abc name=alice&token=abc");
+
+    client
+        .post("/")
+        .header(header::COOKIE, "csrf=abc")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body("name=alice&token=abd")
+        .send()
+        .assert_status(StatusCode::FORBIDDEN);
+
+    // Forms are only read when they are URL-encoded.
+    client
+        .post("/")
+        .header(header::COOKIE, "csrf=abc")
+        .header(header::CONTENT_TYPE, "text/plain")
+        .body("token=abc")
+        .send()
+        .assert_status(StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn limits_form_size() {
+    let csrf = Csrf::new().max_form_size(32);
+    let client = TestClient::new(middlewares::with_csrf(csrf, handler));
+    let form = |body: &str| {
+        client
+            .post("/")
+            .header(header::COOKIE, "csrf=abc")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(body.to_owned())
+            .send()
+    };
+
+    form("csrf_token=abc").assert_status(StatusCode::OK);
+    form(&format!("csrf_token=abc&name={}", "a".repeat(32)))
+        .assert_status(StatusCode::PAYLOAD_TOO_LARGE)
+        .assert_body("form too large");
+
+    // Bodies which exceed the limit are rejected before they are read, using their length.
+    client
+        .post("/")
+        .header(header::COOKIE, "csrf=abc")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .header(header::CONTENT_LENGTH, "1000")
+        .body("csrf_token=abc")
+        .send()
+        .assert_status(StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[cfg(feature = "sessions")]
+#[test]
+fn session_bound() {
+    use std::sync::Arc;
+
+    use aitch::sessions::MemoryStore;
+
+    let csrf = Csrf::new().session_key("csrf");
+    let store = Arc::new(MemoryStore::new());
+    let config = middlewares::SessionConfig::new(store.clone());
+    let client = TestClient::new(middlewares::with_sessions(
+        config,
+        middlewares::with_csrf(csrf, handler),
+    ));
+
+    let resp = client.get("/").send();
+    resp.assert_status(StatusCode::OK);
+    let token = resp.text().trim().to_owned();
+    let cookie = resp.header(header::SET_COOKIE).unwrap();
+    let session = cookie.split(';').next().unwrap().to_owned();
+    assert!(session.starts_with("session="));
+    assert_eq!(store.len(), 1);
+
+    client
+        .post("/")
+        .header(header::COOKIE, session.as_str())
+        .header("x-csrf-token", token.as_str())
+        .send()
+        .assert_status(StatusCode::OK);
+
+    // A cookie holding the token is not enough.
+    client
+        .post("/")
+        .header(header::COOKIE, format!("csrf={}", token).as_str())
+        .header("x-csrf-token", token.as_str())
+        .send()
+        .assert_status(StatusCode::FORBIDDEN);
+
+    // Without the sessions middleware, the request fails.
+    let csrf = Csrf::new().session_key("csrf");
+    let client = TestClient::new(middlewares::with_csrf(csrf, handler));
+    assert!(client.get("/").try_send().is_err());
+}
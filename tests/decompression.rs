@@ -0,0 +1,160 @@
+#![cfg(feature = "compression")]
+
+extern crate aitch;
+extern crate brotli;
+extern crate flate2;
+extern crate http;
+
+use std::io::Write;
+
+use aitch::middlewares::{self, Decompression};
+use aitch::testing::TestClient;
+use aitch::ResponseBuilder;
+use http::header;
+use http::{Request, StatusCode};
+
+fn handler(
+    req: Request<String>,
+    mut resp: ResponseBuilder,
+) -> http::Result<http::Response<String>> {
+    assert!(!req.headers().contains_key(header::CONTENT_ENCODING));
+    assert!(!req.headers().contains_key(header::CONTENT_LENGTH));
+    resp.body(req.into_body())
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn brotli(data: &[u8]) -> Vec<u8> {
+    let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22);
+    encoder.write_all(data).unwrap();
+    encoder.into_inner()
+}
+
+#[test]
+fn encodings() {
+    let client = TestClient::new(middlewares::with_decompression(
+        Decompression::default(),
+        handler,
+    ));
+    let body = "{\"hello\": \"world\"}".repeat(100);
+
+    let cases = vec![
+        ("gzip", gzip(body.as_bytes())),
+        ("x-gzip", gzip(body.as_bytes())),
+        ("deflate", deflate(body.as_bytes())),
+        ("br", brotli(body.as_bytes())),
+        ("gzip, br", brotli(&gzip(body.as_bytes()))),
+    ];
+    for (encoding, compressed) in cases {
+        client
+            .post("/")
+            .header(header::CONTENT_ENCODING, encoding)
+            .header(header::CONTENT_LENGTH, compressed.len())
+            .body(compressed)
+            .send()
+            .assert_status(StatusCode::OK)
+            .assert_body(&body);
+    }
+}
+
+#[test]
+fn uncompressed() {
+    let handler = |req: Request<String>, mut resp: ResponseBuilder| {
+        assert!(!req.headers().contains_key(header::CONTENT_ENCODING));
+        resp.body(req.into_body())
+    };
+    let client = TestClient::new(middlewares::with_decompression(
+        Decompression::default(),
+        handler,
+    ));
+
+    client
+        .post("/")
+        .body("plain")
+        .send()
+        .assert_status(StatusCode::OK)
+        .assert_body("plain");
+    client
+        .post("/")
+        .header(header::CONTENT_ENCODING, "identity")
+        .body("plain")
+        .send()
+        .assert_status(StatusCode::OK)
+        .assert_body("plain");
+}
+
+#[test]
+fn unsupported_encoding() {
+    let client = TestClient::new(middlewares::with_decompression(
+        Decompression::default(),
+        handler,
+    ));
+
+    client
+        .post("/")
+        .header(header::CONTENT_ENCODING, "compress")
+        .body("data")
+        .send()
+        .assert_status(StatusCode::UNSUPPORTED_MEDIA_TYPE)
+        .assert_header(header::ACCEPT_ENCODING, "gzip, deflate, br");
+}
+
+#[test]
+fn invalid_body() {
+    let client = TestClient::new(middlewares::with_decompression(
+        Decompression::default(),
+        handler,
+    ));
+
+    client
+        .post("/")
+        .header(header::CONTENT_ENCODING, "gzip")
+        .body("not gzip")
+        .send()
+        .assert_status(StatusCode::BAD_REQUEST);
+
+    let mut truncated = gzip(b"Hello, world!");
+    truncated.truncate(12);
+    client
+        .post("/")
+        .header(header::CONTENT_ENCODING, "gzip")
+        .body(truncated)
+        .send()
+        .assert_status(StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn size_limit() {
+    let decompression = Decompression::new().max_size(1024 * 1024);
+    let client = TestClient::new(middlewares::with_decompression(decompression, handler));
+
+    // Each of these is a few KiB compressed, but 10MiB decompressed.
+    let bomb = vec![0; 10 * 1024 * 1024];
+    for &(encoding, ref compressed) in &[("gzip", gzip(&bomb)), ("br", brotli(&bomb))] {
+        client
+            .post("/")
+            .header(header::CONTENT_ENCODING, encoding)
+            .body(compressed.clone())
+            .send()
+            .assert_status(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    let body = vec![b'a'; 1024 * 1024];
+    client
+        .post("/")
+        .header(header::CONTENT_ENCODING, "gzip")
+        .body(gzip(&body))
+        .send()
+        .assert_status(StatusCode::OK)
+        .assert_body(&body);
+}
@@ -0,0 +1,131 @@
+extern crate aitch;
+extern crate http;
+
+use aitch::middlewares::{self, Guard, Principal, SimpleRouter};
+use aitch::testing::TestClient;
+use aitch::ResponseBuilder;
+use http::{Request, StatusCode};
+
+#[derive(Clone, Debug)]
+struct User {
+    roles: Vec<&'static str>,
+    permissions: Vec<&'static str>,
+}
+
+impl Principal for User {
+    fn has_role(&self, role: &str) -> bool {
+        self.roles.contains(&role)
+    }
+
+    fn has_permission(&self, permission: &str) -> bool {
+        self.permissions.contains(&permission)
+    }
+}
+
+fn user(roles: &[&'static str], permissions: &[&'static str]) -> User {
+    User {
+        roles: roles.to_vec(),
+        permissions: permissions.to_vec(),
+    }
+}
+
+fn handler(_req: Request<()>, mut resp: ResponseBuilder) -> http::Result<http::Response<String>> {
+    resp.body("Hello!".to_owned())
+}
+
+#[test]
+fn roles_and_permissions() {
+    let guard = Guard::<User>::role("admin");
+    let client = TestClient::new(middlewares::with_guard(guard, handler));
+
+    client
+        .get("/")
+        .extension(user(&["admin"], &[]))
+        .send()
+        .assert_status(StatusCode::OK)
+        .assert_body("Hello!");
+    client
+        .get("/")
+        .extension(user(&["viewer"], &["admin"]))
+        .send()
+        .assert_status(StatusCode::FORBIDDEN)
+        .assert_body("forbidden");
+    // Requests without a principal are rejected.
+    client.get("/").send().assert_status(StatusCode::FORBIDDEN);
+
+    let guard = Guard::<User>::permission("orders:write");
+    let client = TestClient::new(middlewares::with_guard(guard, handler));
+    client
+        .get("/")
+        .extension(user(&[], &["orders:write"]))
+        .send()
+        .assert_status(StatusCode::OK);
+    client
+        .get("/")
+        .extension(user(&["orders:write"], &["orders:read"]))
+        .send()
+        .assert_status(StatusCode::FORBIDDEN);
+
+    let client = TestClient::new(middlewares::with_guard(
+        Guard::<User>::authenticated(),
+        handler,
+    ));
+    client
+        .get("/")
+        .extension(user(&[], &[]))
+        .send()
+        .assert_status(StatusCode::OK);
+    client.get("/").send().assert_status(StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn composition() {
+    let guard = Guard::any(vec![
+        Guard::role("admin"),
+        Guard::all(vec![Guard::role("staff"), Guard::permission("orders:read")]),
+    ]);
+    assert!(guard.check(&user(&["admin"], &[])));
+    assert!(guard.check(&user(&["staff"], &["orders:read"])));
+    assert!(!guard.check(&user(&["staff"], &[])));
+    assert!(!guard.check(&user(&[], &["orders:read"])));
+
+    let guard = Guard::role("staff")
+        .and(Guard::permission("orders:read").or(Guard::permission("orders:write")));
+    assert!(guard.check(&user(&["staff"], &["orders:write"])));
+    assert!(!guard.check(&user(&["staff"], &["billing:read"])));
+
+    let guard = Guard::new(|user: &User| user.roles.len() > 1);
+    assert!(guard.check(&user(&["a", "b"], &[])));
+    assert!(!guard.check(&user(&["a"], &[])));
+
+    assert!(!Guard::<User>::any(vec![]).check(&user(&["admin"], &[])));
+    assert!(Guard::<User>::all(vec![]).check(&user(&[], &[])));
+}
+
+#[test]
+fn router() {
+    let mut router = SimpleRouter::new();
+    router.register_handler("/", handler);
+    router.register_handler(
+        "/admin/",
+        middlewares::with_guard(Guard::<User>::role("admin"), handler),
+    );
+    let client = TestClient::new(router);
+
+    let viewer = user(&["viewer"], &[]);
+    client
+        .get("/")
+        .extension(viewer.clone())
+        .send()
+        .assert_status(StatusCode::OK);
+    client
+        .get("/admin/users")
+        .extension(viewer)
+        .send()
+        .assert_status(StatusCode::FORBIDDEN);
+    client
+        .get("/admin/users")
+        .extension(user(&["admin"], &[]))
+        .send()
+        .assert_status(StatusCode::OK);
+}
@@ -6,8 +6,10 @@ extern crate serde;
 extern crate serde_derive;
 
 use std::net::SocketAddr;
+use std::sync::{mpsc, Mutex};
 use std::thread;
 
+use aitch::servers::ConnectionInfo;
 use aitch::{handlers, middlewares, Body, Handler, Json, ResponseBuilder};
 use http::Request;
 
@@ -29,24 +31,6 @@ impl Server {
     }
 }
 
-#[test]
-fn echo_server() {
-    let server = Server::start_in_thread(|req: Request<String>, mut resp: ResponseBuilder| {
-        resp.body(req.into_body())
-    });
-
-    let client = reqwest::Client::new();
-    let mut resp = client
-        .post(&server.path("/"))
-        .body("some body")
-        .send()
-        .unwrap();
-    assert_eq!(resp.status(), reqwest::StatusCode::Ok);
-
-    let body = resp.text().unwrap();
-    assert_eq!(body, "some body");
-}
-
 #[test]
 fn json() {
     #[derive(Deserialize, Serialize)]
@@ -72,16 +56,6 @@ fn json() {
     assert_eq!(body, "some message");
 }
 
-#[test]
-fn status_code() {
-    let server = Server::start_in_thread(|_: Request<()>, mut resp: ResponseBuilder| {
-        resp.status(http::StatusCode::NOT_FOUND).body(())
-    });
-
-    let resp = reqwest::get(&server.path("/")).unwrap();
-    assert_eq!(resp.status(), reqwest::StatusCode::NotFound);
-}
-
 #[test]
 fn router() {
     let handler =
@@ -142,3 +116,45 @@ fn static_file_handler() {
     let body = resp.text().unwrap();
     assert_eq!(body, "hello");
 }
+
+#[test]
+fn error_hook() {
+    let (tx, rx) = mpsc::channel();
+    let tx = Mutex::new(tx);
+
+    let handler = |_: Request<()>, _: ResponseBuilder| -> aitch::Result<http::Response<()>> {
+        Err("handler failed".into())
+    };
+    let addr = "127.0.0.1:0".parse().unwrap();
+    let server = aitch::servers::hyper::Server::new(addr, handler)
+        .unwrap()
+        .with_error_hook(move |err, meta| {
+            let path = meta.map(|meta| meta.uri.path().to_owned());
+            tx.lock().unwrap().send((err.to_string(), path)).unwrap();
+        })
+        .with_error_body("something went wrong");
+    let addr = server.addr();
+    thread::spawn(move || server.run());
+
+    let mut resp = reqwest::get(&format!("http://{}/some/path", addr)).unwrap();
+    assert_eq!(resp.status(), reqwest::StatusCode::InternalServerError);
+    let body = resp.text().unwrap();
+    assert_eq!(body, "something went wrong");
+
+    let (err, path) = rx.recv().unwrap();
+    assert_eq!(err, "handler failed");
+    assert_eq!(path, Some("/some/path".to_owned()));
+}
+
+#[test]
+fn connection_info() {
+    let server = Server::start_in_thread(|req: Request<()>, mut resp: ResponseBuilder| {
+        let info = *req.extensions().get::<ConnectionInfo>().unwrap();
+        resp.body(format!("{} {}", info.remote_addr.ip(), info.secure))
+    });
+
+    let mut resp = reqwest::get(&server.path("/")).unwrap();
+    assert_eq!(resp.status(), reqwest::StatusCode::Ok);
+    let body = resp.text().unwrap();
+    assert_eq!(body, "127.0.0.1 false");
+}
@@ -0,0 +1,86 @@
+extern crate aitch;
+extern crate futures;
+extern crate http;
+
+use aitch::middlewares::{self, PanicError};
+use aitch::testing::TestClient;
+use aitch::ResponseBuilder;
+use futures::{future, Future};
+use http::{Request, StatusCode};
+
+#[test]
+fn no_panic() {
+    let handler = |_: Request<()>, mut resp: ResponseBuilder| resp.body("ok".to_owned());
+    let client = TestClient::new(middlewares::with_panic_recovery(handler));
+
+    client
+        .get("/")
+        .send()
+        .assert_status(StatusCode::OK)
+        .assert_body("ok");
+}
+
+#[test]
+fn handler_panics() {
+    let handler = |req: Request<()>, mut resp: ResponseBuilder| {
+        if req.uri().path() == "/panic" {
+            panic!("handler failed: {}", 42);
+        }
+        resp.body("ok".to_owned())
+    };
+    middlewares::install_panic_hook();
+    let client = TestClient::new(middlewares::with_panic_recovery(handler));
+
+    let err = client.get("/panic").try_send().unwrap_err();
+    let panic = err.downcast_ref::<PanicError>().unwrap();
+    assert_eq!(panic.message(), "handler failed: 42");
+    assert!(panic
+        .location()
+        .unwrap()
+        .starts_with("tests/panic_recovery.rs:"));
+    assert!(err
+        .to_string()
+        .starts_with("handler panicked at 'handler failed: 42', tests/"));
+
+    // The handler continues to work after a panic.
+    client.get("/").send().assert_status(StatusCode::OK);
+}
+
+#[test]
+fn responder_panics() {
+    let handler = |_: Request<()>, mut resp: ResponseBuilder| {
+        future::lazy(move || {
+            if true {
+                panic!("responder failed");
+            }
+            resp.body("unreachable".to_owned())
+        })
+    };
+    middlewares::install_panic_hook();
+    let client = TestClient::new(middlewares::with_panic_recovery(handler));
+
+    let err = client.get("/").try_send().unwrap_err();
+    let panic = err.downcast_ref::<PanicError>().unwrap();
+    assert_eq!(panic.message(), "responder failed");
+    assert!(panic.location().is_some());
+}
+
+#[test]
+fn non_string_payload() {
+    let handler = |_: Request<()>, mut resp: ResponseBuilder| {
+        future::ok::<(), aitch::Error>(()).and_then(move |_| {
+            if true {
+                std::panic::panic_any(42);
+            }
+            resp.body("unreachable".to_owned())
+                .map_err(aitch::Error::from)
+        })
+    };
+    let client = TestClient::new(middlewares::with_panic_recovery(handler));
+
+    let err = client.get("/").try_send().unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PanicError>().unwrap().message(),
+        "Box<Any>"
+    );
+}
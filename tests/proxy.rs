@@ -0,0 +1,198 @@
+#![cfg(all(feature = "proxy", feature = "server-hyper"))]
+
+extern crate aitch;
+extern crate http;
+
+use std::net::{SocketAddr, TcpListener};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use aitch::handlers::proxy::{proxy_handler, Proxy, Upstream};
+use aitch::servers::hyper::Server;
+use aitch::testing::TestClient;
+use aitch::{Body, Handler, ResponseBuilder};
+use http::header;
+use http::{Request, StatusCode};
+
+fn start_upstream<B: Body>(handler: impl Handler<B>) -> String {
+    let addr = "127.0.0.1:0".parse().unwrap();
+    let server = Server::new(addr, handler).unwrap();
+    let addr = server.addr();
+    thread::spawn(move || server.run());
+    format!("http://{}", addr)
+}
+
+// Returns an upstream which responds with `name`, and whose health check fails when `healthy` is
+// false.
+fn named_upstream(name: &'static str, healthy: Arc<AtomicBool>) -> String {
+    start_upstream(move |req: Request<()>, mut resp: ResponseBuilder| {
+        if req.uri().path() == "/health" && !healthy.load(Ordering::SeqCst) {
+            resp.status(StatusCode::SERVICE_UNAVAILABLE);
+        }
+        resp.body(name.to_owned())
+    })
+}
+
+#[test]
+fn forwards_requests() {
+    let upstream = start_upstream(|req: Request<String>, mut resp: ResponseBuilder| {
+        let header = |name: &str| {
+            req.headers()
+                .get(name)
+                .map(|value| value.to_str().unwrap())
+                .unwrap_or("-")
+        };
+        let lines = [
+            format!("{} {}", req.method(), req.uri()),
+            format!("host: {}", header("host")),
+            format!("x-secret: {}", header("x-secret")),
+            format!("x-forwarded-for: {}", header("x-forwarded-for")),
+            format!("x-forwarded-host: {}", header("x-forwarded-host")),
+            format!("x-forwarded-proto: {}", header("x-forwarded-proto")),
+            format!("forwarded: {}", header("forwarded")),
+            format!("body: {}", req.body()),
+        ];
+        resp.header("keep-alive", "timeout=5")
+            .header("x-upstream", "1")
+            .body(lines.join("\n"))
+    });
+    let upstream_host = upstream.trim_start_matches("http://").to_owned();
+    let proxy = Proxy::new().upstream(Upstream::new(&format!("{}/api/", upstream)).unwrap());
+    let client = TestClient::new(proxy_handler(proxy).unwrap());
+
+    let resp = client
+        .post("/users?page=2")
+        .header(header::HOST, "example.com")
+        .header(header::CONNECTION, "x-secret")
+        .header("x-secret", "hunter2")
+        .header("x-forwarded-for", "203.0.113.7")
+        .header(header::CONTENT_LENGTH, "5")
+        .remote_addr(([192, 0, 2, 43], 1234).into())
+        .body("hello")
+        .send();
+    resp.assert_status(StatusCode::OK)
+        .assert_header("x-upstream", "1")
+        .assert_no_header("keep-alive");
+    let expected = [
+        "POST /api/users?page=2".to_owned(),
+        format!("host: {}", upstream_host),
+        "x-secret: -".to_owned(),
+        "x-forwarded-for: 203.0.113.7, 192.0.2.43".to_owned(),
+        "x-forwarded-host: example.com".to_owned(),
+        "x-forwarded-proto: http".to_owned(),
+        "forwarded: for=192.0.2.43;proto=http;host=example.com".to_owned(),
+        "body: hello".to_owned(),
+    ];
+    assert_eq!(resp.text(), expected.join("\n"));
+
+    // Requests without a body are forwarded without one.
+    let resp = client.get("/").send();
+    assert!(resp.text().starts_with("GET /api/\n"));
+    assert!(resp.text().ends_with("body: "));
+}
+
+#[test]
+fn streams_large_bodies() {
+    let upstream = start_upstream(|req: Request<Vec<u8>>, mut resp: ResponseBuilder| {
+        let mut body = req.into_body();
+        body.reverse();
+        resp.body(body)
+    });
+    let proxy = Proxy::new().upstream(Upstream::new(&upstream).unwrap());
+    let client = TestClient::new(proxy_handler(proxy).unwrap());
+
+    let body = (0..1024 * 1024)
+        .map(|i| (i % 251) as u8)
+        .collect::<Vec<_>>();
+    let resp = client
+        .put("/")
+        .header(header::CONTENT_LENGTH, body.len().to_string().as_str())
+        .body(body.clone())
+        .send();
+    let mut expected = body;
+    expected.reverse();
+    resp.assert_status(StatusCode::OK).assert_body(expected);
+}
+
+#[test]
+fn round_robin_and_health_checks() {
+    let a = named_upstream("a", Arc::new(AtomicBool::new(true)));
+    let b_healthy = Arc::new(AtomicBool::new(false));
+    let b = named_upstream("b", b_healthy.clone());
+
+    // Without health checks, both upstreams are used.
+    let proxy = Proxy::new()
+        .upstream(Upstream::new(&a).unwrap())
+        .upstream(Upstream::new(&b).unwrap());
+    let client = TestClient::new(proxy_handler(proxy).unwrap());
+    let bodies = (0..4)
+        .map(|_| client.get("/").send().text().to_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(bodies, vec!["a", "b", "a", "b"]);
+
+    let proxy = Proxy::new()
+        .upstream(Upstream::new(&a).unwrap())
+        .upstream(Upstream::new(&b).unwrap())
+        .health_check("/health", Duration::from_millis(100));
+    let client = TestClient::new(proxy_handler(proxy).unwrap());
+    thread::sleep(Duration::from_millis(300));
+    for _ in 0..4 {
+        client.get("/").send().assert_body("a");
+    }
+
+    b_healthy.store(true, Ordering::SeqCst);
+    thread::sleep(Duration::from_millis(300));
+    let bodies = (0..4)
+        .map(|_| client.get("/").send().text().to_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(bodies.iter().filter(|body| *body == "b").count(), 2);
+}
+
+#[test]
+fn upstream_errors() {
+    // Reserve a port which nothing is listening on.
+    let closed = {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr: SocketAddr = listener.local_addr().unwrap();
+        format!("http://{}", addr)
+    };
+    let proxy = Proxy::new().upstream(Upstream::new(&closed).unwrap());
+    let client = TestClient::new(proxy_handler(proxy).unwrap());
+    client
+        .get("/")
+        .send()
+        .assert_status(StatusCode::BAD_GATEWAY)
+        .assert_body("upstream unavailable");
+
+    let slow = start_upstream(|_req: Request<()>, mut resp: ResponseBuilder| {
+        thread::sleep(Duration::from_millis(500));
+        resp.body("slow".to_owned())
+    });
+    let proxy = Proxy::new().upstream(
+        Upstream::new(&slow)
+            .unwrap()
+            .timeout(Duration::from_millis(100)),
+    );
+    let client = TestClient::new(proxy_handler(proxy).unwrap());
+    client
+        .get("/")
+        .send()
+        .assert_status(StatusCode::GATEWAY_TIMEOUT)
+        .assert_body("upstream timed out");
+
+    // When every upstream fails its health check, no requests are forwarded.
+    let proxy = Proxy::new()
+        .upstream(Upstream::new(&closed).unwrap())
+        .health_check("/health", Duration::from_secs(60));
+    let client = TestClient::new(proxy_handler(proxy).unwrap());
+    thread::sleep(Duration::from_millis(200));
+    client
+        .get("/")
+        .send()
+        .assert_status(StatusCode::SERVICE_UNAVAILABLE)
+        .assert_body("no healthy upstream");
+
+    assert!(proxy_handler(Proxy::new()).is_err());
+}
@@ -0,0 +1,165 @@
+extern crate aitch;
+extern crate futures;
+extern crate http;
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use aitch::middlewares::{
+    self, Quota, RateLimit, RateLimitDecision, RateLimitFuture, RateLimitStore,
+};
+use aitch::testing::TestClient;
+use aitch::ResponseBuilder;
+use futures::future;
+use http::header::{self, HeaderName};
+use http::{Request, StatusCode};
+
+fn handler(_: Request<()>, mut resp: ResponseBuilder) -> http::Result<http::Response<String>> {
+    resp.body("Hello, world!".to_owned())
+}
+
+#[test]
+fn limits_by_ip() {
+    let rate_limit = RateLimit::new(Quota::per_minute(2));
+    let client = TestClient::new(middlewares::with_rate_limit(rate_limit, handler));
+    let alice = "10.0.0.1:1234".parse().unwrap();
+    let bob = "10.0.0.2:1234".parse().unwrap();
+
+    client
+        .get("/")
+        .remote_addr(alice)
+        .send()
+        .assert_status(StatusCode::OK)
+        .assert_header("RateLimit-Limit", "2")
+        .assert_header("RateLimit-Remaining", "1")
+        .assert_header("RateLimit-Reset", "30")
+        .assert_no_header(header::RETRY_AFTER);
+    client
+        .get("/")
+        .remote_addr(alice)
+        .send()
+        .assert_status(StatusCode::OK)
+        .assert_header("RateLimit-Remaining", "0");
+    client
+        .get("/")
+        .remote_addr(alice)
+        .send()
+        .assert_status(StatusCode::TOO_MANY_REQUESTS)
+        .assert_header("RateLimit-Remaining", "0")
+        .assert_header(header::RETRY_AFTER, "30");
+
+    // Requests from a different port on the same IP share a quota.
+    client
+        .get("/")
+        .remote_addr("10.0.0.1:5678".parse().unwrap())
+        .send()
+        .assert_status(StatusCode::TOO_MANY_REQUESTS);
+
+    client
+        .get("/")
+        .remote_addr(bob)
+        .send()
+        .assert_status(StatusCode::OK);
+}
+
+#[test]
+fn refills() {
+    let rate_limit = RateLimit::new(Quota::new(1, Duration::from_millis(50)));
+    let client = TestClient::new(middlewares::with_rate_limit(rate_limit, handler));
+
+    client.get("/").send().assert_status(StatusCode::OK);
+    client
+        .get("/")
+        .send()
+        .assert_status(StatusCode::TOO_MANY_REQUESTS)
+        .assert_header(header::RETRY_AFTER, "1");
+
+    thread::sleep(Duration::from_millis(60));
+    client.get("/").send().assert_status(StatusCode::OK);
+}
+
+#[test]
+fn key_header() {
+    let rate_limit =
+        RateLimit::new(Quota::per_minute(1)).key_header(HeaderName::from_static("x-api-key"));
+    let client = TestClient::new(middlewares::with_rate_limit(rate_limit, handler));
+
+    for key in &["a", "b"] {
+        client
+            .get("/")
+            .header("X-Api-Key", *key)
+            .send()
+            .assert_status(StatusCode::OK);
+        client
+            .get("/")
+            .header("X-Api-Key", *key)
+            .send()
+            .assert_status(StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    // Requests without a key aren't limited.
+    for _ in 0..5 {
+        client
+            .get("/")
+            .send()
+            .assert_status(StatusCode::OK)
+            .assert_no_header("RateLimit-Limit");
+    }
+}
+
+struct UserId(String);
+
+#[test]
+fn key_from_extensions() {
+    let rate_limit = RateLimit::new(Quota::per_minute(1)).key(|parts| {
+        parts
+            .extensions
+            .get::<UserId>()
+            .map(|user| format!("user:{}", user.0))
+    });
+    let client = TestClient::new(middlewares::with_rate_limit(rate_limit, handler));
+
+    client
+        .get("/")
+        .extension(UserId("alice".to_owned()))
+        .send()
+        .assert_status(StatusCode::OK);
+    client
+        .get("/")
+        .extension(UserId("alice".to_owned()))
+        .send()
+        .assert_status(StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[derive(Clone, Default)]
+struct RecordingStore(Arc<Mutex<Vec<String>>>);
+
+impl RateLimitStore for RecordingStore {
+    fn acquire(&self, key: &str, quota: &Quota) -> RateLimitFuture {
+        self.0.lock().unwrap().push(key.to_owned());
+        Box::new(future::ok(RateLimitDecision {
+            allowed: key != "127.0.0.1",
+            limit: quota.max_burst(),
+            remaining: 0,
+            reset: Duration::from_millis(1500),
+            retry_after: Some(Duration::from_secs(7)),
+        }))
+    }
+}
+
+#[test]
+fn custom_store() {
+    let store = RecordingStore::default();
+    let rate_limit = RateLimit::new(Quota::per_second(5)).store(store.clone());
+    let client = TestClient::new(middlewares::with_rate_limit(rate_limit, handler));
+
+    client
+        .get("/")
+        .send()
+        .assert_status(StatusCode::TOO_MANY_REQUESTS)
+        .assert_header("RateLimit-Limit", "5")
+        .assert_header("RateLimit-Reset", "2")
+        .assert_header(header::RETRY_AFTER, "7");
+    assert_eq!(*store.0.lock().unwrap(), vec!["127.0.0.1".to_owned()]);
+}
@@ -0,0 +1,140 @@
+extern crate aitch;
+extern crate http;
+
+#[cfg(feature = "server-hyper")]
+use std::io::{Read, Write};
+#[cfg(feature = "server-hyper")]
+use std::net::TcpStream;
+#[cfg(feature = "server-hyper")]
+use std::sync::{mpsc, Mutex};
+#[cfg(feature = "server-hyper")]
+use std::thread;
+
+use aitch::middlewares::{self, RequestId, RequestIdConfig};
+#[cfg(feature = "server-hyper")]
+use aitch::servers::hyper::Server;
+use aitch::testing::TestClient;
+use aitch::ResponseBuilder;
+use http::Request;
+
+fn handler(req: Request<()>, mut resp: ResponseBuilder) -> http::Result<http::Response<String>> {
+    let id = req.extensions().get::<RequestId>().unwrap();
+    let header = req.headers().get("x-request-id").unwrap();
+    assert_eq!(id.as_str(), header.to_str().unwrap());
+    resp.body(id.to_string())
+}
+
+#[test]
+fn generates_id() {
+    let client = TestClient::new(middlewares::with_request_id(
+        RequestIdConfig::default(),
+        handler,
+    ));
+
+    let first = client.get("/").send();
+    let id = first.header("x-request-id").unwrap();
+    assert_eq!(id.len(), 32);
+    assert!(id.chars().all(|c| c.is_ascii_hexdigit()));
+    first.assert_body(id);
+
+    let second = client.get("/").send();
+    assert_ne!(second.header("x-request-id").unwrap(), id);
+}
+
+#[test]
+fn propagates_incoming_id() {
+    let client = TestClient::new(middlewares::with_request_id(
+        RequestIdConfig::default(),
+        handler,
+    ));
+
+    client
+        .get("/")
+        .header("X-Request-Id", "upstream-id")
+        .send()
+        .assert_header("x-request-id", "upstream-id")
+        .assert_body("upstream-id");
+}
+
+#[test]
+fn replaces_invalid_incoming_id() {
+    let client = TestClient::new(middlewares::with_request_id(
+        RequestIdConfig::default(),
+        handler,
+    ));
+
+    let long = "a".repeat(1000);
+    let resp = client.get("/").header("X-Request-Id", long.as_str()).send();
+    assert_ne!(resp.header("x-request-id").unwrap(), long);
+}
+
+#[test]
+fn custom_header_and_generator() {
+    let config = RequestIdConfig::new()
+        .header(http::header::HeaderName::from_static("x-correlation-id"))
+        .generator(|| "fixed".to_owned());
+    let handler = |req: Request<()>, mut resp: ResponseBuilder| {
+        let id = req.extensions().get::<RequestId>().unwrap().clone();
+        resp.body(id.to_string())
+    };
+    let client = TestClient::new(middlewares::with_request_id(config, handler));
+
+    client
+        .get("/")
+        .send()
+        .assert_header("x-correlation-id", "fixed")
+        .assert_no_header("x-request-id")
+        .assert_body("fixed");
+}
+
+#[test]
+fn passes_errors_through() {
+    let handler = |_: Request<()>, _: ResponseBuilder| -> aitch::Result<http::Response<()>> {
+        Err("handler failed".into())
+    };
+    let client = TestClient::new(middlewares::with_request_id(
+        RequestIdConfig::default(),
+        handler,
+    ));
+
+    let err = client
+        .get("/")
+        .header("X-Request-Id", "abc")
+        .try_send()
+        .unwrap_err();
+    assert_eq!(err.to_string(), "handler failed");
+}
+
+#[test]
+#[cfg(feature = "server-hyper")]
+fn reports_id_to_error_hook() {
+    let handler = |_: Request<()>, _: ResponseBuilder| -> aitch::Result<http::Response<()>> {
+        Err("handler failed".into())
+    };
+    let wrapped = middlewares::with_request_id(RequestIdConfig::default(), handler);
+
+    let (tx, rx) = mpsc::channel();
+    let tx = Mutex::new(tx);
+    let server = Server::new("127.0.0.1:0".parse().unwrap(), wrapped)
+        .unwrap()
+        .with_error_hook(move |err, meta| {
+            let id = meta.and_then(|meta| meta.request_id.clone());
+            tx.lock().unwrap().send((err.to_string(), id)).unwrap();
+        });
+    let addr = server.addr();
+    thread::spawn(move || server.run());
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .write_all(
+            b"GET / HTTP/1.1\r\nHost: localhost\r\nX-Request-Id: abc\r\nConnection: close\r\n\r\n",
+        )
+        .unwrap();
+    let mut resp = String::new();
+    stream.read_to_string(&mut resp).unwrap();
+    assert!(resp.starts_with("HTTP/1.1 500 "));
+
+    let (err, id) = rx.recv().unwrap();
+    assert_eq!(err, "handler failed");
+    assert_eq!(id.as_ref().map(RequestId::as_str), Some("abc"));
+}
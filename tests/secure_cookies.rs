@@ -0,0 +1,97 @@
+#![cfg(feature = "secure-cookies")]
+
+extern crate aitch;
+extern crate http;
+
+use aitch::cookies::{Cookie, CookieJar, CookieKeys, Key, PrivateCookies, SignedCookies};
+use aitch::middlewares;
+use aitch::testing::TestClient;
+use aitch::ResponseBuilder;
+use http::header;
+use http::{Request, StatusCode};
+
+fn keys() -> CookieKeys {
+    CookieKeys::new(Key::derive_from(b"an example very very secret key."))
+}
+
+fn handler(req: Request<()>, mut resp: ResponseBuilder) -> http::Result<http::Response<String>> {
+    let signed = req.extensions().get::<SignedCookies>().unwrap();
+    let private = req.extensions().get::<PrivateCookies>().unwrap();
+    if req.uri().path() == "/login" {
+        signed.add(Cookie::new("user", "alice"));
+        private.add(Cookie::new("token", "s3cret"));
+        return resp.body(String::new());
+    }
+
+    let user = signed.get("user").map(|cookie| cookie.value().to_owned());
+    let token = private.get("token").map(|cookie| cookie.value().to_owned());
+    resp.body(format!("{:?} {:?}", user, token))
+}
+
+// Converts the response's `Set-Cookie` headers into a `Cookie` header, as a browser would.
+fn cookie_header(resp: &aitch::testing::TestResponse) -> String {
+    let cookies: Vec<_> = resp
+        .headers()
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .map(|value| value.to_str().unwrap().to_owned())
+        .collect();
+    cookies.join("; ")
+}
+
+#[test]
+fn round_trip() {
+    let client = TestClient::new(middlewares::with_cookie_keys(keys(), handler));
+
+    let login = client.get("/login").send();
+    login.assert_status(StatusCode::OK);
+    let cookies = cookie_header(&login);
+    assert!(cookies.contains("user=alice."));
+    assert!(!cookies.contains("s3cret"));
+
+    client
+        .get("/")
+        .header(header::COOKIE, cookies)
+        .send()
+        .assert_body("Some(\"alice\") Some(\"s3cret\")");
+}
+
+#[test]
+fn rejects_tampered_cookies() {
+    let client = TestClient::new(middlewares::with_cookie_keys(keys(), handler));
+
+    let login = client.get("/login").send();
+    let cookies = cookie_header(&login).replace("user=alice.", "user=admin.");
+    client
+        .get("/")
+        .header(header::COOKIE, cookies)
+        .send()
+        .assert_body("None Some(\"s3cret\")");
+
+    client
+        .get("/")
+        .header(header::COOKIE, "user=alice; token=s3cret")
+        .send()
+        .assert_body("None None");
+}
+
+#[test]
+fn shares_jar_with_with_cookies() {
+    let handler = |req: Request<()>, mut resp: ResponseBuilder| {
+        let jar = req.extensions().get::<CookieJar>().unwrap();
+        let signed = req.extensions().get::<SignedCookies>().unwrap();
+        jar.add(Cookie::new("plain", "1"));
+        signed.add(Cookie::new("signed", "2"));
+        resp.body(())
+    };
+    let client = TestClient::new(middlewares::with_cookies(middlewares::with_cookie_keys(
+        keys(),
+        handler,
+    )));
+
+    let resp = client.get("/").send();
+    let set_cookies: Vec<_> = resp.headers().get_all(header::SET_COOKIE).iter().collect();
+    assert_eq!(set_cookies.len(), 2);
+    assert_eq!(set_cookies[0], "plain=1");
+    assert!(set_cookies[1].to_str().unwrap().starts_with("signed=2."));
+}
@@ -0,0 +1,146 @@
+extern crate aitch;
+extern crate http;
+
+use std::time::Duration;
+
+use aitch::middlewares::{self, CspNonce, SecurityHeaders, SimpleRouter};
+use aitch::testing::TestClient;
+use aitch::ResponseBuilder;
+use http::header;
+use http::{Request, StatusCode};
+
+fn handler(_: Request<()>, mut resp: ResponseBuilder) -> http::Result<http::Response<String>> {
+    resp.body("Hello, world!".to_owned())
+}
+
+#[test]
+fn defaults() {
+    let client = TestClient::new(middlewares::with_security_headers(
+        SecurityHeaders::default(),
+        handler,
+    ));
+
+    client
+        .get("/")
+        .send()
+        .assert_status(StatusCode::OK)
+        .assert_header(
+            header::STRICT_TRANSPORT_SECURITY,
+            "max-age=31536000; includeSubDomains",
+        )
+        .assert_header(header::X_CONTENT_TYPE_OPTIONS, "nosniff")
+        .assert_header(header::X_FRAME_OPTIONS, "DENY")
+        .assert_header(header::REFERRER_POLICY, "strict-origin-when-cross-origin")
+        .assert_header(
+            header::CONTENT_SECURITY_POLICY,
+            "default-src 'self'; frame-ancestors 'none'",
+        );
+}
+
+#[test]
+fn configured() {
+    let config = SecurityHeaders::new()
+        .hsts(Some(Duration::from_secs(600)))
+        .hsts_include_subdomains(false)
+        .hsts_preload(true)
+        .content_type_options(false)
+        .frame_options(Some("SAMEORIGIN"))
+        .referrer_policy(None)
+        .content_security_policy(None);
+    let client = TestClient::new(middlewares::with_security_headers(config, handler));
+
+    client
+        .get("/")
+        .send()
+        .assert_header(header::STRICT_TRANSPORT_SECURITY, "max-age=600; preload")
+        .assert_header(header::X_FRAME_OPTIONS, "SAMEORIGIN")
+        .assert_no_header(header::X_CONTENT_TYPE_OPTIONS)
+        .assert_no_header(header::REFERRER_POLICY)
+        .assert_no_header(header::CONTENT_SECURITY_POLICY);
+}
+
+#[test]
+fn handler_headers_take_precedence() {
+    let handler = |_: Request<()>, mut resp: ResponseBuilder| {
+        resp.header(header::X_FRAME_OPTIONS, "SAMEORIGIN").body(())
+    };
+    let client = TestClient::new(middlewares::with_security_headers(
+        SecurityHeaders::default(),
+        handler,
+    ));
+
+    client
+        .get("/")
+        .send()
+        .assert_header(header::X_FRAME_OPTIONS, "SAMEORIGIN")
+        .assert_header(header::X_CONTENT_TYPE_OPTIONS, "nosniff");
+}
+
+#[test]
+fn per_route_overrides() {
+    let mut router = SimpleRouter::new();
+    router.register_handler("/", handler);
+    let embeddable = SecurityHeaders::new()
+        .frame_options(None)
+        .content_security_policy(Some("default-src *"));
+    router.register_handler(
+        "/widget",
+        middlewares::with_security_headers(embeddable, handler),
+    );
+    let client = TestClient::new(middlewares::with_security_headers(
+        SecurityHeaders::default(),
+        router,
+    ));
+
+    client
+        .get("/")
+        .send()
+        .assert_header(header::X_FRAME_OPTIONS, "DENY")
+        .assert_header(
+            header::CONTENT_SECURITY_POLICY,
+            "default-src 'self'; frame-ancestors 'none'",
+        );
+    client
+        .get("/widget")
+        .send()
+        .assert_no_header(header::X_FRAME_OPTIONS)
+        .assert_header(header::CONTENT_SECURITY_POLICY, "default-src *")
+        .assert_header(header::X_CONTENT_TYPE_OPTIONS, "nosniff");
+}
+
+#[test]
+fn csp_nonce() {
+    let handler = |req: Request<()>, mut resp: ResponseBuilder| {
+        let nonce = req.extensions().get::<CspNonce>().unwrap();
+        resp.body(nonce.to_string())
+    };
+    let config = SecurityHeaders::new().content_security_policy(Some(
+        "script-src 'nonce-{nonce}'; style-src 'nonce-{nonce}'",
+    ));
+    let client = TestClient::new(middlewares::with_security_headers(config, handler));
+
+    let first = client.get("/").send();
+    let nonce = first.text();
+    assert_eq!(nonce.len(), 32);
+    first.assert_header(
+        header::CONTENT_SECURITY_POLICY,
+        &format!("script-src 'nonce-{0}'; style-src 'nonce-{0}'", nonce),
+    );
+
+    let second = client.get("/").send();
+    assert_ne!(second.text(), nonce);
+}
+
+#[test]
+fn no_nonce_without_placeholder() {
+    let handler = |req: Request<()>, mut resp: ResponseBuilder| {
+        assert!(req.extensions().get::<CspNonce>().is_none());
+        resp.body(())
+    };
+    let client = TestClient::new(middlewares::with_security_headers(
+        SecurityHeaders::default(),
+        handler,
+    ));
+
+    client.get("/").send().assert_status(StatusCode::OK);
+}
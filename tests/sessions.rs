@@ -0,0 +1,228 @@
+#![cfg(feature = "sessions")]
+
+extern crate aitch;
+extern crate http;
+#[macro_use]
+extern crate serde_derive;
+
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use aitch::cookies::Cookie;
+use aitch::middlewares::{self, SessionConfig};
+use aitch::sessions::{MemoryStore, Session};
+use aitch::testing::{TestClient, TestResponse};
+use aitch::ResponseBuilder;
+use http::header;
+use http::{Request, StatusCode};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct User {
+    name: String,
+    admin: bool,
+}
+
+fn handler(req: Request<()>, mut resp: ResponseBuilder) -> http::Result<http::Response<String>> {
+    let session = req.extensions().get::<Session>().unwrap().clone();
+    match req.uri().path() {
+        "/login" => {
+            session.regenerate();
+            session
+                .insert(
+                    "user",
+                    User {
+                        name: "alice".to_owned(),
+                        admin: false,
+                    },
+                )
+                .unwrap();
+        }
+        "/logout" => session.destroy(),
+        "/count" => {
+            let count = session.get::<u32>("count").unwrap_or(0);
+            session.insert("count", count + 1).unwrap();
+        }
+        _ => {}
+    }
+
+    let user = session.get::<User>("user").map(|user| user.name);
+    let count = session.get::<u32>("count");
+    resp.body(format!("{:?} {:?}", user, count))
+}
+
+// Returns the session ID set by the response, if any.
+fn session_id(resp: &TestResponse) -> Option<String> {
+    let value = resp.header(header::SET_COOKIE)?;
+    let value = value.split(';').next().unwrap();
+    Some(value["session=".len()..].to_owned())
+}
+
+fn cookie(id: &str) -> String {
+    format!("session={}", id)
+}
+
+#[test]
+fn new_sessions() {
+    let store = Arc::new(MemoryStore::new());
+    let client = TestClient::new(middlewares::with_sessions(
+        SessionConfig::new(store.clone()),
+        handler,
+    ));
+
+    // Sessions aren't created until something is stored in them.
+    client
+        .get("/")
+        .send()
+        .assert_status(StatusCode::OK)
+        .assert_body("None None")
+        .assert_no_header(header::SET_COOKIE);
+    assert!(store.is_empty());
+
+    let resp = client.get("/count").send();
+    resp.assert_body("None Some(1)");
+    let id = session_id(&resp).unwrap();
+    assert_eq!(id.len(), 64);
+    assert_eq!(
+        resp.header(header::SET_COOKIE).unwrap(),
+        format!("session={}; Path=/; HttpOnly; SameSite=Lax", id)
+    );
+    assert_eq!(store.len(), 1);
+
+    // The cookie is only sent when the session ID changes.
+    let resp = client
+        .get("/count")
+        .header(header::COOKIE, cookie(&id))
+        .send();
+    resp.assert_body("None Some(2)")
+        .assert_no_header(header::SET_COOKIE);
+
+    // Unknown IDs are treated as new sessions.
+    client
+        .get("/")
+        .header(header::COOKIE, cookie("unknown"))
+        .send()
+        .assert_body("None None");
+}
+
+#[test]
+fn regenerate_and_destroy() {
+    let store = Arc::new(MemoryStore::new());
+    let client = TestClient::new(middlewares::with_sessions(
+        SessionConfig::new(store.clone()),
+        handler,
+    ));
+
+    let anonymous = session_id(&client.get("/count").send()).unwrap();
+
+    let resp = client
+        .get("/login")
+        .header(header::COOKIE, cookie(&anonymous))
+        .send();
+    resp.assert_body("Some(\"alice\") Some(1)");
+    let logged_in = session_id(&resp).unwrap();
+    assert_ne!(logged_in, anonymous);
+    assert_eq!(store.len(), 1);
+
+    client
+        .get("/")
+        .header(header::COOKIE, cookie(&anonymous))
+        .send()
+        .assert_body("None None");
+    client
+        .get("/")
+        .header(header::COOKIE, cookie(&logged_in))
+        .send()
+        .assert_body("Some(\"alice\") Some(1)");
+
+    let resp = client
+        .get("/logout")
+        .header(header::COOKIE, cookie(&logged_in))
+        .send();
+    assert!(resp
+        .header(header::SET_COOKIE)
+        .unwrap()
+        .starts_with("session=; Path=/; Expires=Thu, 01 Jan 1970 00:00:00 GMT; Max-Age=0"));
+    assert!(store.is_empty());
+    client
+        .get("/")
+        .header(header::COOKIE, cookie(&logged_in))
+        .send()
+        .assert_body("None None");
+}
+
+#[test]
+fn idle_timeout() {
+    let config = SessionConfig::new(MemoryStore::new()).idle_timeout(Duration::from_millis(100));
+    let client = TestClient::new(middlewares::with_sessions(config, handler));
+
+    let id = session_id(&client.get("/count").send()).unwrap();
+    for count in 2..5 {
+        thread::sleep(Duration::from_millis(40));
+        client
+            .get("/count")
+            .header(header::COOKIE, cookie(&id))
+            .send()
+            .assert_body(format!("None Some({})", count));
+    }
+
+    thread::sleep(Duration::from_millis(150));
+    client
+        .get("/")
+        .header(header::COOKIE, cookie(&id))
+        .send()
+        .assert_body("None None");
+}
+
+#[test]
+fn absolute_timeout() {
+    let config = SessionConfig::new(MemoryStore::new())
+        .idle_timeout(Duration::from_secs(60))
+        .absolute_timeout(Duration::from_millis(100));
+    let client = TestClient::new(middlewares::with_sessions(config, handler));
+
+    let id = session_id(&client.get("/count").send()).unwrap();
+    thread::sleep(Duration::from_millis(50));
+    client
+        .get("/count")
+        .header(header::COOKIE, cookie(&id))
+        .send()
+        .assert_body("None Some(2)");
+
+    thread::sleep(Duration::from_millis(100));
+    client
+        .get("/")
+        .header(header::COOKIE, cookie(&id))
+        .send()
+        .assert_body("None None");
+}
+
+#[test]
+fn unbounded_timeouts() {
+    let never = Duration::from_secs(u64::MAX);
+    let store = Arc::new(MemoryStore::new());
+    let config = SessionConfig::new(store.clone())
+        .idle_timeout(never)
+        .absolute_timeout(never);
+    let client = TestClient::new(middlewares::with_sessions(config, handler));
+
+    let id = session_id(&client.get("/count").send()).unwrap();
+    client
+        .get("/count")
+        .header(header::COOKIE, cookie(&id))
+        .send()
+        .assert_body("None Some(2)");
+    assert_eq!(store.len(), 1);
+}
+
+#[test]
+fn custom_cookie() {
+    let config = SessionConfig::new(MemoryStore::new())
+        .cookie(Cookie::named("sid").path("/app").secure(true));
+    let client = TestClient::new(middlewares::with_sessions(config, handler));
+
+    let resp = client.get("/count").send();
+    let set_cookie = resp.header(header::SET_COOKIE).unwrap();
+    assert!(set_cookie.starts_with("sid="));
+    assert!(set_cookie.ends_with("; Path=/app; Secure"));
+}
@@ -0,0 +1,111 @@
+extern crate aitch;
+extern crate http;
+#[macro_use]
+extern crate serde_derive;
+
+use aitch::servers::ConnectionInfo;
+use aitch::testing::TestClient;
+use aitch::{middlewares, Json, ResponseBuilder};
+use http::Request;
+
+#[test]
+fn echo() {
+    let client = TestClient::new(|req: Request<String>, mut resp: ResponseBuilder| {
+        resp.body(req.into_body())
+    });
+
+    client
+        .post("/")
+        .body("some body")
+        .send()
+        .assert_status(http::StatusCode::OK)
+        .assert_body("some body");
+}
+
+#[test]
+fn json() {
+    #[derive(Deserialize, Serialize)]
+    struct Message {
+        message: String,
+    }
+
+    let client = TestClient::new(|req: Request<Json<Message>>, mut resp: ResponseBuilder| {
+        let mut body = req.into_body().json();
+        body.message += "!";
+        resp.header(http::header::CONTENT_TYPE, "application/json")
+            .body(Json(body))
+    });
+
+    let message = Message {
+        message: "hello".to_owned(),
+    };
+    let resp = client.post("/").json(&message).send();
+    resp.assert_status(http::StatusCode::OK)
+        .assert_header(http::header::CONTENT_TYPE, "application/json")
+        .assert_json(&Message {
+            message: "hello!".to_owned(),
+        });
+    assert_eq!(resp.json::<Message>().message, "hello!");
+}
+
+#[test]
+fn headers() {
+    let client = TestClient::new(|req: Request<()>, mut resp: ResponseBuilder| {
+        let value = req.headers()["X-Request"].clone();
+        resp.header("X-Response", value).body(())
+    });
+
+    client
+        .get("/")
+        .header("X-Request", "value")
+        .send()
+        .assert_header("X-Response", "value")
+        .assert_no_header("X-Request")
+        .assert_body("");
+}
+
+#[test]
+fn connection_info() {
+    let client = TestClient::new(|req: Request<()>, mut resp: ResponseBuilder| {
+        let info = req.extensions().get::<ConnectionInfo>().unwrap();
+        resp.body(info.remote_addr.to_string())
+    });
+
+    client.get("/").send().assert_body("127.0.0.1:50000");
+
+    let addr = "10.0.0.1:1234".parse().unwrap();
+    client
+        .get("/")
+        .remote_addr(addr)
+        .send()
+        .assert_body("10.0.0.1:1234");
+}
+
+#[test]
+fn router() {
+    let handler =
+        |t: &'static str| move |_: Request<()>, mut resp: ResponseBuilder| resp.body(t.to_owned());
+    let mut router = middlewares::SimpleRouter::new();
+    router.register_handler("/handler1", handler("1"));
+    router.register_handler("/handler2", handler("2"));
+
+    let client = TestClient::new(router);
+    client
+        .get("/")
+        .send()
+        .assert_status(http::StatusCode::NOT_FOUND);
+    client.get("/handler1").send().assert_body("1");
+    client.get("/handler2/sub").send().assert_body("2");
+}
+
+#[test]
+fn handler_error() {
+    let client = TestClient::new(
+        |_: Request<()>, _: ResponseBuilder| -> aitch::Result<http::Response<()>> {
+            Err("handler failed".into())
+        },
+    );
+
+    let err = client.get("/").try_send().unwrap_err();
+    assert_eq!(err.to_string(), "handler failed");
+}
@@ -0,0 +1,118 @@
+extern crate aitch;
+extern crate bytes;
+extern crate futures;
+extern crate futures_timer;
+extern crate http;
+
+use std::time::{Duration, Instant};
+
+use aitch::middlewares::{self, BodyTimeoutError};
+use aitch::testing::{self, TestClient};
+use aitch::{BodyStream, ResponseBuilder};
+use bytes::Bytes;
+use futures::{future, stream, Async, Future, Stream};
+use futures_timer::Delay;
+use http::{Request, StatusCode};
+
+fn slow_handler(
+    _: Request<()>,
+    mut resp: ResponseBuilder,
+) -> impl Future<Item = http::Response<String>, Error = aitch::Error> {
+    Delay::new(Duration::from_millis(50))
+        .map_err(aitch::Error::from)
+        .and_then(move |_| resp.body("done".to_owned()).map_err(aitch::Error::from))
+}
+
+// A body which sends a single chunk, and then stalls forever.
+fn stalled_body() -> BodyStream {
+    let chunk = stream::once(Ok(Bytes::from_static(b"partial")));
+    Box::new(chunk.chain(stream::poll_fn(|| Ok(Async::NotReady))))
+}
+
+#[test]
+fn responds_before_timeout() {
+    let client = TestClient::new(middlewares::with_timeout(
+        Duration::from_secs(5),
+        slow_handler,
+    ));
+
+    client
+        .get("/")
+        .send()
+        .assert_status(StatusCode::OK)
+        .assert_body("done");
+}
+
+#[test]
+fn times_out() {
+    let client = TestClient::new(middlewares::with_timeout(
+        Duration::from_millis(10),
+        slow_handler,
+    ));
+
+    client
+        .get("/")
+        .send()
+        .assert_status(StatusCode::SERVICE_UNAVAILABLE);
+}
+
+#[test]
+fn never_responds() {
+    let handler =
+        |_: Request<()>, _: ResponseBuilder| future::empty::<http::Response<()>, aitch::Error>();
+    let client = TestClient::new(middlewares::with_timeout(
+        Duration::from_millis(10),
+        handler,
+    ));
+
+    let start = Instant::now();
+    client
+        .get("/")
+        .send()
+        .assert_status(StatusCode::SERVICE_UNAVAILABLE);
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn body_received() {
+    let handler = |req: Request<String>, mut resp: ResponseBuilder| resp.body(req.into_body());
+    let client = TestClient::new(middlewares::with_body_timeout(
+        Duration::from_secs(5),
+        handler,
+    ));
+
+    client
+        .post("/")
+        .body("Hello, world!")
+        .send()
+        .assert_status(StatusCode::OK)
+        .assert_body("Hello, world!");
+}
+
+#[test]
+fn body_times_out() {
+    let handler =
+        |_: Request<String>, mut resp: ResponseBuilder| resp.body("unreachable".to_owned());
+    let handler = middlewares::with_body_timeout(Duration::from_millis(10), handler);
+
+    let req = Request::post("/").body(stalled_body()).unwrap();
+    let resp = testing::call(&handler, req).unwrap();
+    assert_eq!(resp.status(), StatusCode::REQUEST_TIMEOUT);
+}
+
+#[test]
+fn streaming_body_times_out() {
+    let handler = |req: Request<BodyStream>, mut resp: ResponseBuilder| {
+        req.into_body().concat2().then(move |result| {
+            let err = result.unwrap_err();
+            assert!(err.is::<BodyTimeoutError>());
+            resp.body(err.to_string())
+        })
+    };
+    let handler = middlewares::with_body_timeout(Duration::from_millis(10), handler);
+
+    let req = Request::post("/").body(stalled_body()).unwrap();
+    let resp = testing::call(&handler, req).unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.body(), "request body was not received within 10ms");
+}
@@ -5,6 +5,7 @@ extern crate reqwest;
 use std::net::SocketAddr;
 use std::thread;
 
+use aitch::servers::ConnectionInfo;
 use aitch::{Body, Handler, ResponseBuilder};
 use http::Request;
 
@@ -27,19 +28,14 @@ impl Server {
 }
 
 #[test]
-fn echo_server() {
-    let server = Server::start_in_thread(|req: Request<String>, mut resp: ResponseBuilder| {
-        resp.body(req.into_body())
+fn connection_info() {
+    let server = Server::start_in_thread(|req: Request<()>, mut resp: ResponseBuilder| {
+        let info = *req.extensions().get::<ConnectionInfo>().unwrap();
+        resp.body(format!("{} {}", info.remote_addr.ip(), info.secure))
     });
 
-    let client = reqwest::Client::new();
-    let mut resp = client
-        .post(&server.path("/"))
-        .body("some body")
-        .send()
-        .unwrap();
+    let mut resp = reqwest::get(&server.path("/")).unwrap();
     assert_eq!(resp.status(), reqwest::StatusCode::Ok);
-
     let body = resp.text().unwrap();
-    assert_eq!(body, "some body");
+    assert_eq!(body, "127.0.0.1 false");
 }